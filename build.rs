@@ -0,0 +1,25 @@
+fn main() {
+    #[cfg(feature = "ffi")]
+    generate_header();
+}
+
+// Only ever written to OUT_DIR, include/p2vec.h is refreshed by hand with
+// cbindgen --config cbindgen.toml --output include/p2vec.h
+// and tests/ffi.rs fails when the two drift apart
+#[cfg(feature = "ffi")]
+fn generate_header() {
+    let crate_directory = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    let out_directory = std::env::var("OUT_DIR").unwrap();
+
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    cbindgen::Builder::new()
+        .with_crate(&crate_directory)
+        .with_config(
+            cbindgen::Config::from_file(format!("{}/cbindgen.toml", crate_directory)).unwrap(),
+        )
+        .generate()
+        .expect("Failed to generate the C header")
+        .write_to_file(format!("{}/p2vec.h", out_directory));
+}
@@ -0,0 +1,51 @@
+#![no_main]
+
+use std::sync::OnceLock;
+
+use libfuzzer_sys::fuzz_target;
+use p2vec::{AccessMode, ChunkPos, RegionPos};
+
+static DIRECTORY: OnceLock<&'static str> = OnceLock::new();
+
+fn get_directory() -> &'static str {
+    DIRECTORY.get_or_init(|| {
+        let directory =
+            std::env::temp_dir().join(format!("p2vec-fuzz-read-{}", std::process::id()));
+
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let directory: &'static str =
+            Box::leak(directory.to_string_lossy().into_owned().into_boxed_str());
+
+        // Nothing is ever written, so the input stays exactly as it was generated
+        p2vec::set_access_mode(directory, AccessMode::ReadOnlyUnlocked).unwrap();
+
+        directory
+    })
+}
+
+// Any input is a region file. Reading it may fail but must never panic or crash
+fuzz_target!(|data: &[u8]| {
+    let directory = get_directory();
+
+    std::fs::write(format!("{}/r.0.0.mca", directory), data).unwrap();
+
+    if let Ok(Some(info)) = p2vec::read_region_info(directory, RegionPos::new(0, 0)) {
+        let sector_map = info.get_sector_map();
+
+        let _ = sector_map.get_free_ranges();
+        let _ = sector_map.to_text();
+    }
+
+    let _ = p2vec::verify_region(directory, RegionPos::new(0, 0));
+
+    let coords: Vec<ChunkPos> = RegionPos::new(0, 0).chunks().collect();
+
+    for chunk_coords in coords.iter() {
+        let _ = p2vec::read_chunk(directory, *chunk_coords);
+    }
+
+    let _ = p2vec::read_chunks(directory, &coords);
+
+    let _ = p2vec::close_regions(directory);
+});
@@ -0,0 +1,58 @@
+#![no_main]
+
+use std::sync::OnceLock;
+
+use libfuzzer_sys::fuzz_target;
+use p2vec::{ChunkPos, RegionPos};
+
+static DIRECTORY: OnceLock<&'static str> = OnceLock::new();
+
+fn get_directory() -> &'static str {
+    DIRECTORY.get_or_init(|| {
+        let directory =
+            std::env::temp_dir().join(format!("p2vec-fuzz-write-{}", std::process::id()));
+
+        Box::leak(directory.to_string_lossy().into_owned().into_boxed_str())
+    })
+}
+
+// Any input is a region file. Writing into it may fail but must never panic, and a chunk that was written must read back as written
+fuzz_target!(|data: &[u8]| {
+    let directory = get_directory();
+
+    let _ = std::fs::remove_dir_all(directory);
+
+    std::fs::create_dir_all(directory).unwrap();
+    std::fs::write(format!("{}/r.0.0.mca", directory), data).unwrap();
+
+    // Taken from the input so the writes land next to whatever the header claims
+    let chunk_data: Vec<u8> = data.iter().rev().take(5000).copied().collect();
+
+    for index in [0, data.len() % 1024] {
+        let coords = ChunkPos::new(index as i32 % 32, index as i32 / 32);
+
+        let timestamp = match p2vec::read_chunk_timestamp(directory, coords) {
+            Ok(timestamp) => timestamp,
+            Err(_) => continue,
+        };
+
+        if let Ok(true) = p2vec::write_chunk_if_unchanged(
+            directory,
+            coords,
+            timestamp,
+            timestamp.wrapping_add(1),
+            &chunk_data,
+            2,
+            6,
+        ) {
+            assert_eq!(
+                p2vec::read_chunk(directory, coords).unwrap().as_deref(),
+                Some(chunk_data.as_slice())
+            );
+        }
+    }
+
+    let _ = p2vec::compact_region(directory, RegionPos::new(0, 0));
+
+    let _ = p2vec::close_regions(directory);
+});
@@ -0,0 +1,137 @@
+#ifndef P2VEC_H
+#define P2VEC_H
+
+/* Generated by cbindgen from src/ffi.rs, don't edit by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define P2VEC_OK 0
+
+#define P2VEC_ERROR_INVALID_ARGUMENT 1
+
+#define P2VEC_ERROR_NOT_FOUND 2
+
+#define P2VEC_ERROR_PERMISSION_DENIED 3
+
+/**
+ * The region file is locked by another process
+ */
+#define P2VEC_ERROR_LOCKED 4
+
+/**
+ * The region or chunk data is corrupt
+ */
+#define P2VEC_ERROR_INVALID_DATA 5
+
+#define P2VEC_ERROR_IO 6
+
+#define P2VEC_ERROR_PANIC 7
+
+#define P2VEC_DIMENSION_OVERWORLD 0
+
+#define P2VEC_DIMENSION_NETHER 1
+
+#define P2VEC_DIMENSION_END 2
+
+#define P2VEC_REGION_KIND_REGION 0
+
+#define P2VEC_REGION_KIND_ENTITIES 1
+
+#define P2VEC_REGION_KIND_POI 2
+
+#define P2VEC_DURABILITY_ASYNC 0
+
+#define P2VEC_DURABILITY_SYNC 1
+
+#define P2VEC_DURABILITY_SYNC_DIRECTORY 2
+
+/**
+ * An open world, only ever handled through a pointer
+ */
+typedef struct P2vecWorld P2vecWorld;
+
+
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Opens the world folder at `path`. On success `world` is set to a handle that must be closed with `p2vec_close_world`.
+ *
+ * # Safety
+ * `path` must be a valid NUL terminated string and `world` must be valid for writes.
+ */
+int32_t p2vec_open_world(const char *path,
+                         struct P2vecWorld **world);
+
+/**
+ * Closes every region of the world and frees the handle. Passing null does nothing.
+ *
+ * # Safety
+ * `world` must be null or a handle from `p2vec_open_world` that isn't used anymore.
+ */
+int32_t p2vec_close_world(struct P2vecWorld *world);
+
+/**
+ * Reads a chunk into a new buffer that must be freed with `p2vec_free_buffer`. A chunk that doesn't exist sets `data` to null and `length` to 0.
+ *
+ * # Safety
+ * `world` must be a handle from `p2vec_open_world`, `data` and `length` must be valid for writes.
+ */
+int32_t p2vec_read_chunk(const struct P2vecWorld *world,
+                         uint32_t dimension,
+                         uint32_t kind,
+                         int32_t x,
+                         int32_t z,
+                         uint8_t **data,
+                         size_t *length);
+
+/**
+ * Writes a chunk if `timestamp` is newer than the one stored for it. `compression_type` is 1 for gzip, 2 for zlib, 3 for uncompressed and 4 for LZ4.
+ *
+ * # Safety
+ * `world` must be a handle from `p2vec_open_world` and `data` must be valid for reads of `length` bytes.
+ */
+int32_t p2vec_write_chunk(const struct P2vecWorld *world,
+                          uint32_t dimension,
+                          uint32_t kind,
+                          int32_t x,
+                          int32_t z,
+                          uint32_t timestamp,
+                          const uint8_t *data,
+                          size_t length,
+                          uint8_t compression_type,
+                          int32_t compression_level);
+
+/**
+ * Flushes every open region of the world. `durability` is one of the `P2VEC_DURABILITY_` constants.
+ *
+ * # Safety
+ * `world` must be a handle from `p2vec_open_world`.
+ */
+int32_t p2vec_flush_world(const struct P2vecWorld *world, uint32_t durability);
+
+/**
+ * Frees a buffer returned by `p2vec_read_chunk`. Passing null does nothing.
+ *
+ * # Safety
+ * `data` and `length` must come from the same `p2vec_read_chunk` call and the buffer must not be freed twice.
+ */
+void p2vec_free_buffer(uint8_t *data,
+                       size_t length);
+
+/**
+ * Returns a description of the last error on this thread, or null if there wasn't one. The string stays valid until the next call on this thread.
+ */
+const char *p2vec_last_error_message(void);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* P2VEC_H */
@@ -0,0 +1,80 @@
+package com.duplexsystem.p2vec;
+
+import java.io.IOException;
+import java.nio.ByteBuffer;
+
+/**
+ * Java side of the p2vec JNI bindings, built with the {@code java} feature. Every method is safe to call from many threads at once.
+ * A world handle must not be used after it has been closed.
+ */
+public final class P2vec {
+    public static final int DIMENSION_OVERWORLD = 0;
+    public static final int DIMENSION_NETHER = 1;
+    public static final int DIMENSION_END = 2;
+
+    public static final int REGION_KIND_REGION = 0;
+    public static final int REGION_KIND_ENTITIES = 1;
+    public static final int REGION_KIND_POI = 2;
+
+    /** Starts writing back without waiting for it. */
+    public static final int DURABILITY_ASYNC = 0;
+    /** Waits until the data is on disk. */
+    public static final int DURABILITY_SYNC = 1;
+    /** Like {@link #DURABILITY_SYNC}, and also makes newly created region files durable. Use this for {@code /save-all flush}. */
+    public static final int DURABILITY_SYNC_DIRECTORY = 2;
+
+    public static final int COMPRESSION_GZIP = 1;
+    public static final int COMPRESSION_ZLIB = 2;
+    public static final int COMPRESSION_UNCOMPRESSED = 3;
+    public static final int COMPRESSION_LZ4 = 4;
+
+    static {
+        System.loadLibrary("p2vec");
+    }
+
+    private P2vec() {
+    }
+
+    /** Opens the world folder at {@code path} and returns a handle to it. */
+    public static native long openWorld(String path);
+
+    /** Closes every region of the world and frees the handle. */
+    public static native void closeWorld(long world) throws IOException;
+
+    /**
+     * Returns the decompressed chunk in a direct buffer that must be passed to {@link #freeBuffer} once it isn't used anymore,
+     * or null if the chunk doesn't exist.
+     */
+    public static native ByteBuffer readChunk(long world, int dimension, int kind, int x, int z) throws IOException;
+
+    /**
+     * Frees a buffer returned by {@link #readChunk}. The buffer must not be used afterwards. Throws
+     * {@link IllegalArgumentException} for any other buffer, including slices of one and buffers that were already freed.
+     */
+    public static native void freeBuffer(ByteBuffer buffer);
+
+    public static native int readChunkTimestamp(long world, int dimension, int kind, int x, int z) throws IOException;
+
+    /**
+     * Writes the remaining bytes of the direct buffer {@code data} if {@code timestamp} is newer than the stored one.
+     * Returns whether the chunk was written.
+     */
+    public static boolean writeChunk(long world, int dimension, int kind, int x, int z, int timestamp, ByteBuffer data,
+                                     int compressionType, int compressionLevel) throws IOException {
+        return writeChunk0(world, dimension, kind, x, z, timestamp, data.slice(), data.remaining(), compressionType,
+                compressionLevel);
+    }
+
+    private static native boolean writeChunk0(long world, int dimension, int kind, int x, int z, int timestamp,
+                                              ByteBuffer data, int length, int compressionType, int compressionLevel)
+            throws IOException;
+
+    public static native void flushRegion(long world, int dimension, int kind, int regionX, int regionZ, int durability)
+            throws IOException;
+
+    /** Flushes every open region of the world. */
+    public static native void flushWorld(long world, int durability) throws IOException;
+
+    public static native void closeRegion(long world, int dimension, int kind, int regionX, int regionZ)
+            throws IOException;
+}
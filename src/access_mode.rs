@@ -0,0 +1,16 @@
+#[derive(Hash, Eq, PartialEq, Copy, Clone, Debug, Default)]
+pub enum AccessMode {
+    /// Region files are locked exclusively and created when missing
+    #[default]
+    ReadWrite,
+    /// Region files are never created or extended and are locked shared, so other readers can open them too
+    ReadOnly,
+    /// Like ReadOnly but without taking any lock, for reading a world a running server is writing to
+    ReadOnlyUnlocked,
+}
+
+impl AccessMode {
+    pub fn is_read_only(&self) -> bool {
+        !matches!(self, AccessMode::ReadWrite)
+    }
+}
@@ -1,17 +1,23 @@
+use std::borrow::Cow;
 use std::io::Error;
 use std::ops::Range;
 use std::path::Path;
-use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::atomic::{AtomicU32, Ordering};
 
-use glam::IVec2;
-use parking_lot::{RwLock, RwLockUpgradableReadGuard};
+use parking_lot::{RwLock, RwLockUpgradableReadGuard, RwLockWriteGuard};
 
-use crate::memory_mapped_file::MemoryMappedFile;
+use crate::compression::CompressionType;
+use crate::encryption::get_encryption_status;
+use crate::memory_util::{get_alignment_vector, u8x4_to_u32};
+use crate::position::{ChunkPos, LocalPos, RegionPos};
 use crate::region::{MutableRegionMetadata, StaticRegionMetadata};
 use crate::region_file_util::{
-    get_chunk_compression_type, get_chunk_length, get_chunk_location, get_chunk_offset,
-    get_chunk_timestamp, get_oversized_status,
+    get_checksum_data, get_checksum_location, get_chunk_checksum, get_chunk_compression_type,
+    get_chunk_header_data, get_chunk_length, get_chunk_timestamp, get_oversized_status,
+    CHECKSUM_ENTRY_SIZE,
 };
+use crate::region_header::{RegionHeader, SectorRange, SECTOR_SIZE};
+use crate::storage::StorageFile;
 
 pub(crate) struct ChunkGuard {
     pub(crate) chunk: RwLock<Chunk>,
@@ -19,13 +25,14 @@ pub(crate) struct ChunkGuard {
 }
 
 pub(crate) struct Chunk {
-    data: RwLock<Option<MemoryMappedFile>>,
+    data: RwLock<Option<Box<dyn StorageFile>>>,
 }
 
 impl Chunk {
     pub(crate) fn new(
-        chunk_region_coords: IVec2,
-        region_coords: IVec2,
+        chunk_region_coords: LocalPos,
+        region_coords: RegionPos,
+        location: SectorRange,
         static_region_metadata: &StaticRegionMetadata,
     ) -> Result<(Self, Range<usize>), Error> {
         let file = match static_region_metadata.file.as_ref() {
@@ -38,52 +45,76 @@ impl Chunk {
             Some(file) => file,
         };
 
-        if static_region_metadata.file.is_none() {}
-
-        let location = get_chunk_location(chunk_region_coords) as usize;
-
-        let chunk_region_table_data = file.read_file(location..location + 4)?;
-
-        let offset_data = &chunk_region_table_data[0..3];
-
-        let offset = get_chunk_offset(offset_data) as usize;
+        if location.is_empty() {
+            return Ok((
+                Chunk {
+                    data: RwLock::new(None),
+                },
+                0..0,
+            ));
+        }
 
-        let file_offset = offset * 4096;
+        let file_offset = location.bytes().start;
 
         let chunk_header_oversized_byte = file.read_file(file_offset + 4..file_offset + 5)?[0];
 
-        let data: Option<MemoryMappedFile> = match get_oversized_status(chunk_header_oversized_byte)
-        {
-            true => {
-                let chunk_coords: IVec2 = region_coords << 5 | chunk_region_coords;
-
-                Some(MemoryMappedFile::open_file(
-                    4096,
-                    Path::new(&format!(
-                        "{}/c.{}.{}.mcc",
-                        static_region_metadata.directory, chunk_coords.x, chunk_coords.y
-                    )),
-                    false,
-                )?)
-            }
-            false => None,
-        };
+        let data: Option<Box<dyn StorageFile>> =
+            match get_oversized_status(chunk_header_oversized_byte) {
+                true => {
+                    let chunk_coords = region_coords.chunk(chunk_region_coords);
+
+                    // A missing oversized file only breaks that chunk, so it is reported when the chunk is read
+                    match Chunk::open_oversized_file(static_region_metadata, chunk_coords) {
+                        Ok(file) => Some(file),
+                        Err(error) if error.kind() == std::io::ErrorKind::NotFound => None,
+                        Err(error) => return Err(error),
+                    }
+                }
+                false => None,
+            };
 
         Ok((
             Chunk {
                 data: RwLock::new(data),
             },
-            offset..offset + (chunk_region_table_data[4] as usize),
+            location.sectors(),
         ))
     }
 
-    pub(crate) fn read_chunk_data(
+    // Hands the compressed data to reader while it is still borrowed from the region file
+    pub(crate) fn read_chunk_data<T>(
         &self,
-        chunk_coords: IVec2,
-        chunk_region_coords: IVec2,
+        chunk_coords: ChunkPos,
+        chunk_region_coords: LocalPos,
         static_region_metadata: &StaticRegionMetadata,
-    ) -> Result<Option<Vec<u8>>, Error> {
-        let mut file = match &static_region_metadata.file {
+        reader: impl FnOnce(CompressionType, Cow<[u8]>) -> Result<T, Error>,
+    ) -> Result<Option<T>, Error> {
+        self.read_stored_data(
+            chunk_coords,
+            chunk_region_coords,
+            static_region_metadata,
+            |compression_byte, stored_data| {
+                let (compression_type, compressed_data) = Chunk::decode_stored_data(
+                    chunk_coords,
+                    compression_byte,
+                    stored_data,
+                    static_region_metadata,
+                )?;
+
+                reader(compression_type, compressed_data)
+            },
+        )
+    }
+
+    // Like read_chunk_data but hands over the compression byte and payload exactly as they are stored
+    pub(crate) fn read_stored_data<T>(
+        &self,
+        chunk_coords: ChunkPos,
+        chunk_region_coords: LocalPos,
+        static_region_metadata: &StaticRegionMetadata,
+        reader: impl FnOnce(u8, Cow<[u8]>) -> Result<T, Error>,
+    ) -> Result<Option<T>, Error> {
+        let file = match &static_region_metadata.file {
             Some(file) => file,
             None => {
                 return Err(Error::new(
@@ -93,16 +124,138 @@ impl Chunk {
             }
         };
 
-        let location = get_chunk_location(chunk_region_coords) as usize;
+        let location = RegionHeader::read_location(&**file, chunk_region_coords)?;
+
+        if location.is_empty() {
+            return Ok(None);
+        }
+
+        let sector_data = file.read_file(location.bytes())?;
 
-        let chunk_region_table_data = &(file.read_file(location..location + 4)?)[0..3];
+        let (compression_byte, stored_data) =
+            self.get_stored_data(chunk_coords, location, &sector_data, static_region_metadata)?;
 
-        let offset = get_chunk_offset(chunk_region_table_data) as usize;
+        Ok(Some(reader(compression_byte, stored_data)?))
+    }
+
+    // Splits the sectors of a chunk into its compression type and compressed data
+    pub(crate) fn get_compressed_data<'a>(
+        &self,
+        chunk_coords: ChunkPos,
+        location: SectorRange,
+        sector_data: &'a [u8],
+        static_region_metadata: &StaticRegionMetadata,
+    ) -> Result<(CompressionType, Cow<'a, [u8]>), Error> {
+        let (compression_byte, stored_data) =
+            self.get_stored_data(chunk_coords, location, sector_data, static_region_metadata)?;
+
+        Chunk::decode_stored_data(
+            chunk_coords,
+            compression_byte,
+            stored_data,
+            static_region_metadata,
+        )
+    }
+
+    // Splits the sectors of a chunk into its compression byte and the payload as it is stored
+    fn get_stored_data<'a>(
+        &self,
+        chunk_coords: ChunkPos,
+        location: SectorRange,
+        sector_data: &'a [u8],
+        static_region_metadata: &StaticRegionMetadata,
+    ) -> Result<(u8, Cow<'a, [u8]>), Error> {
+        if sector_data.len() < 5 {
+            return Err(Error::new(
+                std::io::ErrorKind::Other,
+                "Invalid Chunk Header",
+            ));
+        }
 
-        let chunk_header_data = file.read_file(offset..offset + 5)?;
+        let compression_byte = sector_data[4];
 
-        let compression_byte = chunk_header_data[4];
+        let stored_data = match get_oversized_status(compression_byte) {
+            true => Cow::Owned(self.read_oversized_data(chunk_coords, static_region_metadata)?),
+            false => {
+                let length = get_chunk_length(&sector_data[0..4]) as usize;
+
+                if length == 0 || 4 + length > sector_data.len() {
+                    return Err(Error::new(
+                        std::io::ErrorKind::Other,
+                        "Invalid Chunk Length",
+                    ));
+                }
+
+                Cow::Borrowed(&sector_data[5..4 + length])
+            }
+        };
 
+        if let Some(checksum) = Chunk::get_checksum(
+            chunk_coords.local(),
+            location,
+            stored_data.len(),
+            static_region_metadata,
+        )? {
+            if get_chunk_checksum(compression_byte, &stored_data) != checksum {
+                return Err(Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "Chunk doesn't match its checksum",
+                ));
+            }
+        }
+
+        Ok((compression_byte, stored_data))
+    }
+
+    // Returns the checksum recorded for the chunk stored at location with stored_length bytes of payload, or None if there is none for the data that is stored now
+    pub(crate) fn get_checksum(
+        chunk_region_coords: LocalPos,
+        location: SectorRange,
+        stored_length: usize,
+        static_region_metadata: &StaticRegionMetadata,
+    ) -> Result<Option<u32>, Error> {
+        let (file, checksums) = match (
+            &static_region_metadata.file,
+            static_region_metadata.checksums.get(),
+        ) {
+            (Some(file), Some(checksums)) => (file, checksums),
+            _ => return Ok(None),
+        };
+
+        let checksum_location = get_checksum_location(chunk_region_coords);
+
+        // Sidecar files from before entries were this big are too short, their chunks count as unchecked until they are rewritten
+        if checksums.get_file_size()? < (checksum_location + CHECKSUM_ENTRY_SIZE) as u64 {
+            return Ok(None);
+        }
+
+        let checksum_data =
+            checksums.read_file(checksum_location..checksum_location + CHECKSUM_ENTRY_SIZE)?;
+
+        if checksum_data.iter().all(|byte| *byte == 0) {
+            return Ok(None);
+        }
+
+        let timestamp = RegionHeader::read_timestamp(&**file, chunk_region_coords)?;
+
+        // Anything that doesn't know about the sidecar file, like the game itself, leaves a checksum behind that belongs to an older write.
+        // Timestamps are only in seconds, so the location and length have to match too before a mismatch counts as corruption
+        match get_chunk_timestamp(&checksum_data[0..4]) == timestamp
+            && SectorRange::from_entry(&checksum_data[4..8]) == location
+            && u8x4_to_u32(&checksum_data[8..12]) as usize == stored_length
+        {
+            true => Ok(Some(u8x4_to_u32(&checksum_data[12..16]))),
+            false => Ok(None),
+        }
+    }
+
+    // Turns a stored payload into its compression type and compressed data, decrypting it if needed
+    pub(crate) fn decode_stored_data<'a>(
+        chunk_coords: ChunkPos,
+        compression_byte: u8,
+        stored_data: Cow<'a, [u8]>,
+        static_region_metadata: &StaticRegionMetadata,
+    ) -> Result<(CompressionType, Cow<'a, [u8]>), Error> {
         let compression_type = match get_chunk_compression_type(compression_byte) {
             None => {
                 return Err(Error::new(
@@ -113,51 +266,66 @@ impl Chunk {
             Some(result) => result,
         };
 
-        let oversized = get_oversized_status(compression_byte);
+        if !get_encryption_status(compression_byte) {
+            return Ok((compression_type, stored_data));
+        }
 
-        let file_lock;
+        let encryption_keys = match &static_region_metadata.encryption_keys {
+            None => {
+                return Err(Error::new(
+                    std::io::ErrorKind::PermissionDenied,
+                    "Chunk is encrypted but no encryption key is set",
+                ));
+            }
+            Some(encryption_keys) => encryption_keys,
+        };
+
+        Ok((
+            compression_type,
+            Cow::Owned(encryption_keys.decrypt(chunk_coords, compression_byte, &stored_data)?),
+        ))
+    }
 
-        let mut file_write_lock;
+    pub(crate) fn read_oversized_data(
+        &self,
+        chunk_coords: ChunkPos,
+        static_region_metadata: &StaticRegionMetadata,
+    ) -> Result<Vec<u8>, Error> {
+        let file_lock = self.data.upgradable_read();
 
-        let compressed_data = match oversized {
+        let file_lock = match file_lock.is_none() {
             true => {
-                file_lock = self.data.upgradable_read();
-                if file_lock.is_none() {
-                    file_write_lock = RwLockUpgradableReadGuard::upgrade(file_lock);
-                    file = file_write_lock.insert(Chunk::open_oversized_file(
-                        static_region_metadata.directory,
-                        chunk_coords,
-                    )?);
-                }
-                file.read_file(0..file.get_file_size()? as usize)?
-            }
-            false => {
-                let length_data = &chunk_header_data[0..4];
+                let mut file_write_lock = RwLockUpgradableReadGuard::upgrade(file_lock);
 
-                let length = get_chunk_length(length_data) as usize;
+                *file_write_lock = Some(Chunk::open_oversized_file(
+                    static_region_metadata,
+                    chunk_coords,
+                )?);
 
-                file.read_file(offset + 5..offset + 5 + length)?
+                RwLockWriteGuard::downgrade(file_write_lock)
             }
+            false => RwLockUpgradableReadGuard::downgrade(file_lock),
         };
 
-        let data = compression_type.decompress(compressed_data)?;
+        let file = file_lock.as_ref().unwrap();
 
-        Ok(Some(data))
+        Ok(file
+            .read_file(0..file.get_file_size()? as usize)?
+            .into_owned())
     }
 
     #[allow(clippy::too_many_arguments)]
     pub(crate) fn write_chunk_data(
         &self,
-        chunk_coords: IVec2,
-        chunk_region_coords: IVec2,
+        chunk_coords: ChunkPos,
+        chunk_region_coords: LocalPos,
         static_region_metadata: &StaticRegionMetadata,
         mutable_region_metadata: &MutableRegionMetadata,
         timestamp: u32,
+        compression_byte: u8,
         data: &[u8],
         alignment_data: &[u8],
     ) -> Result<(), Error> {
-        let location = get_chunk_location(chunk_region_coords) as usize;
-
         let file = match &static_region_metadata.file {
             Some(file) => file,
             None => {
@@ -168,60 +336,264 @@ impl Chunk {
             }
         };
 
-        let timestamp_location = location * 2;
-
-        if get_chunk_timestamp(&data[timestamp_location..timestamp_location + 2]) > timestamp as u32
-        {
-            return Ok(());
-        }
-
-        let chunk_region_table_data = &(file.read_file(location..location + 4)?);
+        // A chunk that claims part of the header is corrupt, so it is written somewhere else instead of over the header
+        let location = match RegionHeader::read_location(&**file, chunk_region_coords)? {
+            SectorRange { offset: 1, .. } => SectorRange::EMPTY,
+            location => location,
+        };
 
-        let offset = get_chunk_offset(&chunk_region_table_data[0..4]) as usize;
+        let offset = location.offset as u64;
+        let sectors = location.count as u64;
 
-        let sectors = chunk_region_table_data[3] as usize;
+        let was_oversized = sectors != 0
+            && get_oversized_status(
+                file.read_file(
+                    offset as usize * SECTOR_SIZE + 4..offset as usize * SECTOR_SIZE + 5,
+                )?[0],
+            );
 
-        let mut wanted_sectors = data.len() >> 12;
+        let mut wanted_sectors = ((5 + data.len() + alignment_data.len()) >> 12) as u64;
 
-        let oversized = wanted_sectors > u8::MAX as usize;
+        let oversized = wanted_sectors > u8::MAX as u64;
 
         if oversized {
             wanted_sectors = 1;
         }
 
-        let location = Chunk::find_space_to_write(
-            offset as u64,
-            offset as u64 + sectors as u64,
-            sectors as u64,
-            wanted_sectors as u64,
+        let _modify_guard = mutable_region_metadata.modify_lock.read();
+
+        let new_range = Chunk::find_space_to_write(
+            offset,
+            offset + sectors,
+            sectors,
+            wanted_sectors,
             mutable_region_metadata,
         );
 
+        file.ensure_file_size(new_range.end * 4096)?;
+
+        let file_offset = new_range.start as usize * 4096;
+
+        let mut oversized_file_lock = self.data.write();
+
+        if let Some(oversized_file) = oversized_file_lock.take() {
+            oversized_file.close_file()?;
+        }
+
+        if oversized {
+            Chunk::write_oversized_file(static_region_metadata, chunk_coords, data)?;
+
+            file.write_file(
+                file_offset,
+                &get_chunk_header_data(1, compression_byte | 128),
+            )?;
+            file.write_file(file_offset + 5, &get_alignment_vector(5, 4096))?;
+        } else {
+            if was_oversized {
+                Chunk::remove_oversized_file(static_region_metadata, chunk_coords)?;
+            }
+
+            file.write_file(
+                file_offset,
+                &get_chunk_header_data(data.len() as u32 + 1, compression_byte),
+            )?;
+            file.write_file(file_offset + 5, data)?;
+            file.write_file(file_offset + 5 + data.len(), alignment_data)?;
+        }
+
+        let new_location = SectorRange::new(new_range.start as u32, wanted_sectors as u8);
+
+        RegionHeader::write_location(&**file, chunk_region_coords, new_location)?;
+        RegionHeader::write_timestamp(&**file, chunk_region_coords, timestamp)?;
+
+        if let Some(checksums) = static_region_metadata.get_checksums_for_write()? {
+            checksums.write_file(
+                get_checksum_location(chunk_region_coords),
+                &get_checksum_data(
+                    timestamp,
+                    new_location,
+                    data.len() as u32,
+                    get_chunk_checksum(compression_byte, data),
+                ),
+            )?;
+        }
+
+        if new_range.start == offset {
+            Chunk::free_space(new_range.end..offset + sectors, mutable_region_metadata);
+        } else {
+            Chunk::free_space(offset..offset + sectors, mutable_region_metadata);
+        }
+
         Ok(())
     }
 
+    // Clears the chunk from the location, timestamp and checksum tables and gives its sectors back. Returns whether there was a chunk
+    pub(crate) fn delete_chunk_data(
+        &self,
+        chunk_coords: ChunkPos,
+        chunk_region_coords: LocalPos,
+        static_region_metadata: &StaticRegionMetadata,
+        mutable_region_metadata: &MutableRegionMetadata,
+    ) -> Result<bool, Error> {
+        let file = match &static_region_metadata.file {
+            Some(file) => file,
+            None => {
+                return Err(Error::new(
+                    std::io::ErrorKind::Other,
+                    "Region file is not open",
+                ));
+            }
+        };
+
+        let location = RegionHeader::read_location(&**file, chunk_region_coords)?;
+
+        if location.is_empty() {
+            return Ok(false);
+        }
+
+        let offset = location.offset as u64;
+        let sectors = location.count as u64;
+
+        let was_oversized = get_oversized_status(
+            file.read_file(offset as usize * SECTOR_SIZE + 4..offset as usize * SECTOR_SIZE + 5)?
+                [0],
+        );
+
+        let _modify_guard = mutable_region_metadata.modify_lock.read();
+
+        let mut oversized_file_lock = self.data.write();
+
+        if let Some(oversized_file) = oversized_file_lock.take() {
+            oversized_file.close_file()?;
+        }
+
+        if was_oversized {
+            Chunk::remove_oversized_file(static_region_metadata, chunk_coords)?;
+        }
+
+        RegionHeader::write_location(&**file, chunk_region_coords, SectorRange::EMPTY)?;
+        RegionHeader::write_timestamp(&**file, chunk_region_coords, 0)?;
+
+        // Without a sidecar file there is no checksum to clear
+        if let Some(checksums) = static_region_metadata.checksums.get() {
+            checksums.write_file(
+                get_checksum_location(chunk_region_coords),
+                &[0; CHECKSUM_ENTRY_SIZE],
+            )?;
+        }
+
+        Chunk::free_space(offset..offset + sectors, mutable_region_metadata);
+
+        Ok(true)
+    }
+
     pub(crate) fn find_space_to_write(
         current_start: u64,
         current_end: u64,
         current_sectors: u64,
         wanted_sectors: u64,
         mutable_region_metadata: &MutableRegionMetadata,
-    ) {
-        let mut new_start = 0;
-        let mut new_end = 0;
+    ) -> Range<u64> {
+        if current_start != 0 && wanted_sectors <= current_sectors {
+            return current_start..current_start + wanted_sectors;
+        }
+
+        // The chunk is the last thing in the file so it can grow in place
+        if current_start != 0
+            && mutable_region_metadata
+                .wanted_space
+                .compare_exchange(
+                    current_end as u32,
+                    (current_start + wanted_sectors) as u32,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+        {
+            return current_start..current_start + wanted_sectors;
+        }
+
+        for free_ranges in
+            mutable_region_metadata.free_ranges[(wanted_sectors as usize - 1)..].iter()
+        {
+            if let Ok(free_range) = free_ranges.pop() {
+                let new_end = free_range.start as u64 + wanted_sectors;
+
+                Chunk::free_space(new_end..free_range.end as u64, mutable_region_metadata);
+
+                return free_range.start as u64..new_end;
+            }
+        }
+
+        let new_start = mutable_region_metadata
+            .wanted_space
+            .fetch_add(wanted_sectors as u32, Ordering::Relaxed) as u64;
+
+        new_start..new_start + wanted_sectors
+    }
+
+    pub(crate) fn free_space(range: Range<u64>, mutable_region_metadata: &MutableRegionMetadata) {
+        if range.start < 2 || range.end <= range.start {
+            return;
+        }
+
+        let bucket = ((range.end - range.start) as usize).min(256) - 1;
+
+        // The queues are unbounded and never closed so this can't fail
+        let _ =
+            mutable_region_metadata.free_ranges[bucket].push(range.start as u32..range.end as u32);
     }
 
     pub(crate) fn open_oversized_file(
-        directory: &'static str,
-        chunk_coords: IVec2,
-    ) -> Result<MemoryMappedFile, Error> {
-        MemoryMappedFile::open_file(
+        static_region_metadata: &StaticRegionMetadata,
+        chunk_coords: ChunkPos,
+    ) -> Result<Box<dyn StorageFile>, Error> {
+        match static_region_metadata.storage.open_file(
             4096,
-            Path::new(&format!(
-                "{}/c.{}.{}.mcc",
-                directory, chunk_coords.x, chunk_coords.y
+            Path::new(&Chunk::get_oversized_file_path(
+                static_region_metadata.directory,
+                chunk_coords,
             )),
             false,
+            static_region_metadata.access_mode,
+            false,
+        )? {
+            Some(file) => Ok(file),
+            None => Err(Error::new(
+                std::io::ErrorKind::NotFound,
+                "Oversized chunk file is missing",
+            )),
+        }
+    }
+
+    // Oversized chunks are rare, so they are synced right away instead of being tracked until the next flush
+    pub(crate) fn write_oversized_file(
+        static_region_metadata: &StaticRegionMetadata,
+        chunk_coords: ChunkPos,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        static_region_metadata.storage.write_whole_file(
+            Path::new(&Chunk::get_oversized_file_path(
+                static_region_metadata.directory,
+                chunk_coords,
+            )),
+            data,
         )
     }
+
+    pub(crate) fn remove_oversized_file(
+        static_region_metadata: &StaticRegionMetadata,
+        chunk_coords: ChunkPos,
+    ) -> Result<(), Error> {
+        static_region_metadata
+            .storage
+            .remove_file(Path::new(&Chunk::get_oversized_file_path(
+                static_region_metadata.directory,
+                chunk_coords,
+            )))
+    }
+
+    fn get_oversized_file_path(directory: &'static str, chunk_coords: ChunkPos) -> String {
+        format!("{}/c.{}.{}.mcc", directory, chunk_coords.x, chunk_coords.z)
+    }
 }
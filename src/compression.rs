@@ -1,13 +1,45 @@
 use std::borrow::Cow;
 use std::io::{Error, Read};
+use std::thread;
 
-use libdeflater::{CompressionLvl, Compressor, Decompressor};
+use libdeflater::{CompressionLvl, Compressor, DecompressionError, Decompressor};
+
+use crate::io_pool::spawn_io;
+use crate::lz4_block;
+#[cfg(feature = "nbt")]
+use crate::lz4_block::Lz4BlockReader;
+
+// The most libdeflate gets a buffer for up front, anything bigger is decompressed as a stream
+const MAX_GZIP_PREALLOCATION: usize = 16 << 20;
+
+// Deflate can't expand data by more than this, so a bigger ISIZE is a lie
+const MAX_DEFLATE_RATIO: usize = 1032;
+
+// A chunk as it was read, copied out of the region file so it can be decompressed after its lock is let go. None if it doesn't exist
+pub(crate) type CompressedChunk = Result<Option<(CompressionType, Vec<u8>)>, Error>;
+
+// Decompresses gzip without knowing the size up front, the buffer only grows as far as the data actually goes
+fn decompress_gzip_stream(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut decoder = flate2::read::GzDecoder::new(data);
+    let mut buffer = Vec::new();
+    decoder.read_to_end(&mut buffer).map_err(get_data_error)?;
+    Ok(buffer)
+}
+
+// flate2 reports a corrupt stream as invalid input, but here the stream is always data read from a file
+fn get_data_error(error: Error) -> Error {
+    match error.kind() {
+        std::io::ErrorKind::InvalidInput => Error::new(std::io::ErrorKind::InvalidData, error),
+        _ => error,
+    }
+}
 
 // CompressionType is an enum that represents different compression types that this code can handle
 pub(crate) enum CompressionType {
     Gzip,
     Zlib,
     Uncompressed,
+    Lz4,
 }
 
 // Implementations of various methods for the CompressionType enum
@@ -18,6 +50,7 @@ impl CompressionType {
             1 => Some(CompressionType::Gzip),
             2 => Some(CompressionType::Zlib),
             3 => Some(CompressionType::Uncompressed),
+            4 => Some(CompressionType::Lz4),
             _ => None,
         }
     }
@@ -28,6 +61,7 @@ impl CompressionType {
             CompressionType::Gzip => 1,
             CompressionType::Zlib => 2,
             CompressionType::Uncompressed => 3,
+            CompressionType::Lz4 => 4,
         }
     }
 
@@ -40,6 +74,13 @@ impl CompressionType {
                 // footer, which is a little-endian u32 number representing the
                 // decompressed size. This is ideal for libdeflate, which needs
                 // pre-allocating the decompressed buffer.
+                if data.len() < 4 {
+                    return Err(Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "Gzip data is too short",
+                    ));
+                }
+
                 let isize = {
                     let isize_start = data.len() - 4;
                     let isize_bytes = &data[isize_start..];
@@ -50,13 +91,27 @@ impl CompressionType {
                     ret as usize
                 };
 
+                // ISIZE comes from the file, so it is only trusted as long as deflate could actually have produced it
+                if isize > MAX_GZIP_PREALLOCATION
+                    || isize > data.len().saturating_mul(MAX_DEFLATE_RATIO)
+                {
+                    return decompress_gzip_stream(data.as_ref());
+                }
+
                 let mut decompressor = Decompressor::new();
                 let mut outbuf = Vec::new();
                 outbuf.resize(isize, 0);
                 match decompressor.gzip_decompress(data.as_ref(), &mut outbuf) {
-                    Ok(_) => {}
+                    Ok(size) => outbuf.truncate(size),
+                    // ISIZE is only the size modulo 2^32, and wrong in files that were cut short and patched up
+                    Err(DecompressionError::InsufficientSpace) => {
+                        return decompress_gzip_stream(data.as_ref());
+                    }
                     Err(error) => {
-                        return Err(Error::new(std::io::ErrorKind::Other, error.to_string()));
+                        return Err(Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            error.to_string(),
+                        ));
                     }
                 }
                 Ok(outbuf)
@@ -66,11 +121,24 @@ impl CompressionType {
                 //we don't know the decompressed size, so we have to use system zlib here
                 let mut decoder = flate2::read::ZlibDecoder::new(data.as_ref());
                 let mut buffer = Vec::new();
-                decoder.read_to_end(&mut buffer)?;
+                decoder.read_to_end(&mut buffer).map_err(get_data_error)?;
                 Ok(buffer)
             }
             // For uncompressed data, return a copy of the input data
             CompressionType::Uncompressed => Ok(data.to_vec()),
+            // For LZ4 compression, undo the lz4-java block framing vanilla uses
+            CompressionType::Lz4 => lz4_block::decompress(data.as_ref()),
+        }
+    }
+
+    // Decompresses a given slice of bytes lazily, so readers that stop early never decompress the rest
+    #[cfg(feature = "nbt")]
+    pub(crate) fn decompress_stream<'a>(&self, data: &'a [u8]) -> Box<dyn Read + 'a> {
+        match self {
+            CompressionType::Gzip => Box::new(flate2::read::GzDecoder::new(data)),
+            CompressionType::Zlib => Box::new(flate2::read::ZlibDecoder::new(data)),
+            CompressionType::Uncompressed => Box::new(data),
+            CompressionType::Lz4 => Box::new(Lz4BlockReader::new(data)),
         }
     }
 
@@ -88,7 +156,7 @@ impl CompressionType {
                 let mut compressed_data = Vec::new();
                 compressed_data.resize(max_sz, 0);
                 match compressor.gzip_compress(data, &mut compressed_data) {
-                    Ok(_) => {}
+                    Ok(size) => compressed_data.truncate(size),
                     Err(error) => {
                         return Err(Error::new(std::io::ErrorKind::Other, error.to_string()));
                     }
@@ -102,7 +170,7 @@ impl CompressionType {
                 let mut compressed_data = Vec::new();
                 compressed_data.resize(max_sz, 0);
                 match compressor.zlib_compress(data, &mut compressed_data) {
-                    Ok(_) => {}
+                    Ok(size) => compressed_data.truncate(size),
                     Err(error) => {
                         return Err(Error::new(std::io::ErrorKind::Other, error.to_string()));
                     }
@@ -112,6 +180,66 @@ impl CompressionType {
             }
             // For uncompressed data, return a copy of the input data
             CompressionType::Uncompressed => Ok(data.to_vec()),
+            // LZ4 has no compression levels, so the level is ignored
+            CompressionType::Lz4 => Ok(lz4_block::compress(data)),
         }
     }
 }
+
+// Decompresses a batch of chunks on the I/O pool, with this thread taking a share of the work as well. A chunk that fails only fails its own entry
+pub(crate) fn decompress_all(
+    compressed_chunks: Vec<CompressedChunk>,
+) -> Vec<Result<Option<Vec<u8>>, Error>> {
+    let threads = thread::available_parallelism()
+        .map_or(1, |threads| threads.get())
+        .min(compressed_chunks.len());
+
+    if threads <= 1 {
+        return decompress_batch(compressed_chunks);
+    }
+
+    let batch_size = (compressed_chunks.len() + threads - 1) / threads;
+
+    let mut compressed_chunks = compressed_chunks.into_iter();
+
+    let own_batch: Vec<_> = compressed_chunks.by_ref().take(batch_size).collect();
+
+    let jobs: Vec<_> = (1..threads)
+        .map(|_| {
+            let batch: Vec<_> = compressed_chunks.by_ref().take(batch_size).collect();
+
+            (batch.len(), spawn_io(move || Ok(decompress_batch(batch))))
+        })
+        .collect();
+
+    let mut chunks = decompress_batch(own_batch);
+
+    for (length, job) in jobs {
+        match job.wait() {
+            Ok(batch) => chunks.extend(batch),
+            // Only if decompressing panicked
+            Err(error) => chunks.extend((0..length).map(|_| Err(copy_error(&error)))),
+        }
+    }
+
+    chunks
+}
+
+fn decompress_batch(
+    compressed_chunks: Vec<CompressedChunk>,
+) -> Vec<Result<Option<Vec<u8>>, Error>> {
+    compressed_chunks
+        .into_iter()
+        .map(|chunk| match chunk? {
+            None => Ok(None),
+            Some((compression_type, data)) => {
+                compression_type.decompress(Cow::Owned(data)).map(Some)
+            }
+        })
+        .collect()
+}
+
+// io::Error can't be cloned, this keeps its kind and message
+pub(crate) fn copy_error(error: &Error) -> Error {
+    Error::new(error.kind(), error.to_string())
+}
@@ -0,0 +1,24 @@
+/// How far a flush goes before it returns.
+#[derive(Hash, Eq, PartialEq, Copy, Clone, Debug, Default)]
+pub enum Durability {
+    /// Starts writing dirty pages back without waiting for them (`msync` with `MS_ASYNC`)
+    #[default]
+    Async,
+    /// Waits until the data is on disk (`msync` with `MS_SYNC` followed by `fdatasync`)
+    Sync,
+    /// Like Sync, and also syncs the region directory so newly created region files survive a crash
+    SyncDirectory,
+}
+
+impl Durability {
+    // Ids used by the foreign bindings
+    #[cfg(any(feature = "ffi", feature = "java"))]
+    pub(crate) fn from_u32(id: u32) -> Option<Durability> {
+        match id {
+            0 => Some(Durability::Async),
+            1 => Some(Durability::Sync),
+            2 => Some(Durability::SyncDirectory),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use std::io::Error;
+
+use ahash::RandomState;
+use hashbrown::HashMap;
+use openssl::rand::rand_bytes;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+
+use crate::position::ChunkPos;
+
+const KEY_ID_LENGTH: usize = 4;
+const NONCE_LENGTH: usize = 12;
+const TAG_LENGTH: usize = 16;
+
+// Encrypted chunks are framed as key id, nonce, ciphertext and tag, the compression byte gets this flag so they can be told apart
+pub(crate) const ENCRYPTED_FLAG: u8 = 64;
+
+struct EncryptionKey {
+    key: [u8; 32],
+}
+
+// Every key chunks of a directory may be encrypted with, and the one new chunks are encrypted with
+pub(crate) struct EncryptionKeys {
+    current_key_id: Option<u32>,
+    keys: HashMap<u32, EncryptionKey, RandomState>,
+}
+
+impl EncryptionKeys {
+    pub(crate) fn new(
+        keys: &[(u32, [u8; 32])],
+        current_key_id: Option<u32>,
+    ) -> Result<EncryptionKeys, Error> {
+        let keys: HashMap<u32, EncryptionKey, RandomState> = keys
+            .iter()
+            .map(|(key_id, key)| (*key_id, EncryptionKey { key: *key }))
+            .collect();
+
+        if let Some(current_key_id) = current_key_id {
+            if !keys.contains_key(&current_key_id) {
+                return Err(Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "Current encryption key is not one of the keys",
+                ));
+            }
+        }
+
+        Ok(EncryptionKeys {
+            current_key_id,
+            keys,
+        })
+    }
+
+    pub(crate) fn get_current_key_id(&self) -> Option<u32> {
+        self.current_key_id
+    }
+
+    pub(crate) fn encrypt(
+        &self,
+        chunk_coords: ChunkPos,
+        compression_byte: u8,
+        data: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let (key_id, key) = match self.current_key_id {
+            None => {
+                return Err(Error::new(
+                    std::io::ErrorKind::Other,
+                    "No encryption key is in use",
+                ));
+            }
+            Some(key_id) => (key_id, &self.keys[&key_id]),
+        };
+
+        let mut nonce = [0u8; NONCE_LENGTH];
+        let mut tag = [0u8; TAG_LENGTH];
+
+        // Random nonces are fine for AES-GCM as long as a key encrypts well under 2^32 chunks
+        rand_bytes(&mut nonce).map_err(get_error)?;
+
+        let ciphertext = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &key.key,
+            Some(&nonce),
+            &get_associated_data(chunk_coords, compression_byte, key_id),
+            data,
+            &mut tag,
+        )
+        .map_err(get_error)?;
+
+        let mut payload =
+            Vec::with_capacity(KEY_ID_LENGTH + NONCE_LENGTH + ciphertext.len() + TAG_LENGTH);
+
+        payload.extend_from_slice(&key_id.to_be_bytes());
+        payload.extend_from_slice(&nonce);
+        payload.extend_from_slice(&ciphertext);
+        payload.extend_from_slice(&tag);
+
+        Ok(payload)
+    }
+
+    pub(crate) fn decrypt(
+        &self,
+        chunk_coords: ChunkPos,
+        compression_byte: u8,
+        payload: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let key_id = match get_key_id(payload) {
+            Some(key_id) if payload.len() >= KEY_ID_LENGTH + NONCE_LENGTH + TAG_LENGTH => key_id,
+            _ => {
+                return Err(Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "Encrypted chunk is too short",
+                ));
+            }
+        };
+
+        let key = match self.keys.get(&key_id) {
+            None => {
+                return Err(Error::new(
+                    std::io::ErrorKind::PermissionDenied,
+                    format!("Chunk is encrypted with key {} which is not set", key_id),
+                ));
+            }
+            Some(key) => key,
+        };
+
+        let (nonce, rest) = payload[KEY_ID_LENGTH..].split_at(NONCE_LENGTH);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LENGTH);
+
+        match decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &key.key,
+            Some(nonce),
+            &get_associated_data(chunk_coords, compression_byte, key_id),
+            ciphertext,
+            tag,
+        ) {
+            Ok(data) => Ok(data),
+            Err(_) => Err(Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Encrypted chunk failed authentication",
+            )),
+        }
+    }
+}
+
+pub(crate) fn get_encryption_status(compression_byte: u8) -> bool {
+    compression_byte & ENCRYPTED_FLAG != 0
+}
+
+pub(crate) fn get_key_id(payload: &[u8]) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        payload.get(0..KEY_ID_LENGTH)?.try_into().ok()?,
+    ))
+}
+
+// Binding the coordinates, compression type and key id stops chunks from being swapped around or relabeled without the key.
+// The timestamp is left out on purpose: it sits unauthenticated in the header, so an old ciphertext replayed together with its old timestamp would pass anyway
+fn get_associated_data(chunk_coords: ChunkPos, compression_byte: u8, key_id: u32) -> [u8; 13] {
+    let mut associated_data = [0u8; 13];
+
+    associated_data[0..4].copy_from_slice(&chunk_coords.x.to_be_bytes());
+    associated_data[4..8].copy_from_slice(&chunk_coords.z.to_be_bytes());
+    // The oversized flag isn't part of it, it only says where the payload is stored
+    associated_data[8] = compression_byte & 127;
+    associated_data[9..13].copy_from_slice(&key_id.to_be_bytes());
+
+    associated_data
+}
+
+fn get_error(error: openssl::error::ErrorStack) -> Error {
+    Error::new(std::io::ErrorKind::Other, error.to_string())
+}
@@ -0,0 +1,253 @@
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::io::Error;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+use crate::durability::Durability;
+use crate::position::ChunkPos;
+use crate::world::{Dimension, RegionKind, World};
+
+pub const P2VEC_OK: i32 = 0;
+pub const P2VEC_ERROR_INVALID_ARGUMENT: i32 = 1;
+pub const P2VEC_ERROR_NOT_FOUND: i32 = 2;
+pub const P2VEC_ERROR_PERMISSION_DENIED: i32 = 3;
+/// The region file is locked by another process
+pub const P2VEC_ERROR_LOCKED: i32 = 4;
+/// The region or chunk data is corrupt
+pub const P2VEC_ERROR_INVALID_DATA: i32 = 5;
+pub const P2VEC_ERROR_IO: i32 = 6;
+pub const P2VEC_ERROR_PANIC: i32 = 7;
+
+pub const P2VEC_DIMENSION_OVERWORLD: u32 = 0;
+pub const P2VEC_DIMENSION_NETHER: u32 = 1;
+pub const P2VEC_DIMENSION_END: u32 = 2;
+
+pub const P2VEC_REGION_KIND_REGION: u32 = 0;
+pub const P2VEC_REGION_KIND_ENTITIES: u32 = 1;
+pub const P2VEC_REGION_KIND_POI: u32 = 2;
+
+pub const P2VEC_DURABILITY_ASYNC: u32 = 0;
+pub const P2VEC_DURABILITY_SYNC: u32 = 1;
+pub const P2VEC_DURABILITY_SYNC_DIRECTORY: u32 = 2;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// An open world, only ever handled through a pointer
+pub struct P2vecWorld {
+    world: World,
+}
+
+/// Opens the world folder at `path`. On success `world` is set to a handle that must be closed with `p2vec_close_world`.
+///
+/// # Safety
+/// `path` must be a valid NUL terminated string and `world` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn p2vec_open_world(path: *const c_char, world: *mut *mut P2vecWorld) -> i32 {
+    if path.is_null() || world.is_null() {
+        return invalid_argument("Path and world must not be null");
+    }
+
+    let path = match CStr::from_ptr(path).to_str() {
+        Ok(path) => path,
+        Err(_) => return invalid_argument("Path is not valid UTF-8"),
+    };
+
+    call(|| {
+        *world = Box::into_raw(Box::new(P2vecWorld {
+            world: World::new(path),
+        }));
+
+        Ok(())
+    })
+}
+
+/// Closes every region of the world and frees the handle. Passing null does nothing.
+///
+/// # Safety
+/// `world` must be null or a handle from `p2vec_open_world` that isn't used anymore.
+#[no_mangle]
+pub unsafe extern "C" fn p2vec_close_world(world: *mut P2vecWorld) -> i32 {
+    if world.is_null() {
+        return P2VEC_OK;
+    }
+
+    let world = Box::from_raw(world);
+
+    call(|| world.world.close())
+}
+
+/// Reads a chunk into a new buffer that must be freed with `p2vec_free_buffer`. A chunk that doesn't exist sets `data` to null and `length` to 0.
+///
+/// # Safety
+/// `world` must be a handle from `p2vec_open_world`, `data` and `length` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn p2vec_read_chunk(
+    world: *const P2vecWorld,
+    dimension: u32,
+    kind: u32,
+    x: i32,
+    z: i32,
+    data: *mut *mut u8,
+    length: *mut usize,
+) -> i32 {
+    if world.is_null() || data.is_null() || length.is_null() {
+        return invalid_argument("World, data and length must not be null");
+    }
+
+    let (dimension, kind) = match get_dimension_and_kind(dimension, kind) {
+        Some(dimension_and_kind) => dimension_and_kind,
+        None => return invalid_argument("Invalid dimension or region kind"),
+    };
+
+    *data = ptr::null_mut();
+    *length = 0;
+
+    call(|| {
+        if let Some(chunk) = (*world)
+            .world
+            .read_chunk(dimension, kind, ChunkPos::new(x, z))?
+        {
+            let chunk = Box::into_raw(chunk.into_boxed_slice());
+
+            *length = chunk.len();
+            *data = chunk as *mut u8;
+        }
+
+        Ok(())
+    })
+}
+
+/// Writes a chunk if `timestamp` is newer than the one stored for it. `compression_type` is 1 for gzip, 2 for zlib, 3 for uncompressed and 4 for LZ4.
+///
+/// # Safety
+/// `world` must be a handle from `p2vec_open_world` and `data` must be valid for reads of `length` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn p2vec_write_chunk(
+    world: *const P2vecWorld,
+    dimension: u32,
+    kind: u32,
+    x: i32,
+    z: i32,
+    timestamp: u32,
+    data: *const u8,
+    length: usize,
+    compression_type: u8,
+    compression_level: i32,
+) -> i32 {
+    if world.is_null() || (data.is_null() && length != 0) {
+        return invalid_argument("World and data must not be null");
+    }
+
+    let (dimension, kind) = match get_dimension_and_kind(dimension, kind) {
+        Some(dimension_and_kind) => dimension_and_kind,
+        None => return invalid_argument("Invalid dimension or region kind"),
+    };
+
+    if !(1..=4).contains(&compression_type) {
+        return invalid_argument("Invalid compression type");
+    }
+
+    let data = match length {
+        0 => &[],
+        _ => std::slice::from_raw_parts(data, length),
+    };
+
+    call(|| {
+        (*world).world.write_chunk(
+            dimension,
+            kind,
+            ChunkPos::new(x, z),
+            timestamp,
+            data,
+            compression_type,
+            compression_level,
+        )
+    })
+}
+
+/// Flushes every open region of the world. `durability` is one of the `P2VEC_DURABILITY_` constants.
+///
+/// # Safety
+/// `world` must be a handle from `p2vec_open_world`.
+#[no_mangle]
+pub unsafe extern "C" fn p2vec_flush_world(world: *const P2vecWorld, durability: u32) -> i32 {
+    if world.is_null() {
+        return invalid_argument("World must not be null");
+    }
+
+    let durability = match Durability::from_u32(durability) {
+        Some(durability) => durability,
+        None => return invalid_argument("Invalid durability"),
+    };
+
+    call(|| (*world).world.flush(durability))
+}
+
+/// Frees a buffer returned by `p2vec_read_chunk`. Passing null does nothing.
+///
+/// # Safety
+/// `data` and `length` must come from the same `p2vec_read_chunk` call and the buffer must not be freed twice.
+#[no_mangle]
+pub unsafe extern "C" fn p2vec_free_buffer(data: *mut u8, length: usize) {
+    if data.is_null() {
+        return;
+    }
+
+    drop(Box::from_raw(ptr::slice_from_raw_parts_mut(data, length)));
+}
+
+/// Returns a description of the last error on this thread, or null if there wasn't one. The string stays valid until the next call on this thread.
+#[no_mangle]
+pub extern "C" fn p2vec_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last_error| match &*last_error.borrow() {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+fn get_dimension_and_kind(dimension: u32, kind: u32) -> Option<(Dimension, RegionKind)> {
+    Some((Dimension::from_u32(dimension)?, RegionKind::from_u32(kind)?))
+}
+
+// Panics must not unwind into C
+fn call(function: impl FnOnce() -> Result<(), Error>) -> i32 {
+    match catch_unwind(AssertUnwindSafe(function)) {
+        Ok(Ok(())) => P2VEC_OK,
+        Ok(Err(error)) => {
+            set_last_error(&error.to_string());
+
+            get_error_code(&error)
+        }
+        Err(_) => {
+            set_last_error("p2vec panicked");
+
+            P2VEC_ERROR_PANIC
+        }
+    }
+}
+
+fn get_error_code(error: &Error) -> i32 {
+    match error.kind() {
+        std::io::ErrorKind::InvalidInput => P2VEC_ERROR_INVALID_ARGUMENT,
+        std::io::ErrorKind::NotFound => P2VEC_ERROR_NOT_FOUND,
+        std::io::ErrorKind::PermissionDenied => P2VEC_ERROR_PERMISSION_DENIED,
+        std::io::ErrorKind::WouldBlock => P2VEC_ERROR_LOCKED,
+        std::io::ErrorKind::InvalidData => P2VEC_ERROR_INVALID_DATA,
+        _ => P2VEC_ERROR_IO,
+    }
+}
+
+fn invalid_argument(message: &str) -> i32 {
+    set_last_error(message);
+
+    P2VEC_ERROR_INVALID_ARGUMENT
+}
+
+fn set_last_error(message: &str) {
+    LAST_ERROR.with(|last_error| {
+        *last_error.borrow_mut() = CString::new(message.replace('\0', " ")).ok();
+    });
+}
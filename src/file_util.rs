@@ -7,6 +7,8 @@ use close_err::Closable;
 use fs3::FileExt;
 use libc::c_int;
 
+use crate::access_mode::AccessMode;
+
 pub(crate) fn file_advise(file: &File, advice: c_int) -> Result<(), Error> {
     let mut error = 0;
     #[cfg(all(unix, target_os = "linux"))]
@@ -21,19 +23,45 @@ pub(crate) fn file_advise(file: &File, advice: c_int) -> Result<(), Error> {
     }
 }
 
-pub(crate) fn open_file(initial_size: usize, path: &Path) -> Result<File, Error> {
-    if !path.is_file() {
+pub(crate) fn open_file(
+    initial_size: usize,
+    path: &Path,
+    access_mode: AccessMode,
+    create: bool,
+) -> Result<Option<File>, Error> {
+    if access_mode.is_read_only() {
+        let file = match OpenOptions::new().read(true).open(path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error),
+        };
+
+        if access_mode == AccessMode::ReadOnly {
+            file.try_lock_shared()?;
+        }
+
+        file_advise(&file, libc::POSIX_FADV_WILLNEED)?;
+
+        return Ok(Some(file));
+    }
+
+    if create && !path.is_file() {
         fs::create_dir_all(match path.parent() {
             None => return Err(Error::new(std::io::ErrorKind::Other, "Invalid Directory")),
             Some(path) => path,
         })?;
     }
 
-    let file = OpenOptions::new()
+    let file = match OpenOptions::new()
         .read(true)
         .write(true)
-        .create(true)
-        .open(path)?;
+        .create(create)
+        .open(path)
+    {
+        Ok(file) => file,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error),
+    };
 
     file.try_lock_exclusive()?;
 
@@ -41,7 +69,17 @@ pub(crate) fn open_file(initial_size: usize, path: &Path) -> Result<File, Error>
 
     file.allocate(initial_size as u64)?;
 
-    Ok(file)
+    Ok(Some(file))
+}
+
+pub(crate) fn write_file_at(file: &File, offset: u64, data: &[u8]) -> Result<(), Error> {
+    use std::os::unix::fs::FileExt as UnixFileExt;
+
+    file.write_all_at(data, offset)
+}
+
+pub(crate) fn sync_directory(path: &Path) -> Result<(), Error> {
+    File::open(path)?.sync_all()
 }
 
 pub(crate) fn close_file(file: File) -> Result<(), Error> {
@@ -0,0 +1,176 @@
+use std::future::Future;
+use std::io::Error;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::thread::Thread;
+
+use concurrent_queue::ConcurrentQueue;
+use once_cell::sync::Lazy;
+use parking_lot::{Condvar, Mutex};
+
+type IoJob = Box<dyn FnOnce() + Send>;
+
+struct IoPool {
+    jobs: ConcurrentQueue<IoJob>,
+    sleep_lock: Mutex<()>,
+    sleep_condvar: Condvar,
+}
+
+static IO_POOL: Lazy<Arc<IoPool>> = Lazy::new(|| {
+    let pool = Arc::new(IoPool {
+        jobs: ConcurrentQueue::unbounded(),
+        sleep_lock: Mutex::new(()),
+        sleep_condvar: Condvar::new(),
+    });
+
+    let threads = thread::available_parallelism().map_or(4, |threads| threads.get());
+
+    for thread_id in 0..threads {
+        let pool = pool.clone();
+
+        thread::Builder::new()
+            .name(format!("p2vec-io-{}", thread_id))
+            .spawn(move || pool.run())
+            .expect("Failed to spawn p2vec I/O thread");
+    }
+
+    pool
+});
+
+impl IoPool {
+    fn run(&self) {
+        loop {
+            match self.jobs.pop() {
+                Ok(job) => job(),
+                Err(_) => {
+                    let mut sleep_guard = self.sleep_lock.lock();
+
+                    // Checked under the lock so a job pushed right before we sleep can't be missed
+                    if self.jobs.is_empty() {
+                        self.sleep_condvar.wait(&mut sleep_guard);
+                    }
+                }
+            }
+        }
+    }
+
+    fn submit(&self, job: IoJob) {
+        // The queue is unbounded and never closed so this can't fail
+        let _ = self.jobs.push(job);
+
+        let _sleep_guard = self.sleep_lock.lock();
+
+        self.sleep_condvar.notify_one();
+    }
+}
+
+struct IoTask<T> {
+    // Taken by whoever runs it, so it runs at most once and not at all once the future is dropped
+    job: Option<Box<dyn FnOnce() -> Result<T, Error> + Send>>,
+    result: Option<Result<T, Error>>,
+    waker: Option<Waker>,
+}
+
+fn run_task<T>(task: &Mutex<IoTask<T>>) {
+    let job = match task.lock().job.take() {
+        None => return,
+        Some(job) => job,
+    };
+
+    let result = catch_unwind(AssertUnwindSafe(job))
+        .unwrap_or_else(|_| Err(Error::new(std::io::ErrorKind::Other, "I/O task panicked")));
+
+    let waker = {
+        let mut task = task.lock();
+        task.result = Some(result);
+        task.waker.take()
+    };
+
+    if let Some(waker) = waker {
+        waker.wake();
+    }
+}
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+// A handle to a job running on the I/O pool. Dropping it before the job starts skips the job; once a job has started it always runs to completion, so a write is either fully applied or never started.
+pub(crate) struct IoFuture<T> {
+    task: Arc<Mutex<IoTask<T>>>,
+}
+
+pub(crate) fn spawn_io<T, F>(function: F) -> IoFuture<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, Error> + Send + 'static,
+{
+    let task = Arc::new(Mutex::new(IoTask {
+        job: Some(Box::new(function)),
+        result: None,
+        waker: None,
+    }));
+
+    let job_task = task.clone();
+
+    IO_POOL.submit(Box::new(move || run_task(&job_task)));
+
+    IoFuture { task }
+}
+
+impl<T> IoFuture<T> {
+    // Blocks until the job is done. A job no pool thread has started yet runs on this thread instead, so waiting from a pool thread can't leave every thread waiting on jobs nobody runs
+    pub(crate) fn wait(self) -> Result<T, Error> {
+        run_task(&self.task);
+
+        loop {
+            {
+                let mut task = self.task.lock();
+
+                if let Some(result) = task.result.take() {
+                    return result;
+                }
+
+                task.waker = Some(Arc::new(ThreadWaker(thread::current())).into());
+            }
+
+            thread::park();
+        }
+    }
+}
+
+impl<T> Future for IoFuture<T> {
+    type Output = Result<T, Error>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut task = self.task.lock();
+
+        match task.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                match &task.waker {
+                    Some(waker) if waker.will_wake(context.waker()) => {}
+                    _ => task.waker = Some(context.waker().clone()),
+                }
+
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> Drop for IoFuture<T> {
+    fn drop(&mut self) {
+        // Dropped outside the lock, the job may own a lot of data
+        let job = self.task.lock().job.take();
+
+        drop(job);
+    }
+}
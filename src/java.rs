@@ -0,0 +1,316 @@
+use std::io::Error;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+use ahash::RandomState;
+use dashmap::DashMap;
+use jni::objects::{JByteBuffer, JClass, JString};
+use jni::sys::{jboolean, jint, jlong, jobject, JNI_FALSE, JNI_TRUE};
+use jni::JNIEnv;
+use once_cell::sync::Lazy;
+
+use crate::durability::Durability;
+use crate::position::{ChunkPos, RegionPos};
+use crate::world::{Dimension, RegionKind, World};
+
+// Every entry point here backs a native method of com.duplexsystem.p2vec.P2vec, see java/ for the Java side
+
+// Addresses and lengths of the buffers readChunk handed out that weren't freed yet, anything else Java passes to freeBuffer isn't ours to free
+static BUFFERS: Lazy<DashMap<usize, usize, RandomState>> =
+    Lazy::new(|| DashMap::with_capacity_and_hasher(1, RandomState::default()));
+
+#[no_mangle]
+pub extern "system" fn Java_com_duplexsystem_p2vec_P2vec_openWorld<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    path: JString<'local>,
+) -> jlong {
+    call(&mut env, 0, |env| {
+        let path: String = match env.get_string(&path) {
+            Ok(path) => path.into(),
+            Err(_) => return Err(invalid_argument("Path must not be null")),
+        };
+
+        Ok(Box::into_raw(Box::new(World::new(&path))) as jlong)
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_duplexsystem_p2vec_P2vec_closeWorld<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    world: jlong,
+) {
+    if world == 0 {
+        return;
+    }
+
+    let world = unsafe { Box::from_raw(world as *mut World) };
+
+    call(&mut env, (), |_| world.close())
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_duplexsystem_p2vec_P2vec_readChunk<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    world: jlong,
+    dimension: jint,
+    kind: jint,
+    x: jint,
+    z: jint,
+) -> jobject {
+    call(&mut env, ptr::null_mut(), |env| {
+        let (world, dimension, kind) = get_world(world, dimension, kind)?;
+
+        let data = match world.read_chunk(dimension, kind, ChunkPos::new(x, z))? {
+            None => return Ok(ptr::null_mut()),
+            Some(data) => Box::into_raw(data.into_boxed_slice()),
+        };
+
+        // The buffer points straight at the chunk data, Java frees it with freeBuffer
+        match unsafe { env.new_direct_byte_buffer(data as *mut u8, data.len()) } {
+            Ok(buffer) => {
+                // Empty chunks don't allocate, so there is nothing to free later
+                if !data.is_empty() {
+                    BUFFERS.insert(data as *mut u8 as usize, data.len());
+                }
+
+                Ok(buffer.into_raw())
+            }
+            Err(_) => {
+                drop(unsafe { Box::from_raw(data) });
+
+                Err(Error::new(
+                    std::io::ErrorKind::Other,
+                    "Failed to create a direct buffer",
+                ))
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_duplexsystem_p2vec_P2vec_freeBuffer<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    buffer: JByteBuffer<'local>,
+) {
+    call(&mut env, (), |env| {
+        if buffer.is_null() {
+            return Ok(());
+        }
+
+        let (data, length) = get_buffer(env, &buffer)?;
+
+        if length == 0 {
+            return Ok(());
+        }
+
+        // Taking it out of the map first means a buffer freed twice at once is only freed once
+        if BUFFERS
+            .remove_if(&(data as usize), |_, buffer_length| {
+                *buffer_length == length
+            })
+            .is_none()
+        {
+            return Err(invalid_argument(
+                "Buffer wasn't returned by readChunk or was already freed",
+            ));
+        }
+
+        drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(data, length)) });
+
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_duplexsystem_p2vec_P2vec_readChunkTimestamp<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    world: jlong,
+    dimension: jint,
+    kind: jint,
+    x: jint,
+    z: jint,
+) -> jint {
+    call(&mut env, 0, |_| {
+        let (world, dimension, kind) = get_world(world, dimension, kind)?;
+
+        Ok(world.read_chunk_timestamp(dimension, kind, ChunkPos::new(x, z))? as jint)
+    })
+}
+
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub extern "system" fn Java_com_duplexsystem_p2vec_P2vec_writeChunk0<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    world: jlong,
+    dimension: jint,
+    kind: jint,
+    x: jint,
+    z: jint,
+    timestamp: jint,
+    data: JByteBuffer<'local>,
+    length: jint,
+    compression_type: jint,
+    compression_level: jint,
+) -> jboolean {
+    call(&mut env, JNI_FALSE, |env| {
+        let (world, dimension, kind) = get_world(world, dimension, kind)?;
+
+        if !(1..=4).contains(&compression_type) {
+            return Err(invalid_argument("Invalid compression type"));
+        }
+
+        let (address, capacity) = get_buffer(env, &data)?;
+
+        if length < 0 || length as usize > capacity {
+            return Err(invalid_argument(
+                "Length is out of the bounds of the buffer",
+            ));
+        }
+
+        let data = unsafe { std::slice::from_raw_parts(address, length as usize) };
+
+        let written = world.write_chunk_if_newer(
+            dimension,
+            kind,
+            ChunkPos::new(x, z),
+            timestamp as u32,
+            data,
+            compression_type as u8,
+            compression_level,
+        )?;
+
+        Ok(if written { JNI_TRUE } else { JNI_FALSE })
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_duplexsystem_p2vec_P2vec_closeRegion<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    world: jlong,
+    dimension: jint,
+    kind: jint,
+    region_x: jint,
+    region_z: jint,
+) {
+    call(&mut env, (), |_| {
+        let (world, dimension, kind) = get_world(world, dimension, kind)?;
+
+        world.close_region(dimension, kind, RegionPos::new(region_x, region_z))
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_duplexsystem_p2vec_P2vec_flushRegion<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    world: jlong,
+    dimension: jint,
+    kind: jint,
+    region_x: jint,
+    region_z: jint,
+    durability: jint,
+) {
+    call(&mut env, (), |_| {
+        let (world, dimension, kind) = get_world(world, dimension, kind)?;
+
+        world.flush_region(
+            dimension,
+            kind,
+            RegionPos::new(region_x, region_z),
+            get_durability(durability)?,
+        )
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_duplexsystem_p2vec_P2vec_flushWorld<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    world: jlong,
+    durability: jint,
+) {
+    call(&mut env, (), |_| {
+        let (world, _, _) = get_world(world, 0, 0)?;
+
+        world.flush(get_durability(durability)?)
+    })
+}
+
+fn get_durability(durability: jint) -> Result<Durability, Error> {
+    match Durability::from_u32(durability as u32) {
+        None => Err(invalid_argument("Invalid durability")),
+        Some(durability) => Ok(durability),
+    }
+}
+
+fn get_world<'a>(
+    world: jlong,
+    dimension: jint,
+    kind: jint,
+) -> Result<(&'a World, Dimension, RegionKind), Error> {
+    if world == 0 {
+        return Err(invalid_argument("World is closed"));
+    }
+
+    let dimension = match Dimension::from_u32(dimension as u32) {
+        None => return Err(invalid_argument("Invalid dimension")),
+        Some(dimension) => dimension,
+    };
+
+    let kind = match RegionKind::from_u32(kind as u32) {
+        None => return Err(invalid_argument("Invalid region kind")),
+        Some(kind) => kind,
+    };
+
+    Ok((unsafe { &*(world as *const World) }, dimension, kind))
+}
+
+fn get_buffer(env: &mut JNIEnv, buffer: &JByteBuffer) -> Result<(*mut u8, usize), Error> {
+    match (
+        env.get_direct_buffer_address(buffer),
+        env.get_direct_buffer_capacity(buffer),
+    ) {
+        (Ok(address), Ok(capacity)) => Ok((address, capacity)),
+        _ => Err(invalid_argument("Buffer must be a direct buffer")),
+    }
+}
+
+fn invalid_argument(message: &str) -> Error {
+    Error::new(std::io::ErrorKind::InvalidInput, message)
+}
+
+// Errors become Java exceptions and panics must not unwind into the JVM, the returned value is ignored by Java once an exception is pending
+fn call<'local, T>(
+    env: &mut JNIEnv<'local>,
+    default: T,
+    function: impl FnOnce(&mut JNIEnv<'local>) -> Result<T, Error>,
+) -> T {
+    let error = match catch_unwind(AssertUnwindSafe(|| function(env))) {
+        Ok(Ok(value)) => return value,
+        Ok(Err(error)) => error,
+        Err(_) => {
+            let _ = env.throw_new("java/lang/IllegalStateException", "p2vec panicked");
+
+            return default;
+        }
+    };
+
+    let exception_class = match error.kind() {
+        std::io::ErrorKind::InvalidInput => "java/lang/IllegalArgumentException",
+        std::io::ErrorKind::NotFound => "java/io/FileNotFoundException",
+        std::io::ErrorKind::PermissionDenied => "java/nio/file/AccessDeniedException",
+        _ => "java/io/IOException",
+    };
+
+    // A failed throw means another exception is already pending, which Java sees instead
+    let _ = env.throw_new(exception_class, error.to_string());
+
+    default
+}
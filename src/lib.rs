@@ -1,85 +1,1160 @@
 use std::io::Error;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use ahash::RandomState;
+use dashmap::mapref::entry::Entry;
+#[cfg(feature = "linear")]
 use dashmap::mapref::one::Ref;
 use dashmap::DashMap;
-use glam::IVec2;
+use dashmap::DashSet;
+use hashbrown::HashMap;
 use libdeflater::CompressionLvl;
 use once_cell::sync::Lazy;
 
-use crate::compression::CompressionType;
+pub use crate::access_mode::AccessMode;
+use crate::compression::{copy_error, decompress_all, CompressionType};
+pub use crate::durability::Durability;
+use crate::encryption::EncryptionKeys;
+use crate::io_pool::spawn_io;
+#[cfg(feature = "linear")]
+use crate::linear::{write_linear_file, LinearRegion};
+use crate::memory_storage::MemoryStorage;
 use crate::memory_util::get_alignment_vector;
-use crate::region::Region;
-use crate::region_file_util::get_chunk_region_coords;
+#[cfg(feature = "nbt")]
+use crate::nbt::{write_nbt, ChunkNbt, Compound, Tag};
+pub use crate::position::{ChunkPos, LocalPos, RegionPos};
+use crate::region::{Region, WriteCondition};
+use crate::region_format::RegionFormat;
+use crate::region_handle::{ClosingRegion, RegionHandle};
+pub use crate::region_header::{RegionHeader, SectorRange};
+pub use crate::region_info::{ChunkInfo, RegionInfo};
 use crate::region_key::RegionKey;
+pub use crate::sector_map::{SectorMap, SectorOwner};
+use crate::storage::{DiskStorage, Storage};
+pub use crate::verify::{CorruptChunk, VerifyReport};
+pub use crate::world::{Dimension, RegionKind, World};
 
+mod access_mode;
 mod chunk;
 mod compression;
+mod durability;
+mod encryption;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 mod file_util;
+mod io_pool;
+#[cfg(feature = "java")]
+mod java;
+#[cfg(feature = "linear")]
+mod linear;
+mod lz4_block;
 mod memory_mapped_file;
+mod memory_storage;
 mod memory_util;
+#[cfg(feature = "nbt")]
+pub mod nbt;
+#[cfg(feature = "nbt")]
+mod nbt_query;
+mod position;
 mod range_util;
 mod region;
 mod region_file_util;
-mod region_key;cle
+mod region_format;
+mod region_handle;
+mod region_header;
+mod region_info;
+mod region_key;
+mod sector_map;
+mod storage;
+mod verify;
+mod world;
 
-static REGIONS: Lazy<DashMap<RegionKey, Region, RandomState>> =
+// Readers clone the handle and let go of the map right away, so a region is only closed once nobody holds it anymore
+static REGIONS: Lazy<DashMap<RegionKey, RegionHandle, RandomState>> =
     Lazy::new(|| DashMap::with_capacity_and_hasher(1, RandomState::default()));
 
-pub(crate) fn open_region(
-    key: RegionKey,
-) -> Result<Ref<'static, RegionKey, Region, RandomState>, Error> {
-    Ok(REGIONS
-        .entry(key)
-        .or_try_insert_with(|| Region::new(&key))?
-        .downgrade())
+// Regions that were taken out of REGIONS to be closed but whose file is still open
+static CLOSING_REGIONS: Lazy<DashMap<RegionKey, Arc<ClosingRegion>, RandomState>> =
+    Lazy::new(|| DashMap::with_capacity_and_hasher(1, RandomState::default()));
+
+static CLOSE_TIMEOUTS: Lazy<DashMap<&'static str, Duration, RandomState>> =
+    Lazy::new(|| DashMap::with_capacity_and_hasher(1, RandomState::default()));
+
+const DEFAULT_CLOSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+static ACCESS_MODES: Lazy<DashMap<&'static str, AccessMode, RandomState>> =
+    Lazy::new(|| DashMap::with_capacity_and_hasher(1, RandomState::default()));
+
+static ENCRYPTION_KEYS: Lazy<DashMap<&'static str, Arc<EncryptionKeys>, RandomState>> =
+    Lazy::new(|| DashMap::with_capacity_and_hasher(1, RandomState::default()));
+
+static CHECKSUM_DIRECTORIES: Lazy<DashSet<&'static str, RandomState>> =
+    Lazy::new(|| DashSet::with_capacity_and_hasher(1, RandomState::default()));
+
+static MEMORY_DIRECTORIES: Lazy<DashSet<&'static str, RandomState>> =
+    Lazy::new(|| DashSet::with_capacity_and_hasher(1, RandomState::default()));
+
+static MEMORY_STORAGE: Lazy<MemoryStorage> = Lazy::new(MemoryStorage::default);
+
+static DISK_STORAGE: DiskStorage = DiskStorage;
+
+#[cfg(feature = "linear")]
+static LINEAR_REGIONS: Lazy<DashMap<RegionKey, LinearRegion, RandomState>> =
+    Lazy::new(|| DashMap::with_capacity_and_hasher(1, RandomState::default()));
+
+#[cfg(feature = "linear")]
+static LINEAR_DIRECTORIES: Lazy<DashSet<&'static str, RandomState>> =
+    Lazy::new(|| DashSet::with_capacity_and_hasher(1, RandomState::default()));
+
+pub(crate) fn get_access_mode(directory: &'static str) -> AccessMode {
+    match ACCESS_MODES.get(directory) {
+        Some(access_mode) => *access_mode,
+        None => AccessMode::default(),
+    }
 }
 
-pub(crate) fn get_region(
-    key: RegionKey,
-) -> Result<Ref<'static, RegionKey, Region, RandomState>, Error> {
+/// Sets how the region files in `directory` are opened. Regions from `directory` that are already open are closed so they get reopened with the new mode.
+pub fn set_access_mode(directory: &'static str, access_mode: AccessMode) -> Result<(), Error> {
+    ACCESS_MODES.insert(directory, access_mode);
+
+    close_regions(directory)
+}
+
+fn get_close_timeout(directory: &'static str) -> Duration {
+    match CLOSE_TIMEOUTS.get(directory) {
+        Some(timeout) => *timeout,
+        None => DEFAULT_CLOSE_TIMEOUT,
+    }
+}
+
+/// Sets how long closing a region of `directory` waits for other threads to finish with it. Once that runs out closing fails with WouldBlock and the region stays open. Defaults to 10 seconds.
+pub fn set_close_timeout(directory: &'static str, timeout: Duration) {
+    CLOSE_TIMEOUTS.insert(directory, timeout);
+}
+
+pub(crate) fn get_storage(directory: &str) -> &'static dyn Storage {
+    match MEMORY_DIRECTORIES.contains(directory) {
+        true => &*MEMORY_STORAGE,
+        false => &DISK_STORAGE,
+    }
+}
+
+/// Keeps the region files of `directory` in memory instead of on disk, for tests and worlds that are thrown away. Nothing is read from or written to the real directory while this is on, and turning it off again throws away everything that was written in the meantime. Regions from `directory` that are already open are closed so they pick up the change.
+pub fn set_memory_backend(directory: &'static str, enabled: bool) -> Result<(), Error> {
+    match enabled {
+        true => MEMORY_DIRECTORIES.insert(directory),
+        false => MEMORY_DIRECTORIES.remove(directory).is_some(),
+    };
+
+    close_regions(directory)?;
+
+    #[cfg(feature = "linear")]
+    LINEAR_REGIONS.retain(|key, _| key.directory != directory);
+
+    if !enabled {
+        MEMORY_STORAGE.remove_directory(Path::new(directory));
+    }
+
+    Ok(())
+}
+
+/// Sets the AES-256-GCM keys for `directory` by key id. Chunks written from now on are encrypted with the key `current_key_id`, or left unencrypted if it is None. Encrypted chunks can only be read while the key they were written with is set and are rejected if they were tampered with. This doesn't stop rollback: an older encrypted version of the same chunk, copied back into the region file by someone who can write to it, still reads as valid, since the chunk's timestamp isn't authenticated. Callers that need to detect this have to keep track of the versions they wrote outside of the world directory. Regions from `directory` that are already open are closed so they pick up the new keys.
+pub fn set_encryption_keys(
+    directory: &'static str,
+    keys: &[(u32, [u8; 32])],
+    current_key_id: Option<u32>,
+) -> Result<(), Error> {
+    let encryption_keys = EncryptionKeys::new(keys, current_key_id)?;
+
+    match keys.is_empty() {
+        true => {
+            ENCRYPTION_KEYS.remove(directory);
+        }
+        false => {
+            ENCRYPTION_KEYS.insert(directory, Arc::new(encryption_keys));
+        }
+    };
+
+    close_regions(directory)
+}
+
+/// Keeps a checksum for every chunk of `directory` in a sidecar file next to each region file, updated on write and checked on read. Reads of chunks that don't match their checksum fail with InvalidData. A checksum only counts while the chunk's timestamp, location and stored length are the ones it was taken for, so chunks rewritten by something that doesn't know about the sidecar file are unchecked rather than corrupt. Regions from `directory` that are already open are closed so they pick up the change.
+pub fn set_checksums(directory: &'static str, enabled: bool) -> Result<(), Error> {
+    match enabled {
+        true => CHECKSUM_DIRECTORIES.insert(directory),
+        false => CHECKSUM_DIRECTORIES.remove(directory).is_some(),
+    };
+
+    close_regions(directory)
+}
+
+/// Reads and decompresses every chunk of the region at `region_coords`, checking them against their checksums where there are any.
+pub fn verify_region(
+    directory: &'static str,
+    region_coords: RegionPos,
+) -> Result<VerifyReport, Error> {
+    let key = RegionKey {
+        directory,
+        coords: region_coords,
+        format: RegionFormat::Anvil,
+    };
+
+    let region = match get_region(key, false)? {
+        None => return Ok(VerifyReport::default()),
+        Some(region) => region,
+    };
+
+    let mut report = VerifyReport::default();
+
+    for coords in region_coords.chunks() {
+        match region.verify_chunk(coords) {
+            Ok(None) => {}
+            Ok(Some(true)) => report.verified_chunks += 1,
+            Ok(Some(false)) => report.unchecked_chunks += 1,
+            Err(error) => report.corrupt_chunks.push(CorruptChunk {
+                directory,
+                coords,
+                error,
+            }),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Runs `verify_region` on every region file in `directory`.
+pub fn verify_regions(directory: &'static str) -> Result<VerifyReport, Error> {
+    let mut report = VerifyReport::default();
+
+    for region_coords in get_region_files(directory, RegionFormat::Anvil)? {
+        report.merge(verify_region(directory, region_coords)?);
+    }
+
+    Ok(report)
+}
+
+/// Runs `verify_regions` on every dimension and kind of `world`, reporting chunks that are corrupt instead of failing on them.
+pub fn verify_world(world: &World) -> Result<VerifyReport, Error> {
+    let mut report = VerifyReport::default();
+
+    for dimension in [Dimension::Overworld, Dimension::Nether, Dimension::End] {
+        for kind in [RegionKind::Region, RegionKind::Entities, RegionKind::Poi] {
+            report.merge(verify_regions(world.get_directory(dimension, kind))?);
+        }
+    }
+
+    Ok(report)
+}
+
+/// Rewrites every chunk of the region at `region_coords` that isn't encrypted with the current key of `directory` under that key, or unencrypted if there is none. Chunks are locked one at a time so the rest of the region stays readable, and chunks that are already done are skipped, so an interrupted pass can simply be run again. Returns how many chunks were rewritten.
+pub fn reencrypt_region(directory: &'static str, region_coords: RegionPos) -> Result<usize, Error> {
+    let key = RegionKey {
+        directory,
+        coords: region_coords,
+        format: RegionFormat::Anvil,
+    };
+
+    let mut reencrypted_chunks = 0;
+
+    for coords in region_coords.chunks() {
+        // The region is looked up again for every chunk so closing it never has to wait for the whole pass
+        let region = match get_region(key, false)? {
+            None => return Ok(reencrypted_chunks),
+            Some(region) => region,
+        };
+
+        if region.reencrypt_chunk(coords)? {
+            reencrypted_chunks += 1;
+        }
+    }
+
+    Ok(reencrypted_chunks)
+}
+
+/// Runs `reencrypt_region` on every region file in `directory`. Once it finished without an error, keys other than the current one are no longer used by `directory`. Returns how many chunks were rewritten.
+pub fn reencrypt_regions(directory: &'static str) -> Result<usize, Error> {
+    let mut reencrypted_chunks = 0;
+
+    for region_coords in get_region_files(directory, RegionFormat::Anvil)? {
+        reencrypted_chunks += reencrypt_region(directory, region_coords)?;
+    }
+
+    Ok(reencrypted_chunks)
+}
+
+// Lists the coordinates of the region files of the given format in directory
+fn get_region_files(
+    directory: &'static str,
+    format: RegionFormat,
+) -> Result<Vec<RegionPos>, Error> {
+    let mut region_files = Vec::new();
+
+    for file_name in get_storage(directory).list_directory(Path::new(directory))? {
+        let mut parts = file_name.split('.');
+
+        if let (Some("r"), Some(x), Some(z), Some(extension), None) = (
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+        ) {
+            if let (Ok(x), Ok(z), true) = (x.parse(), z.parse(), extension == format.extension()) {
+                region_files.push(RegionPos::new(x, z));
+            }
+        }
+    }
+
+    Ok(region_files)
+}
+
+/// Closes every open region of `directory`.
+pub fn close_regions(directory: &'static str) -> Result<(), Error> {
+    let open_regions: Vec<RegionKey> = REGIONS
+        .iter()
+        .map(|region| *region.key())
+        .filter(|key| key.directory == directory)
+        .collect();
+
+    for key in open_regions {
+        close_region_key(key)?;
+    }
+
+    #[cfg(feature = "linear")]
+    LINEAR_REGIONS.retain(|key, _| key.directory != directory);
+
+    Ok(())
+}
+
+pub(crate) fn open_region(key: RegionKey, create: bool) -> Result<Option<RegionHandle>, Error> {
+    loop {
+        let closing = match REGIONS.entry(key) {
+            Entry::Occupied(entry) => return Ok(Some(entry.get().clone())),
+            // Checked while holding the entry, which closing takes to mark the region, so a close can't slip in between
+            Entry::Vacant(entry) => match CLOSING_REGIONS.get(&key) {
+                Some(closing) => closing.clone(),
+                None => {
+                    return match Region::new(
+                        &key,
+                        get_storage(key.directory),
+                        key.format.get_access_mode(get_access_mode(key.directory)),
+                        ENCRYPTION_KEYS
+                            .get(key.directory)
+                            .map(|encryption_keys| encryption_keys.clone()),
+                        CHECKSUM_DIRECTORIES.contains(key.directory),
+                        create,
+                    )? {
+                        // The region doesn't exist and we either aren't allowed to or don't need to create it
+                        None => Ok(None),
+                        Some(region) => Ok(Some(entry.insert(RegionHandle::new(region)).clone())),
+                    };
+                }
+            },
+        };
+
+        // The entry is let go of first, a close that gives up puts the region back into it
+        closing.wait();
+    }
+}
+
+// The returned region stays open until it is dropped. Closing a region waits for every handle to it, so a handle held while closing its own region makes the close time out
+pub(crate) fn get_region(key: RegionKey, create: bool) -> Result<Option<RegionHandle>, Error> {
     match REGIONS.get(&key) {
-        Some(region) => Ok(region),
-        None => open_region(key),
+        Some(region) => Ok(Some(region.clone())),
+        None => open_region(key, create),
+    }
+}
+
+pub fn close_region(directory: &'static str, coords: RegionPos) -> Result<(), Error> {
+    close_region_key(RegionKey {
+        directory,
+        coords,
+        format: RegionFormat::Anvil,
+    })?;
+
+    close_region_key(RegionKey {
+        directory,
+        coords,
+        format: RegionFormat::McRegion,
+    })?;
+
+    #[cfg(feature = "linear")]
+    LINEAR_REGIONS.remove(&RegionKey {
+        directory,
+        coords,
+        format: RegionFormat::Linear,
+    });
+
+    Ok(())
+}
+
+fn close_region_key(key: RegionKey) -> Result<(), Error> {
+    take_region_key(key, Region::close)?;
+
+    Ok(())
+}
+
+// Removes the region, waits until nobody else holds it and hands it to closer. Returns None if the region isn't open, or WouldBlock with the region left open if it is still held once the close timeout runs out
+fn take_region_key<T>(
+    key: RegionKey,
+    closer: impl FnOnce(&mut Region) -> Result<T, Error>,
+) -> Result<Option<T>, Error> {
+    let closing = Arc::new(ClosingRegion::default());
+
+    // Nobody can take a new handle once it is out of the map, and it can't be reopened until the file is closed
+    let mut region = match REGIONS.entry(key) {
+        Entry::Vacant(entry) => {
+            let other_closing = CLOSING_REGIONS.get(&key).map(|closing| closing.clone());
+
+            // The entry holds the shard, which the other closer may need to put the region back
+            drop(entry);
+
+            // Someone else is already closing it, it only counts as closed once they're done
+            if let Some(other_closing) = other_closing {
+                other_closing.wait();
+            }
+
+            return Ok(None);
+        }
+        Entry::Occupied(entry) => {
+            CLOSING_REGIONS.insert(key, closing.clone());
+
+            entry.remove()
+        }
+    };
+
+    let deadline = Instant::now() + get_close_timeout(key.directory);
+
+    let result = match region.get_unique(deadline) {
+        Some(region) => closer(region).map(Some),
+        None => {
+            REGIONS.insert(key, region);
+
+            Err(Error::new(
+                std::io::ErrorKind::WouldBlock,
+                "Region is still in use",
+            ))
+        }
+    };
+
+    // Once the region is back in the map a later closer can mark it again, and that marker isn't ours to remove
+    CLOSING_REGIONS.remove_if(&key, |_, other_closing| {
+        Arc::ptr_eq(other_closing, &closing)
+    });
+
+    closing.finish();
+
+    result
+}
+
+/// Rewrites the region file at `region_coords` with its chunks packed right after the header, giving back the space that overwritten and deleted chunks left behind. The region is closed while this runs. Returns how many sectors were freed.
+pub fn compact_region(directory: &'static str, region_coords: RegionPos) -> Result<u64, Error> {
+    let key = RegionKey {
+        directory,
+        coords: region_coords,
+        format: RegionFormat::Anvil,
+    };
+
+    // Compacting takes over the open region, so open it first if nobody has yet
+    if open_region(key, false)?.is_none() {
+        return Ok(0);
     }
+
+    Ok(take_region_key(key, Region::compact)?.unwrap_or(0))
 }
 
-pub fn close_region(directory: &'static str, coords: IVec2) -> Result<(), Error> {
-    let key = RegionKey { directory, coords };
+/// Runs `compact_region` on every region file in `directory`. Returns how many sectors were freed.
+pub fn compact_regions(directory: &'static str) -> Result<u64, Error> {
+    let mut freed_sectors = 0;
+
+    for region_coords in get_region_files(directory, RegionFormat::Anvil)? {
+        freed_sectors += compact_region(directory, region_coords)?;
+    }
+
+    Ok(freed_sectors)
+}
+
+/// Reads the header of the region file at `region_coords`, or None if there is none.
+pub fn read_region_info(
+    directory: &'static str,
+    region_coords: RegionPos,
+) -> Result<Option<RegionInfo>, Error> {
+    let key = RegionKey {
+        directory,
+        coords: region_coords,
+        format: RegionFormat::Anvil,
+    };
+
+    match get_region(key, false)? {
+        None => Ok(None),
+        Some(region) => Ok(Some(region.get_info(region_coords)?)),
+    }
+}
 
-    let region_option = match REGIONS.get_mut(&key) {
-        None => return Ok(()),
-        Some(region_option) => region_option,
+/// Reads the location and timestamp tables of the region file at `region_coords`, or None if there is none.
+pub fn read_region_header(
+    directory: &'static str,
+    region_coords: RegionPos,
+) -> Result<Option<RegionHeader>, Error> {
+    let key = RegionKey {
+        directory,
+        coords: region_coords,
+        format: RegionFormat::Anvil,
     };
 
-    let mut region = region_option;
+    match get_region(key, false)? {
+        None => Ok(None),
+        Some(region) => Ok(Some(region.get_header()?)),
+    }
+}
 
-    region.close()?;
+pub fn flush_region(
+    directory: &'static str,
+    coords: RegionPos,
+    durability: Durability,
+) -> Result<(), Error> {
+    for format in [RegionFormat::Anvil, RegionFormat::McRegion] {
+        if let Some(region) = REGIONS.get(&RegionKey {
+            directory,
+            coords,
+            format,
+        }) {
+            region.flush(durability)?;
+        }
+    }
+
+    Ok(())
+}
 
-    REGIONS.remove(&key);
+/// Flushes every open region of `directory`.
+pub fn flush_regions(directory: &'static str, durability: Durability) -> Result<(), Error> {
+    for region in REGIONS
+        .iter()
+        .filter(|region| region.key().directory == directory)
+    {
+        region.flush(durability)?;
+    }
 
     Ok(())
 }
 
-pub fn read_chunk(directory: &'static str, coords: IVec2) -> Result<Option<Vec<u8>>, Error> {
+/// Flushes every open region.
+pub fn flush_all(durability: Durability) -> Result<(), Error> {
+    for region in REGIONS.iter() {
+        region.flush(durability)?;
+    }
+
+    Ok(())
+}
+
+/// Makes everything written to `world` so far durable, including region files that were just created. This is what a full save should end with.
+pub fn sync_world(world: &World) -> Result<(), Error> {
+    world.flush(Durability::SyncDirectory)
+}
+
+pub fn read_chunk(directory: &'static str, coords: ChunkPos) -> Result<Option<Vec<u8>>, Error> {
+    #[cfg(feature = "linear")]
+    if is_linear_directory(directory) {
+        return read_linear_chunk(directory, coords);
+    }
+
+    let key = RegionKey {
+        directory,
+        coords: coords.region(),
+        format: RegionFormat::Anvil,
+    };
+    let region = match get_region(key, false)? {
+        None => return Ok(None),
+        Some(region) => region,
+    };
+
+    region.read_chunk(coords)
+}
+
+#[cfg(feature = "nbt")]
+pub fn read_chunk_nbt(
+    directory: &'static str,
+    coords: ChunkPos,
+) -> Result<Option<ChunkNbt>, Error> {
+    Ok(read_chunk(directory, coords)?.map(ChunkNbt::new))
+}
+
+/// Reads a single tag out of a chunk, e.g. `&["Level", "Status"]`. Only the data up to that tag is decompressed and parsed.
+#[cfg(feature = "nbt")]
+pub fn query_chunk(
+    directory: &'static str,
+    coords: ChunkPos,
+    path: &[&str],
+) -> Result<Option<Tag<'static>>, Error> {
+    Ok(query_chunk_paths(directory, coords, &[path])?
+        .pop()
+        .flatten())
+}
+
+/// Like `query_chunk` but for several tags at once. Results are in the same order as `paths` and are all None if the chunk doesn't exist.
+#[cfg(feature = "nbt")]
+pub fn query_chunk_paths(
+    directory: &'static str,
+    coords: ChunkPos,
+    paths: &[&[&str]],
+) -> Result<Vec<Option<Tag<'static>>>, Error> {
+    #[cfg(feature = "linear")]
+    if is_linear_directory(directory) {
+        return match read_linear_chunk(directory, coords)? {
+            None => Ok(vec![None; paths.len()]),
+            Some(data) => nbt::query_nbt(data.as_slice(), paths),
+        };
+    }
+
+    let key = RegionKey {
+        directory,
+        coords: coords.region(),
+        format: RegionFormat::Anvil,
+    };
+    let region = match get_region(key, false)? {
+        None => return Ok(vec![None; paths.len()]),
+        Some(region) => region,
+    };
+
+    Ok(region
+        .query_chunk(coords, paths)?
+        .unwrap_or_else(|| vec![None; paths.len()]))
+}
+
+/// Reads many chunks at once. Chunks of the same region are read together, then everything is decompressed in parallel on the I/O pool. Results are in the same order as `coords`, and a chunk that can't be read, or a region that can't be opened, only fails the entries it belongs to.
+pub fn read_chunks(
+    directory: &'static str,
+    coords: &[ChunkPos],
+) -> Vec<Result<Option<Vec<u8>>, Error>> {
+    #[cfg(feature = "linear")]
+    if is_linear_directory(directory) {
+        return coords
+            .iter()
+            .map(|coords| read_linear_chunk(directory, *coords))
+            .collect();
+    }
+
+    let mut region_requests: HashMap<RegionKey, Vec<ChunkPos>, RandomState> = HashMap::default();
+
+    for chunk_coords in coords.iter() {
+        region_requests
+            .entry(RegionKey {
+                directory,
+                coords: chunk_coords.region(),
+                format: RegionFormat::Anvil,
+            })
+            .or_default()
+            .push(*chunk_coords);
+    }
+
+    let mut unique_coords = Vec::with_capacity(coords.len());
+    let mut compressed_chunks = Vec::with_capacity(coords.len());
+
+    for (key, mut region_coords) in region_requests {
+        // Each chunk is only read once however often it was asked for, and locked in location table order
+        glidesort::sort_by_key(&mut region_coords, |chunk_coords| chunk_coords.local());
+
+        region_coords.dedup();
+
+        match get_region(key, false) {
+            Ok(None) => compressed_chunks.extend(region_coords.iter().map(|_| Ok(None))),
+            Ok(Some(region)) => {
+                compressed_chunks.extend(region.read_compressed_chunks(&region_coords))
+            }
+            Err(error) => {
+                compressed_chunks.extend(region_coords.iter().map(|_| Err(copy_error(&error))))
+            }
+        }
+
+        unique_coords.extend(region_coords);
+    }
+
+    let mut chunks = decompress_all(compressed_chunks);
+
+    let mut remaining_reads = vec![0usize; unique_coords.len()];
+
+    let unique_indices: HashMap<ChunkPos, usize, RandomState> = unique_coords
+        .into_iter()
+        .enumerate()
+        .map(|(index, chunk_coords)| (chunk_coords, index))
+        .collect();
+
+    let indices: Vec<usize> = coords
+        .iter()
+        .map(|chunk_coords| {
+            let index = unique_indices[chunk_coords];
+
+            remaining_reads[index] += 1;
+
+            index
+        })
+        .collect();
+
+    // Only copy the data when the same chunk was asked for more than once
+    indices
+        .into_iter()
+        .map(|index| {
+            remaining_reads[index] -= 1;
+
+            match (remaining_reads[index], &mut chunks[index]) {
+                (0, chunk) => std::mem::replace(chunk, Ok(None)),
+                (_, Ok(chunk)) => Ok(chunk.clone()),
+                (_, Err(error)) => Err(copy_error(error)),
+            }
+        })
+        .collect()
+}
+
+/// Runs `read_chunk` on the I/O pool. Works with any executor and is safe to drop at any point.
+pub async fn read_chunk_async(
+    directory: &'static str,
+    coords: ChunkPos,
+) -> Result<Option<Vec<u8>>, Error> {
+    spawn_io(move || read_chunk(directory, coords)).await
+}
+
+pub fn read_chunk_timestamp(directory: &'static str, coords: ChunkPos) -> Result<u32, Error> {
+    #[cfg(feature = "linear")]
+    if is_linear_directory(directory) {
+        return match get_linear_region(directory, coords)? {
+            None => Ok(0),
+            Some(region) => Ok(region.get_chunk_timestamp(coords.local())),
+        };
+    }
+
     let key = RegionKey {
         directory,
-        coords: get_chunk_region_coords(coords),
+        coords: coords.region(),
+        format: RegionFormat::Anvil,
+    };
+    let region = match get_region(key, false)? {
+        None => return Ok(0),
+        Some(region) => region,
+    };
+
+    Ok(region.get_chunk_timestamp(coords))
+}
+
+/// Reads a chunk from a legacy McRegion (`.mcr`) file. McRegion files are always opened read only.
+pub fn read_legacy_chunk(
+    directory: &'static str,
+    coords: ChunkPos,
+) -> Result<Option<Vec<u8>>, Error> {
+    let key = RegionKey {
+        directory,
+        coords: coords.region(),
+        format: RegionFormat::McRegion,
+    };
+    let region = match get_region(key, false)? {
+        None => return Ok(None),
+        Some(region) => region,
     };
-    let region = get_region(key)?;
 
     region.read_chunk(coords)
 }
 
 pub fn write_chunk(
     directory: &'static str,
-    coords: IVec2,
+    coords: ChunkPos,
     timestamp: u32,
     data: &[u8],
     compression_type: u8,
     compression_level: i32,
 ) -> Result<(), Error> {
-    let compressed_data = match CompressionType::from_u8(compression_type) {
+    write_chunk_with_condition(
+        directory,
+        coords,
+        timestamp,
+        WriteCondition::Newer,
+        data,
+        compression_type,
+        compression_level,
+    )?;
+
+    Ok(())
+}
+
+/// Runs `write_chunk` on the I/O pool. Works with any executor and is safe to drop at any point, the chunk is either fully written or not written at all.
+pub async fn write_chunk_async(
+    directory: &'static str,
+    coords: ChunkPos,
+    timestamp: u32,
+    data: Vec<u8>,
+    compression_type: u8,
+    compression_level: i32,
+) -> Result<(), Error> {
+    spawn_io(move || {
+        write_chunk(
+            directory,
+            coords,
+            timestamp,
+            &data,
+            compression_type,
+            compression_level,
+        )
+    })
+    .await
+}
+
+#[cfg(feature = "nbt")]
+pub fn write_chunk_nbt(
+    directory: &'static str,
+    coords: ChunkPos,
+    timestamp: u32,
+    root: &Compound,
+    compression_type: u8,
+    compression_level: i32,
+) -> Result<(), Error> {
+    write_chunk(
+        directory,
+        coords,
+        timestamp,
+        &write_nbt("", root)?,
+        compression_type,
+        compression_level,
+    )
+}
+
+/// Writes the chunk only if `timestamp` is newer than the one stored for it. Returns whether the chunk was written.
+pub fn write_chunk_if_newer(
+    directory: &'static str,
+    coords: ChunkPos,
+    timestamp: u32,
+    data: &[u8],
+    compression_type: u8,
+    compression_level: i32,
+) -> Result<bool, Error> {
+    write_chunk_with_condition(
+        directory,
+        coords,
+        timestamp,
+        WriteCondition::Newer,
+        data,
+        compression_type,
+        compression_level,
+    )
+}
+
+/// Writes the chunk only if its stored timestamp still equals `expected_timestamp`. Returns whether the chunk was written.
+pub fn write_chunk_if_unchanged(
+    directory: &'static str,
+    coords: ChunkPos,
+    expected_timestamp: u32,
+    timestamp: u32,
+    data: &[u8],
+    compression_type: u8,
+    compression_level: i32,
+) -> Result<bool, Error> {
+    write_chunk_with_condition(
+        directory,
+        coords,
+        timestamp,
+        WriteCondition::Unchanged(expected_timestamp),
+        data,
+        compression_type,
+        compression_level,
+    )
+}
+
+/// Removes the chunk, along with its oversized file if it has one. Returns whether there was a chunk to remove.
+pub fn delete_chunk(directory: &'static str, coords: ChunkPos) -> Result<bool, Error> {
+    #[cfg(feature = "linear")]
+    if is_linear_directory(directory) {
+        return Err(Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "Linear regions are read only",
+        ));
+    }
+
+    let key = RegionKey {
+        directory,
+        coords: coords.region(),
+        format: RegionFormat::Anvil,
+    };
+    let region = match get_region(key, false)? {
+        None => return Ok(false),
+        Some(region) => region,
+    };
+
+    region.delete_chunk(coords)
+}
+
+/// Decompresses every chunk of the region at `region_coords` and compresses it again with `compression_type`, keeping its timestamp. Chunks written to while this runs are left as they are. Returns how many chunks were rewritten.
+pub fn recompress_region(
+    directory: &'static str,
+    region_coords: RegionPos,
+    compression_type: u8,
+    compression_level: i32,
+) -> Result<usize, Error> {
+    let mut recompressed_chunks = 0;
+
+    for coords in region_coords.chunks() {
+        let timestamp = read_chunk_timestamp(directory, coords)?;
+
+        let data = match read_chunk(directory, coords)? {
+            None => continue,
+            Some(data) => data,
+        };
+
+        if write_chunk_with_condition(
+            directory,
+            coords,
+            timestamp,
+            WriteCondition::Unchanged(timestamp),
+            &data,
+            compression_type,
+            compression_level,
+        )? {
+            recompressed_chunks += 1;
+        }
+    }
+
+    Ok(recompressed_chunks)
+}
+
+/// Runs `recompress_region` on every region file in `directory`. Returns how many chunks were rewritten.
+pub fn recompress_regions(
+    directory: &'static str,
+    compression_type: u8,
+    compression_level: i32,
+) -> Result<usize, Error> {
+    let mut recompressed_chunks = 0;
+
+    for region_coords in get_region_files(directory, RegionFormat::Anvil)? {
+        recompressed_chunks += recompress_region(
+            directory,
+            region_coords,
+            compression_type,
+            compression_level,
+        )?;
+    }
+
+    Ok(recompressed_chunks)
+}
+
+/// Copies every chunk of the McRegion file at `region_coords` into the Anvil file of the same region, keeping their timestamps. Chunks that already exist in the Anvil file are left alone, whatever their timestamp, so an interrupted upgrade can simply be run again. The chunk NBT is copied as is, in the pre-Anvil layout: the game only converts chunks while it converts `.mcr` files itself, so the Anvil file needs an NBT converter run over it before the game can load these chunks. Returns how many chunks were copied.
+pub fn upgrade_legacy_region(
+    directory: &'static str,
+    region_coords: RegionPos,
+    compression_type: u8,
+    compression_level: i32,
+) -> Result<usize, Error> {
+    let key = RegionKey {
+        directory,
+        coords: region_coords,
+        format: RegionFormat::McRegion,
+    };
+
+    let mut upgraded_chunks = 0;
+
+    for coords in region_coords.chunks() {
+        // The legacy region isn't held while writing, opening the Anvil region may need the same map shard
+        let (timestamp, data) = {
+            let region = match get_region(key, false)? {
+                None => return Ok(upgraded_chunks),
+                Some(region) => region,
+            };
+
+            match region.read_chunk(coords)? {
+                None => continue,
+                Some(data) => (region.get_chunk_timestamp(coords), data),
+            }
+        };
+
+        if write_chunk_with_condition(
+            directory,
+            coords,
+            timestamp,
+            WriteCondition::Missing,
+            &data,
+            compression_type,
+            compression_level,
+        )? {
+            upgraded_chunks += 1;
+        }
+    }
+
+    Ok(upgraded_chunks)
+}
+
+/// Serves reads for `directory` from Linear (`.linear`) region files instead of Anvil ones. Linear regions are read only. Each one is decompressed whole on its first read and stays in memory until `close_region` or `close_regions` drops it, or the backend is turned off.
+#[cfg(feature = "linear")]
+pub fn set_linear_backend(directory: &'static str, enabled: bool) {
+    match enabled {
+        true => LINEAR_DIRECTORIES.insert(directory),
+        false => LINEAR_DIRECTORIES.remove(directory).is_some(),
+    };
+
+    LINEAR_REGIONS.retain(|key, _| key.directory != directory);
+}
+
+#[cfg(feature = "linear")]
+fn is_linear_directory(directory: &'static str) -> bool {
+    LINEAR_DIRECTORIES.contains(directory)
+}
+
+#[cfg(feature = "linear")]
+fn get_linear_region(
+    directory: &'static str,
+    coords: ChunkPos,
+) -> Result<Option<Ref<'static, RegionKey, LinearRegion, RandomState>>, Error> {
+    let key = RegionKey {
+        directory,
+        coords: coords.region(),
+        format: RegionFormat::Linear,
+    };
+
+    if let Some(region) = LINEAR_REGIONS.get(&key) {
+        return Ok(Some(region));
+    }
+
+    match LINEAR_REGIONS.entry(key) {
+        Entry::Occupied(entry) => Ok(Some(entry.into_ref().downgrade())),
+        Entry::Vacant(entry) => {
+            match LinearRegion::open_file(
+                get_storage(directory),
+                Path::new(&get_linear_file_path(directory, key.coords)),
+            )? {
+                None => Ok(None),
+                Some(region) => Ok(Some(entry.insert(region).downgrade())),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "linear")]
+fn read_linear_chunk(directory: &'static str, coords: ChunkPos) -> Result<Option<Vec<u8>>, Error> {
+    match get_linear_region(directory, coords)? {
+        None => Ok(None),
+        Some(region) => Ok(region.read_chunk(coords.local())),
+    }
+}
+
+#[cfg(feature = "linear")]
+fn get_linear_file_path(directory: &str, region_coords: RegionPos) -> String {
+    format!(
+        "{0}/r.{1}.{2}.{3}",
+        directory,
+        region_coords.x,
+        region_coords.z,
+        RegionFormat::Linear.extension()
+    )
+}
+
+/// Writes every chunk of the region at `region_coords` into a Linear region file in `output_directory`, replacing any that is already there. Returns how many chunks were written.
+#[cfg(feature = "linear")]
+pub fn export_linear_region(
+    directory: &'static str,
+    region_coords: RegionPos,
+    output_directory: &str,
+    compression_level: i32,
+) -> Result<usize, Error> {
+    let mut chunks = Vec::with_capacity(1024);
+
+    // Same order as the location table
+    for coords in region_coords.chunks() {
+        chunks.push(match read_chunk(directory, coords)? {
+            None => None,
+            Some(data) => Some((read_chunk_timestamp(directory, coords)?, data)),
+        });
+    }
+
+    write_linear_file(
+        get_storage(output_directory),
+        Path::new(&get_linear_file_path(output_directory, region_coords)),
+        &chunks,
+        compression_level,
+    )?;
+
+    Ok(chunks.iter().flatten().count())
+}
+
+/// Writes every chunk of the Linear region file at `region_coords` in `input_directory` into `directory`, skipping chunks that aren't newer than the ones already there. Returns how many chunks were written.
+#[cfg(feature = "linear")]
+pub fn import_linear_region(
+    directory: &'static str,
+    region_coords: RegionPos,
+    input_directory: &str,
+    compression_type: u8,
+    compression_level: i32,
+) -> Result<usize, Error> {
+    let region = match LinearRegion::open_file(
+        get_storage(input_directory),
+        Path::new(&get_linear_file_path(input_directory, region_coords)),
+    )? {
+        None => return Ok(0),
+        Some(region) => region,
+    };
+
+    let mut imported_chunks = 0;
+
+    for chunk_region_coords in LocalPos::all() {
+        let data = match region.read_chunk(chunk_region_coords) {
+            None => continue,
+            Some(data) => data,
+        };
+
+        if write_chunk_with_condition(
+            directory,
+            region_coords.chunk(chunk_region_coords),
+            region.get_chunk_timestamp(chunk_region_coords),
+            WriteCondition::Newer,
+            &data,
+            compression_type,
+            compression_level,
+        )? {
+            imported_chunks += 1;
+        }
+    }
+
+    Ok(imported_chunks)
+}
+
+fn write_chunk_with_condition(
+    directory: &'static str,
+    coords: ChunkPos,
+    timestamp: u32,
+    condition: WriteCondition,
+    data: &[u8],
+    compression_type: u8,
+    compression_level: i32,
+) -> Result<bool, Error> {
+    #[cfg(feature = "linear")]
+    if is_linear_directory(directory) {
+        return Err(Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "Linear regions are read only",
+        ));
+    }
+
+    let key = RegionKey {
+        directory,
+        coords: coords.region(),
+        format: RegionFormat::Anvil,
+    };
+
+    // A missing region counts as a region full of empty chunks, so it is only created if the write would win against one
+    let create = condition.allows(None, timestamp);
+
+    let region = match get_region(key, create)? {
+        Some(region) => region,
+        None if !create => return Ok(false),
+        None => {
+            return Err(Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "Region is read only",
+            ));
+        }
+    };
+
+    // Don't bother compressing if we already know the write is going to lose
+    if !region.can_write_chunk(coords, timestamp, &condition)? {
+        return Ok(false);
+    }
+
+    let compression_type = match CompressionType::from_u8(compression_type) {
         None => {
             return Err(Error::new(
                 std::io::ErrorKind::Other,
@@ -87,8 +1162,9 @@ pub fn write_chunk(
             ));
         }
         Some(compression_type) => compression_type,
-    }
-    .compress(
+    };
+
+    let compressed_data = compression_type.compress(
         data,
         match CompressionLvl::new(compression_level) {
             Ok(level) => level,
@@ -101,15 +1177,17 @@ pub fn write_chunk(
         },
     )?;
 
-    let alignment_data = get_alignment_vector(compressed_data.len(), 4096);
-    let key = RegionKey {
-        directory,
-        coords: get_chunk_region_coords(coords),
-    };
-
-    let region = get_region(key)?;
+    let (compression_byte, payload) =
+        region.encrypt_chunk_data(coords, &compression_type, compressed_data)?;
 
-    region.write_chunk(coords, timestamp, &alignment_data, &compressed_data)?;
+    let alignment_data = get_alignment_vector(payload.len() + 5, 4096);
 
-    Ok(())
+    region.write_chunk(
+        coords,
+        timestamp,
+        &condition,
+        compression_byte,
+        &payload,
+        &alignment_data,
+    )
 }
@@ -0,0 +1,214 @@
+use std::io::{Error, Read};
+use std::path::Path;
+
+use crate::access_mode::AccessMode;
+use crate::position::LocalPos;
+use crate::storage::Storage;
+
+const LINEAR_SIGNATURE: u64 = 0xc3ff13183cca9d9a;
+const LINEAR_VERSION: u8 = 1;
+const LINEAR_HEADER_SIZE: usize = 32;
+const LINEAR_FOOTER_SIZE: usize = 8;
+
+// Every chunk has a size and a timestamp at the start of the decompressed region
+const LINEAR_CHUNK_HEADER_SIZE: usize = 8;
+
+// Bigger chunks are rejected on export, so a region never decompresses to more than the header and 1024 of these
+const MAX_LINEAR_CHUNK_SIZE: usize = 16 << 20;
+
+#[derive(Copy, Clone, Default)]
+struct LinearChunk {
+    offset: usize,
+    size: usize,
+    timestamp: u32,
+}
+
+// A whole Linear region kept decompressed in memory, the format has no way to read a single chunk
+pub(crate) struct LinearRegion {
+    data: Vec<u8>,
+    chunks: Box<[LinearChunk; 1024]>,
+}
+
+impl LinearRegion {
+    pub(crate) fn open_file(
+        storage: &dyn Storage,
+        path: &Path,
+    ) -> Result<Option<LinearRegion>, Error> {
+        let file = match storage.open_file(0, path, false, AccessMode::ReadOnly, false)? {
+            None => return Ok(None),
+            Some(file) => file,
+        };
+
+        let file_data = file
+            .read_file(0..file.get_file_size()? as usize)
+            .map(|file_data| file_data.into_owned());
+
+        file.close_file()?;
+
+        let file_data = file_data?;
+
+        if file_data.len() < LINEAR_HEADER_SIZE + LINEAR_FOOTER_SIZE
+            || u64::from_be_bytes(file_data[0..8].try_into().unwrap()) != LINEAR_SIGNATURE
+            || u64::from_be_bytes(file_data[file_data.len() - 8..].try_into().unwrap())
+                != LINEAR_SIGNATURE
+        {
+            return Err(invalid_linear("Invalid Linear region signature"));
+        }
+
+        if file_data[8] != LINEAR_VERSION {
+            return Err(invalid_linear("Unsupported Linear region version"));
+        }
+
+        let compressed_length = u32::from_be_bytes(file_data[20..24].try_into().unwrap()) as usize;
+
+        if LINEAR_HEADER_SIZE + compressed_length + LINEAR_FOOTER_SIZE != file_data.len() {
+            return Err(invalid_linear("Invalid Linear region length"));
+        }
+
+        let mut decoder = zstd::stream::read::Decoder::with_buffer(
+            &file_data[LINEAR_HEADER_SIZE..LINEAR_HEADER_SIZE + compressed_length],
+        )?;
+
+        let mut data = vec![0; LINEAR_CHUNK_HEADER_SIZE * 1024];
+
+        decoder
+            .read_exact(&mut data)
+            .map_err(|error| match error.kind() {
+                std::io::ErrorKind::UnexpectedEof => invalid_linear("Invalid Linear region length"),
+                _ => error,
+            })?;
+
+        let mut chunks = Box::new([LinearChunk::default(); 1024]);
+
+        let mut offset = LINEAR_CHUNK_HEADER_SIZE * 1024;
+
+        for (index, chunk) in chunks.iter_mut().enumerate() {
+            let header =
+                &data[index * LINEAR_CHUNK_HEADER_SIZE..(index + 1) * LINEAR_CHUNK_HEADER_SIZE];
+
+            let size = u32::from_be_bytes(header[0..4].try_into().unwrap()) as usize;
+
+            if size > MAX_LINEAR_CHUNK_SIZE {
+                return Err(invalid_linear("Invalid Linear chunk length"));
+            }
+
+            *chunk = LinearChunk {
+                offset,
+                size,
+                timestamp: u32::from_be_bytes(header[4..8].try_into().unwrap()),
+            };
+
+            offset += size;
+        }
+
+        // Only decompresses as much as the header asks for, a zstd bomb stops right after it
+        decoder
+            .take((offset - data.len()) as u64 + 1)
+            .read_to_end(&mut data)?;
+
+        match data.len().cmp(&offset) {
+            std::cmp::Ordering::Less => Err(invalid_linear("Invalid Linear chunk length")),
+            std::cmp::Ordering::Greater => Err(invalid_linear(
+                "Linear region has data after its last chunk",
+            )),
+            std::cmp::Ordering::Equal => Ok(Some(LinearRegion { data, chunks })),
+        }
+    }
+
+    pub(crate) fn read_chunk(&self, chunk_region_coords: LocalPos) -> Option<Vec<u8>> {
+        let chunk = self.get_chunk(chunk_region_coords);
+
+        match chunk.size {
+            0 => None,
+            _ => Some(self.data[chunk.offset..chunk.offset + chunk.size].to_vec()),
+        }
+    }
+
+    pub(crate) fn get_chunk_timestamp(&self, chunk_region_coords: LocalPos) -> u32 {
+        self.get_chunk(chunk_region_coords).timestamp
+    }
+
+    fn get_chunk(&self, chunk_region_coords: LocalPos) -> &LinearChunk {
+        &self.chunks[chunk_region_coords.index()]
+    }
+}
+
+// Chunks are indexed like the Anvil location table and hold the decompressed chunk data
+pub(crate) fn write_linear_file(
+    storage: &dyn Storage,
+    path: &Path,
+    chunks: &[Option<(u32, Vec<u8>)>],
+    compression_level: i32,
+) -> Result<(), Error> {
+    let mut data = Vec::with_capacity(LINEAR_CHUNK_HEADER_SIZE * 1024);
+
+    let mut chunk_count: u16 = 0;
+
+    let mut newest_timestamp = 0;
+
+    for chunk in chunks.iter() {
+        match chunk {
+            None => data.extend_from_slice(&[0; LINEAR_CHUNK_HEADER_SIZE]),
+            Some((timestamp, chunk_data)) => {
+                if chunk_data.len() > MAX_LINEAR_CHUNK_SIZE {
+                    return Err(Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        "Chunk is too big for a Linear region",
+                    ));
+                }
+
+                data.extend_from_slice(&(chunk_data.len() as u32).to_be_bytes());
+                data.extend_from_slice(&timestamp.to_be_bytes());
+
+                chunk_count += 1;
+
+                newest_timestamp = newest_timestamp.max(*timestamp);
+            }
+        }
+    }
+
+    for (_, chunk_data) in chunks.iter().flatten() {
+        data.extend_from_slice(chunk_data);
+    }
+
+    let compressed_data = zstd::stream::encode_all(data.as_slice(), compression_level)?;
+
+    let compressed_length = match u32::try_from(compressed_data.len()) {
+        Ok(compressed_length) => compressed_length,
+        Err(_) => {
+            return Err(Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Region is too big for a Linear region",
+            ));
+        }
+    };
+
+    let mut file_data =
+        Vec::with_capacity(LINEAR_HEADER_SIZE + compressed_data.len() + LINEAR_FOOTER_SIZE);
+
+    file_data.extend_from_slice(&LINEAR_SIGNATURE.to_be_bytes());
+    file_data.push(LINEAR_VERSION);
+    file_data.extend_from_slice(&(newest_timestamp as i64).to_be_bytes());
+    file_data.push(compression_level.clamp(i8::MIN as i32, i8::MAX as i32) as u8);
+    file_data.extend_from_slice(&chunk_count.to_be_bytes());
+    file_data.extend_from_slice(&compressed_length.to_be_bytes());
+    file_data.extend_from_slice(&[0; 8]);
+    file_data.extend_from_slice(&compressed_data);
+    file_data.extend_from_slice(&LINEAR_SIGNATURE.to_be_bytes());
+
+    // Written next to the old file first so an interrupted export never leaves a broken region behind
+    let temporary_path = path.with_extension("linear.tmp");
+
+    storage.write_whole_file(&temporary_path, &file_data)?;
+
+    storage.rename_file(&temporary_path, path)?;
+
+    match path.parent() {
+        Some(parent) => storage.sync_directory(parent),
+        None => Ok(()),
+    }
+}
+
+fn invalid_linear(message: &str) -> Error {
+    Error::new(std::io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,178 @@
+use std::io::{Error, Read};
+
+use xxhash_rust::xxh32::xxh32;
+
+// The framing of lz4-java's LZ4BlockOutputStream, which is what vanilla writes for LZ4 chunks
+const MAGIC: &[u8; 8] = b"LZ4Block";
+const HEADER_LENGTH: usize = MAGIC.len() + 13;
+
+const METHOD_RAW: u8 = 0x10;
+const METHOD_LZ4: u8 = 0x20;
+
+// Vanilla uses the default 64KiB blocks, the level in the token is log2 of the block size minus 10
+const BLOCK_SIZE: usize = 1 << 16;
+const BLOCK_SIZE_LEVEL: u8 = 6;
+
+const CHECKSUM_SEED: u32 = 0x9747b28c;
+
+// lz4-java only keeps the lower 28 bits of the checksum
+fn get_checksum(data: &[u8]) -> u32 {
+    xxh32(data, CHECKSUM_SEED) & 0x0FFFFFFF
+}
+
+fn write_block(output: &mut Vec<u8>, method: u8, block: &[u8], original_length: usize) {
+    output.extend_from_slice(MAGIC);
+    output.push(method | BLOCK_SIZE_LEVEL);
+    output.extend_from_slice(&(block.len() as u32).to_le_bytes());
+    output.extend_from_slice(&(original_length as u32).to_le_bytes());
+}
+
+pub(crate) fn compress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len() + HEADER_LENGTH * 2);
+
+    for block in data.chunks(BLOCK_SIZE) {
+        let compressed_block = lz4_flex::block::compress(block);
+
+        // Same as lz4-java, blocks that don't shrink are stored as is
+        let (method, stored_block) = if compressed_block.len() < block.len() {
+            (METHOD_LZ4, compressed_block.as_slice())
+        } else {
+            (METHOD_RAW, block)
+        };
+
+        write_block(&mut output, method, stored_block, block.len());
+        output.extend_from_slice(&get_checksum(block).to_le_bytes());
+        output.extend_from_slice(stored_block);
+    }
+
+    // An empty block marks the end of the stream
+    write_block(&mut output, METHOD_RAW, &[], 0);
+    output.extend_from_slice(&0u32.to_le_bytes());
+
+    output
+}
+
+pub(crate) fn decompress(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut reader = Lz4BlockReader::new(data);
+    let mut output = Vec::new();
+
+    reader.read_to_end(&mut output)?;
+
+    Ok(output)
+}
+
+fn get_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+fn corrupt(message: &str) -> Error {
+    Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+// Decompresses one block at a time, so readers that stop early never decompress the rest
+pub(crate) struct Lz4BlockReader<'a> {
+    data: &'a [u8],
+    block: Vec<u8>,
+    position: usize,
+    finished: bool,
+}
+
+impl<'a> Lz4BlockReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Lz4BlockReader<'a> {
+        Lz4BlockReader {
+            data,
+            block: Vec::new(),
+            position: 0,
+            finished: false,
+        }
+    }
+
+    fn read_block(&mut self) -> Result<(), Error> {
+        // A stream that ends without the empty block is fine as long as it ends between blocks
+        if self.data.is_empty() {
+            self.finished = true;
+            return Ok(());
+        }
+
+        if self.data.len() < HEADER_LENGTH || &self.data[..MAGIC.len()] != MAGIC {
+            return Err(corrupt("Invalid LZ4 block header"));
+        }
+
+        let token = self.data[MAGIC.len()];
+        let compressed_length = get_u32(self.data, MAGIC.len() + 1) as usize;
+        let original_length = get_u32(self.data, MAGIC.len() + 5) as usize;
+        let checksum = get_u32(self.data, MAGIC.len() + 9);
+
+        let max_block_size = 1usize << (10 + (token & 0x0F));
+
+        if original_length > max_block_size
+            || (token & 0xF0 == METHOD_RAW && compressed_length != original_length)
+        {
+            return Err(corrupt("Invalid LZ4 block lengths"));
+        }
+
+        // The empty block at the end has a zero checksum instead of the checksum of nothing
+        if original_length == 0 && compressed_length == 0 {
+            if checksum != 0 {
+                return Err(corrupt("LZ4 block checksum mismatch"));
+            }
+
+            self.finished = true;
+            return Ok(());
+        }
+
+        let block = match self
+            .data
+            .get(HEADER_LENGTH..HEADER_LENGTH + compressed_length)
+        {
+            Some(block) => block,
+            None => return Err(corrupt("LZ4 block is truncated")),
+        };
+
+        self.block = match token & 0xF0 {
+            METHOD_RAW => block.to_vec(),
+            METHOD_LZ4 => match lz4_flex::block::decompress(block, original_length) {
+                Ok(block) => block,
+                Err(error) => return Err(corrupt(&error.to_string())),
+            },
+            _ => return Err(corrupt("Invalid LZ4 block compression method")),
+        };
+
+        if self.block.len() != original_length {
+            return Err(corrupt("LZ4 block has the wrong length"));
+        }
+
+        if get_checksum(&self.block) != checksum {
+            return Err(corrupt("LZ4 block checksum mismatch"));
+        }
+
+        self.data = &self.data[HEADER_LENGTH + compressed_length..];
+        self.position = 0;
+
+        Ok(())
+    }
+}
+
+impl Read for Lz4BlockReader<'_> {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        while self.position == self.block.len() {
+            if self.finished || buffer.is_empty() {
+                return Ok(0);
+            }
+
+            self.read_block()?;
+        }
+
+        let length = buffer.len().min(self.block.len() - self.position);
+
+        buffer[..length].copy_from_slice(&self.block[self.position..self.position + length]);
+        self.position += length;
+
+        Ok(length)
+    }
+}
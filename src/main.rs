@@ -0,0 +1,366 @@
+use std::env;
+use std::io::{Error, Read, Write};
+use std::process::ExitCode;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use p2vec::{AccessMode, ChunkPos, RegionPos};
+
+const USAGE: &str = "Usage: p2vec [--unlocked] <command> <directory> [arguments]
+
+Commands:
+    info <directory> <region x> <region z>          Summary of the region header
+    ls <directory> <region x> <region z>            Chunks in the region
+    map <directory> <region x> <region z> [pgm]     Which chunk owns each sector, as text or a PGM image
+    cat <directory> <chunk x> <chunk z>             Write the decompressed chunk to stdout
+    put <directory> <chunk x> <chunk z> [compression type] [compression level]
+                                                    Write the chunk from stdin
+    rm <directory> <chunk x> <chunk z>              Remove the chunk
+    check <directory> [<region x> <region z>]       Read and decompress every chunk
+    compact <directory> [<region x> <region z>]     Give back unused sectors
+    recompress <directory> <compression type> [compression level] [<region x> <region z>]
+                                                    Compress every chunk again
+
+info, ls, map, cat and check only read the region files and lock them shared, so they fail while something has them open for writing. With --unlocked they don't lock them at all, for looking at the world of a running server.
+
+Coordinates are region or chunk coordinates, not block coordinates. Compression types are 1 for gzip, 2 for zlib, 3 for uncompressed and 4 for LZ4.";
+
+const READ_ONLY_COMMANDS: [&str; 5] = ["info", "ls", "map", "cat", "check"];
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    match run(&args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(error) if error.kind() == std::io::ErrorKind::InvalidInput => {
+            eprintln!("p2vec: {}\n\n{}", error, USAGE);
+
+            ExitCode::from(2)
+        }
+        Err(error) => {
+            eprintln!("p2vec: {}", error);
+
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// Returns whether the command succeeded, commands like check fail without an error
+fn run(args: &[String]) -> Result<bool, Error> {
+    let (command, args) = match args.split_first() {
+        None => return Err(invalid_input("Missing command")),
+        Some((command, args)) => (command.as_str(), args),
+    };
+
+    let (unlocked, command, args) = match (command, args.split_first()) {
+        ("--unlocked", None) => return Err(invalid_input("Missing command")),
+        ("--unlocked", Some((command, args))) => (true, command.as_str(), args),
+        _ => (false, command, args),
+    };
+
+    if command == "help" || command == "--help" || command == "-h" {
+        println!("{}", USAGE);
+
+        return Ok(true);
+    }
+
+    let (directory, args) = match args.split_first() {
+        None => return Err(invalid_input("Missing directory")),
+        // The library keeps directories around for as long as regions are open
+        Some((directory, args)) => (
+            &*Box::leak(directory.trim_end_matches('/').to_owned().into_boxed_str()),
+            args,
+        ),
+    };
+
+    // Opening regions for writing would lock them exclusively, create sidecar files and grow the region files
+    if READ_ONLY_COMMANDS.contains(&command) {
+        p2vec::set_access_mode(
+            directory,
+            match unlocked {
+                true => AccessMode::ReadOnlyUnlocked,
+                false => AccessMode::ReadOnly,
+            },
+        )?;
+    } else if unlocked {
+        return Err(invalid_input(&format!(
+            "{} writes to the region files, it can't be run with --unlocked",
+            command
+        )));
+    }
+
+    let result = match command {
+        "info" => info(directory, get_region_pos(args, 0)?),
+        "ls" => ls(directory, get_region_pos(args, 0)?),
+        "map" => map(
+            directory,
+            get_region_pos(args, 0)?,
+            args.get(2).map(String::as_str) == Some("pgm"),
+        ),
+        "cat" => cat(directory, get_chunk_pos(args, 0)?),
+        "put" => put(
+            directory,
+            get_chunk_pos(args, 0)?,
+            get_optional_arg(args, 2, 2)?,
+            get_optional_arg(args, 3, 6)?,
+        ),
+        "rm" => rm(directory, get_chunk_pos(args, 0)?),
+        "check" => check(directory, get_optional_region_pos(args, 0)?),
+        "compact" => compact(directory, get_optional_region_pos(args, 0)?),
+        "recompress" => recompress(
+            directory,
+            get_arg(args, 0)?,
+            get_optional_arg(args, 1, 6)?,
+            get_optional_region_pos(args, 2)?,
+        ),
+        _ => return Err(invalid_input(&format!("Unknown command {}", command))),
+    };
+
+    p2vec::close_regions(directory)?;
+
+    result
+}
+
+fn info(directory: &'static str, region_coords: RegionPos) -> Result<bool, Error> {
+    let info = match p2vec::read_region_info(directory, region_coords)? {
+        None => return Err(missing_region(region_coords)),
+        Some(info) => info,
+    };
+
+    let mut compression_types = [0usize; 5];
+
+    for chunk in info.chunks.iter() {
+        compression_types[(chunk.compression_type as usize).min(4)] += 1;
+    }
+
+    println!("File size:       {} bytes", info.file_size);
+    println!("Chunks:          {}", info.chunks.len());
+    println!("Used sectors:    {}", info.get_used_sectors());
+    println!("Free sectors:    {}", info.get_free_sectors());
+    println!("Gzip:            {}", compression_types[1]);
+    println!("Zlib:            {}", compression_types[2]);
+    println!("Uncompressed:    {}", compression_types[3]);
+    println!("LZ4:             {}", compression_types[4]);
+    println!(
+        "Oversized:       {}",
+        info.chunks.iter().filter(|chunk| chunk.oversized).count()
+    );
+    println!(
+        "Encrypted:       {}",
+        info.chunks.iter().filter(|chunk| chunk.encrypted).count()
+    );
+
+    Ok(true)
+}
+
+fn ls(directory: &'static str, region_coords: RegionPos) -> Result<bool, Error> {
+    let info = match p2vec::read_region_info(directory, region_coords)? {
+        None => return Err(missing_region(region_coords)),
+        Some(info) => info,
+    };
+
+    let mut stdout = std::io::stdout().lock();
+
+    for chunk in info.chunks.iter() {
+        writeln!(
+            stdout,
+            "{}\t{}\t{}\t{}\t{}\t{}{}{}",
+            chunk.coords.x,
+            chunk.coords.z,
+            chunk.timestamp,
+            chunk.sector_offset,
+            chunk.sector_count,
+            chunk.compression_type,
+            if chunk.oversized { "\toversized" } else { "" },
+            if chunk.encrypted { "\tencrypted" } else { "" },
+        )?;
+    }
+
+    Ok(true)
+}
+
+fn map(directory: &'static str, region_coords: RegionPos, pgm: bool) -> Result<bool, Error> {
+    let sector_map = match p2vec::read_region_info(directory, region_coords)? {
+        None => return Err(missing_region(region_coords)),
+        Some(info) => info.get_sector_map(),
+    };
+
+    let mut stdout = std::io::stdout().lock();
+
+    match pgm {
+        true => sector_map.write_pgm(&mut stdout)?,
+        false => stdout.write_all(sector_map.to_text().as_bytes())?,
+    }
+
+    stdout.flush()?;
+
+    Ok(true)
+}
+
+fn cat(directory: &'static str, coords: ChunkPos) -> Result<bool, Error> {
+    match p2vec::read_chunk(directory, coords)? {
+        None => Err(Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("Chunk {} {} doesn't exist", coords.x, coords.z),
+        )),
+        Some(data) => {
+            let mut stdout = std::io::stdout().lock();
+
+            stdout.write_all(&data)?;
+            stdout.flush()?;
+
+            Ok(true)
+        }
+    }
+}
+
+fn put(
+    directory: &'static str,
+    coords: ChunkPos,
+    compression_type: u8,
+    compression_level: i32,
+) -> Result<bool, Error> {
+    let mut data = Vec::new();
+
+    std::io::stdin().lock().read_to_end(&mut data)?;
+
+    let timestamp = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as u32,
+        Err(_) => 0,
+    };
+
+    // The chunk is replaced even if its stored timestamp is in the future
+    let written = p2vec::write_chunk_if_unchanged(
+        directory,
+        coords,
+        p2vec::read_chunk_timestamp(directory, coords)?,
+        timestamp,
+        &data,
+        compression_type,
+        compression_level,
+    )?;
+
+    if !written {
+        eprintln!("p2vec: Chunk was changed while writing it");
+    }
+
+    Ok(written)
+}
+
+fn rm(directory: &'static str, coords: ChunkPos) -> Result<bool, Error> {
+    if !p2vec::delete_chunk(directory, coords)? {
+        eprintln!("p2vec: Chunk {} {} doesn't exist", coords.x, coords.z);
+    }
+
+    Ok(true)
+}
+
+fn check(directory: &'static str, region_coords: Option<RegionPos>) -> Result<bool, Error> {
+    let report = match region_coords {
+        None => p2vec::verify_regions(directory)?,
+        Some(region_coords) => p2vec::verify_region(directory, region_coords)?,
+    };
+
+    for corrupt_chunk in report.corrupt_chunks.iter() {
+        println!(
+            "{}\t{}\t{}",
+            corrupt_chunk.coords.x, corrupt_chunk.coords.z, corrupt_chunk.error
+        );
+    }
+
+    eprintln!(
+        "{} verified, {} without checksum, {} corrupt",
+        report.verified_chunks,
+        report.unchecked_chunks,
+        report.corrupt_chunks.len()
+    );
+
+    Ok(report.is_ok())
+}
+
+fn compact(directory: &'static str, region_coords: Option<RegionPos>) -> Result<bool, Error> {
+    let freed_sectors = match region_coords {
+        None => p2vec::compact_regions(directory)?,
+        Some(region_coords) => p2vec::compact_region(directory, region_coords)?,
+    };
+
+    eprintln!("Freed {} sectors", freed_sectors);
+
+    Ok(true)
+}
+
+fn recompress(
+    directory: &'static str,
+    compression_type: u8,
+    compression_level: i32,
+    region_coords: Option<RegionPos>,
+) -> Result<bool, Error> {
+    let recompressed_chunks = match region_coords {
+        None => p2vec::recompress_regions(directory, compression_type, compression_level)?,
+        Some(region_coords) => p2vec::recompress_region(
+            directory,
+            region_coords,
+            compression_type,
+            compression_level,
+        )?,
+    };
+
+    eprintln!("Recompressed {} chunks", recompressed_chunks);
+
+    Ok(true)
+}
+
+fn get_arg<T: std::str::FromStr>(args: &[String], index: usize) -> Result<T, Error> {
+    match args.get(index) {
+        None => Err(invalid_input("Missing argument")),
+        Some(arg) => arg
+            .parse()
+            .map_err(|_| invalid_input(&format!("Invalid argument {}", arg))),
+    }
+}
+
+fn get_optional_arg<T: std::str::FromStr>(
+    args: &[String],
+    index: usize,
+    default: T,
+) -> Result<T, Error> {
+    match args.get(index) {
+        None => Ok(default),
+        Some(_) => get_arg(args, index),
+    }
+}
+
+fn get_chunk_pos(args: &[String], index: usize) -> Result<ChunkPos, Error> {
+    Ok(ChunkPos::new(
+        get_arg(args, index)?,
+        get_arg(args, index + 1)?,
+    ))
+}
+
+fn get_region_pos(args: &[String], index: usize) -> Result<RegionPos, Error> {
+    Ok(RegionPos::new(
+        get_arg(args, index)?,
+        get_arg(args, index + 1)?,
+    ))
+}
+
+fn get_optional_region_pos(args: &[String], index: usize) -> Result<Option<RegionPos>, Error> {
+    match args.get(index) {
+        None => Ok(None),
+        Some(_) => Ok(Some(get_region_pos(args, index)?)),
+    }
+}
+
+fn invalid_input(message: &str) -> Error {
+    Error::new(std::io::ErrorKind::InvalidInput, message)
+}
+
+fn missing_region(region_coords: RegionPos) -> Error {
+    Error::new(
+        std::io::ErrorKind::NotFound,
+        format!(
+            "Region {} {} doesn't exist",
+            region_coords.x, region_coords.z
+        ),
+    )
+}
@@ -1,18 +1,39 @@
 use std::borrow::Cow;
 use std::fs::File;
-use std::io::{Error, Write};
+use std::io::Error;
 use std::ops::Range;
 use std::path::Path;
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use memmap2::MmapMut;
+use fs3::FileExt;
+use memmap2::{Advice, Mmap, MmapOptions, MmapRaw};
+use parking_lot::RwLock;
 use positioned_io::ReadAt;
 
-use crate::file_util::{close_file, file_advise, open_file};
+use crate::access_mode::AccessMode;
+use crate::durability::Durability;
+use crate::file_util::{close_file, file_advise, open_file, write_file_at};
+use crate::storage::StorageFile;
 
+// Mapping of the part of a file that was added after it was opened
+struct MappedSegment {
+    start: usize,
+    data: MmapRaw,
+}
+
+// Mappings are never moved or unmapped before the file is closed, so slices borrowed from them stay valid while the file grows
 pub(crate) struct MemoryMappedFile {
     file: File,
-    data: MmapMut,
+    data: MmapRaw,
     memory_size: usize,
+    // Segments are sorted by start and each one starts where the one before it ends
+    segments: RwLock<Vec<MappedSegment>>,
+    // How much of the mappings is backed by the file, everything after this would fault
+    mapped_size: AtomicUsize,
+    is_random: bool,
+    read_only: bool,
 }
 
 impl MemoryMappedFile {
@@ -20,66 +41,198 @@ impl MemoryMappedFile {
         initial_size: usize,
         path: &Path,
         is_random: bool,
-    ) -> Result<MemoryMappedFile, Error> {
-        let file = open_file(initial_size, path)?;
-
-        let data = unsafe { MmapMut::map_mut(&file) }?;
+        access_mode: AccessMode,
+        create: bool,
+    ) -> Result<Option<MemoryMappedFile>, Error> {
+        let file = match open_file(initial_size, path, access_mode, create)? {
+            None => return Ok(None),
+            Some(file) => file,
+        };
 
         let memory_size = file.metadata()?.len() as usize;
 
-        data.advise(memmap2::Advice::WillNeed)?;
+        // Empty files can't be mapped, and we never grow files we aren't allowed to write to
+        if memory_size == 0 {
+            return Ok(None);
+        }
+
+        let data = match access_mode.is_read_only() {
+            true => MmapRaw::from(unsafe { Mmap::map(&file) }?),
+            false => MmapRaw::map_raw(&file)?,
+        };
+
+        data.advise(Advice::WillNeed)?;
 
         match is_random {
-            true => {
-                file_advise(&file, libc::POSIX_FADV_RANDOM)?;
-                data.advise(memmap2::Advice::Random)?;
-            }
-            false => {
-                file_advise(&file, libc::POSIX_FADV_SEQUENTIAL)?;
-                data.advise(memmap2::Advice::Sequential)?;
-            }
+            true => file_advise(&file, libc::POSIX_FADV_RANDOM)?,
+            false => file_advise(&file, libc::POSIX_FADV_SEQUENTIAL)?,
         };
 
-        Ok(MemoryMappedFile {
+        advise_mapping(&data, is_random)?;
+
+        Ok(Some(MemoryMappedFile {
             file,
             data,
             memory_size,
-        })
+            segments: RwLock::new(Vec::new()),
+            mapped_size: AtomicUsize::new(memory_size),
+            is_random,
+            read_only: access_mode.is_read_only(),
+        }))
+    }
+
+    // Finds the mapping that holds all of range, ranges that cross from one mapping into the next go through the file instead
+    fn get_mapped_pointer(&self, range: &Range<usize>) -> Option<*mut u8> {
+        if range.end <= self.memory_size {
+            return Some(unsafe { self.data.as_mut_ptr().add(range.start) });
+        }
+
+        if range.end > self.mapped_size.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let segments = self.segments.read();
+
+        let segment = &segments[segments
+            .partition_point(|segment| segment.start <= range.start)
+            .checked_sub(1)?];
+
+        match range.end <= segment.start + segment.data.len() {
+            true => Some(unsafe { segment.data.as_mut_ptr().add(range.start - segment.start) }),
+            false => None,
+        }
+    }
+
+    fn map_to(&self, size: usize) -> Result<(), Error> {
+        if size <= self.mapped_size.load(Ordering::Acquire) {
+            return Ok(());
+        }
+
+        let mut segments = self.segments.write();
+
+        let mapped_end = segments.last().map_or(self.memory_size, |segment| {
+            segment.start + segment.data.len()
+        });
+
+        // Segments double the mapped size so a growing file only needs a few of them. Mapping past the end of the file is fine as long as nothing touches it before the file is grown
+        if size > mapped_end {
+            let data = MmapOptions::new()
+                .offset(mapped_end as u64)
+                .len((size - mapped_end).max(mapped_end))
+                .map_raw(&self.file)?;
+
+            advise_mapping(&data, self.is_random)?;
+
+            segments.push(MappedSegment {
+                start: mapped_end,
+                data,
+            });
+        }
+
+        self.mapped_size.fetch_max(size, Ordering::Release);
+
+        Ok(())
     }
+}
 
-    pub(crate) fn close_file(self) -> Result<(), Error> {
+impl StorageFile for MemoryMappedFile {
+    fn close_file(self: Box<Self>) -> Result<(), Error> {
         self.data.flush()?;
 
+        for segment in self.segments.read().iter() {
+            segment.data.flush()?;
+        }
+
         close_file(self.file)
     }
 
-    pub(crate) fn read_file(&self, range: Range<usize>) -> Result<Cow<[u8]>, Error> {
-        if range.end <= self.memory_size {
-            return Ok(Cow::Borrowed(&self.data[range]));
-        } else if range.start <= self.memory_size {
-            let mut vector = Vec::new();
+    fn flush(&self, durability: Durability) -> Result<(), Error> {
+        if self.read_only {
+            return Ok(());
+        }
 
-            vector.resize(range.len(), 0u8);
+        let segments = self.segments.read();
 
-            vector.write_all(&self.data[range.start..self.memory_size])?;
+        let mappings =
+            std::iter::once(&self.data).chain(segments.iter().map(|segment| &segment.data));
 
-            self.file.read_at(
-                (self.memory_size + 1) as u64,
-                &mut vector.as_mut_slice()[self.memory_size - range.start..range.len()],
-            )?;
+        match durability {
+            Durability::Async => {
+                for mapping in mappings {
+                    mapping.flush_async()?;
+                }
 
-            return Ok(Cow::Owned(vector));
+                Ok(())
+            }
+            // Writes that didn't fit in a mapping went through the file, so the file is synced as well
+            Durability::Sync | Durability::SyncDirectory => {
+                for mapping in mappings {
+                    mapping.flush()?;
+                }
+
+                self.file.sync_data()
+            }
+        }
+    }
+
+    fn read_file(&self, range: Range<usize>) -> Result<Cow<[u8]>, Error> {
+        if let Some(pointer) = self.get_mapped_pointer(&range) {
+            return Ok(Cow::Borrowed(unsafe {
+                slice::from_raw_parts(pointer, range.len())
+            }));
         }
 
         let mut data = Vec::new();
         data.resize(range.end - range.start, 0u8);
 
-        self.file.read_at(range.start as u64, &mut data)?;
+        self.file.read_exact_at(range.start as u64, &mut data)?;
 
         Ok(Cow::Owned(data))
     }
 
-    pub(crate) fn get_file_size(&self) -> Result<u64, Error> {
+    fn write_file(&self, offset: usize, data: &[u8]) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "File is read only",
+            ));
+        }
+
+        match self.get_mapped_pointer(&(offset..offset + data.len())) {
+            // Callers hold the chunk lock for every byte they write, so nobody else can observe this range
+            Some(pointer) => unsafe {
+                ptr::copy_nonoverlapping(data.as_ptr(), pointer, data.len());
+
+                Ok(())
+            },
+            // The page cache is shared with the mappings so they see this write as well
+            None => write_file_at(&self.file, offset as u64, data),
+        }
+    }
+
+    fn get_file_size(&self) -> Result<u64, Error> {
         Ok(self.file.metadata()?.len())
     }
+
+    fn ensure_file_size(&self, size: u64) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "File is read only",
+            ));
+        }
+
+        if self.get_file_size()? < size {
+            self.file.allocate(size)?;
+        }
+
+        self.map_to(size as usize)
+    }
+}
+
+fn advise_mapping(data: &MmapRaw, is_random: bool) -> Result<(), Error> {
+    match is_random {
+        true => data.advise(Advice::Random),
+        false => data.advise(Advice::Sequential),
+    }
 }
@@ -0,0 +1,169 @@
+use std::borrow::Cow;
+use std::io::Error;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use ahash::RandomState;
+use dashmap::DashMap;
+use parking_lot::RwLock;
+
+use crate::access_mode::AccessMode;
+use crate::durability::Durability;
+use crate::storage::{Storage, StorageFile};
+
+// Files only live as long as the process, so they outlive the regions that have them open
+#[derive(Default)]
+pub(crate) struct MemoryStorage {
+    files: DashMap<PathBuf, Arc<RwLock<Vec<u8>>>, RandomState>,
+}
+
+impl MemoryStorage {
+    // Throws away every file in the directory
+    pub(crate) fn remove_directory(&self, path: &Path) {
+        self.files
+            .retain(|file_path, _| file_path.parent() != Some(path));
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn open_file(
+        &self,
+        initial_size: usize,
+        path: &Path,
+        _is_random: bool,
+        access_mode: AccessMode,
+        create: bool,
+    ) -> Result<Option<Box<dyn StorageFile>>, Error> {
+        let data = match self.files.get(path) {
+            Some(data) => data.clone(),
+            None if create && !access_mode.is_read_only() => self
+                .files
+                .entry(path.to_path_buf())
+                .or_insert_with(|| Arc::new(RwLock::new(vec![0; initial_size])))
+                .clone(),
+            None => return Ok(None),
+        };
+
+        // Same as on disk, empty files are treated as missing
+        if data.read().is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(Box::new(MemoryFile {
+            data,
+            read_only: access_mode.is_read_only(),
+        })))
+    }
+
+    fn write_whole_file(&self, path: &Path, data: &[u8]) -> Result<(), Error> {
+        self.files
+            .insert(path.to_path_buf(), Arc::new(RwLock::new(data.to_vec())));
+
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<(), Error> {
+        self.files.remove(path);
+
+        Ok(())
+    }
+
+    fn rename_file(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        match self.files.remove(from) {
+            None => Err(Error::new(
+                std::io::ErrorKind::NotFound,
+                "File to rename doesn't exist",
+            )),
+            Some((_, data)) => {
+                self.files.insert(to.to_path_buf(), data);
+
+                Ok(())
+            }
+        }
+    }
+
+    fn sync_directory(&self, _path: &Path) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn list_directory(&self, path: &Path) -> Result<Vec<String>, Error> {
+        Ok(self
+            .files
+            .iter()
+            .filter(|file| file.key().parent() == Some(path))
+            .filter_map(|file| {
+                file.key()
+                    .file_name()
+                    .and_then(|file_name| file_name.to_str())
+                    .map(str::to_owned)
+            })
+            .collect())
+    }
+}
+
+struct MemoryFile {
+    data: Arc<RwLock<Vec<u8>>>,
+    read_only: bool,
+}
+
+impl StorageFile for MemoryFile {
+    // Reads are copied out since the data can move when the file grows
+    fn read_file(&self, range: Range<usize>) -> Result<Cow<[u8]>, Error> {
+        match self.data.read().get(range) {
+            None => Err(Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "Read past the end of the file",
+            )),
+            Some(data) => Ok(Cow::Owned(data.to_vec())),
+        }
+    }
+
+    fn write_file(&self, offset: usize, data: &[u8]) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "File is read only",
+            ));
+        }
+
+        let mut file_data = self.data.write();
+
+        if file_data.len() < offset + data.len() {
+            file_data.resize(offset + data.len(), 0);
+        }
+
+        file_data[offset..offset + data.len()].copy_from_slice(data);
+
+        Ok(())
+    }
+
+    fn get_file_size(&self) -> Result<u64, Error> {
+        Ok(self.data.read().len() as u64)
+    }
+
+    fn ensure_file_size(&self, size: u64) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "File is read only",
+            ));
+        }
+
+        let mut file_data = self.data.write();
+
+        if (file_data.len() as u64) < size {
+            file_data.resize(size as usize, 0);
+        }
+
+        Ok(())
+    }
+
+    fn flush(&self, _durability: Durability) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn close_file(self: Box<Self>) -> Result<(), Error> {
+        Ok(())
+    }
+}
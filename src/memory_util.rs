@@ -19,3 +19,13 @@ pub(crate) fn u8x3_to_u32(data: &[u8]) -> u32 {
 pub(crate) fn u8x4_to_u32(data: &[u8]) -> u32 {
     ((data[0] as u32) << 24) | ((data[1] as u32) << 16) | ((data[2] as u32) << 8) | (data[3] as u32)
 }
+
+#[inline]
+pub(crate) fn u32_to_u8x3(number: u32) -> [u8; 3] {
+    [(number >> 16) as u8, (number >> 8) as u8, number as u8]
+}
+
+#[inline]
+pub(crate) fn u32_to_u8x4(number: u32) -> [u8; 4] {
+    number.to_be_bytes()
+}
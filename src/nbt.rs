@@ -0,0 +1,709 @@
+use std::borrow::Cow;
+use std::io::Error;
+use std::marker::PhantomData;
+
+pub use crate::nbt_query::query_nbt;
+
+pub const TAG_END: u8 = 0;
+pub const TAG_BYTE: u8 = 1;
+pub const TAG_SHORT: u8 = 2;
+pub const TAG_INT: u8 = 3;
+pub const TAG_LONG: u8 = 4;
+pub const TAG_FLOAT: u8 = 5;
+pub const TAG_DOUBLE: u8 = 6;
+pub const TAG_BYTE_ARRAY: u8 = 7;
+pub const TAG_STRING: u8 = 8;
+pub const TAG_LIST: u8 = 9;
+pub const TAG_COMPOUND: u8 = 10;
+pub const TAG_INT_ARRAY: u8 = 11;
+pub const TAG_LONG_ARRAY: u8 = 12;
+
+// Same limit as vanilla, anything deeper is corrupt or malicious
+pub(crate) const MAX_DEPTH: usize = 512;
+
+// The first snapshot that moved everything out of the Level compound
+const FLAT_CHUNK_DATA_VERSION: i32 = 2844;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag<'a> {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Cow<'a, [u8]>),
+    String(Cow<'a, str>),
+    List(List<'a>),
+    Compound(Compound<'a>),
+    IntArray(PackedArray<'a, i32>),
+    LongArray(PackedArray<'a, i64>),
+}
+
+impl<'a> Tag<'a> {
+    pub fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => TAG_BYTE,
+            Tag::Short(_) => TAG_SHORT,
+            Tag::Int(_) => TAG_INT,
+            Tag::Long(_) => TAG_LONG,
+            Tag::Float(_) => TAG_FLOAT,
+            Tag::Double(_) => TAG_DOUBLE,
+            Tag::ByteArray(_) => TAG_BYTE_ARRAY,
+            Tag::String(_) => TAG_STRING,
+            Tag::List(_) => TAG_LIST,
+            Tag::Compound(_) => TAG_COMPOUND,
+            Tag::IntArray(_) => TAG_INT_ARRAY,
+            Tag::LongArray(_) => TAG_LONG_ARRAY,
+        }
+    }
+
+    pub fn as_byte(&self) -> Option<i8> {
+        match self {
+            Tag::Byte(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_short(&self) -> Option<i16> {
+        match self {
+            Tag::Short(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i32> {
+        match self {
+            Tag::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_long(&self) -> Option<i64> {
+        match self {
+            Tag::Long(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f32> {
+        match self {
+            Tag::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_double(&self) -> Option<f64> {
+        match self {
+            Tag::Double(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_byte_array(&self) -> Option<&[u8]> {
+        match self {
+            Tag::ByteArray(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&List<'a>> {
+        match self {
+            Tag::List(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&Compound<'a>> {
+        match self {
+            Tag::Compound(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_int_array(&self) -> Option<&PackedArray<'a, i32>> {
+        match self {
+            Tag::IntArray(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_long_array(&self) -> Option<&PackedArray<'a, i64>> {
+        match self {
+            Tag::LongArray(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Compound<'a> {
+    entries: Vec<(Cow<'a, str>, Tag<'a>)>,
+}
+
+impl<'a> Compound<'a> {
+    pub fn new() -> Self {
+        Compound {
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Tag<'a>> {
+        self.entries
+            .iter()
+            .find(|(entry_name, _)| entry_name == name)
+            .map(|(_, tag)| tag)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Tag<'a>> {
+        self.entries
+            .iter_mut()
+            .find(|(entry_name, _)| entry_name == name)
+            .map(|(_, tag)| tag)
+    }
+
+    /// Replaces and returns the old tag if there already is one with this name
+    pub fn insert(&mut self, name: impl Into<Cow<'a, str>>, tag: Tag<'a>) -> Option<Tag<'a>> {
+        let name = name.into();
+
+        match self.get_mut(&name) {
+            Some(old_tag) => Some(std::mem::replace(old_tag, tag)),
+            None => {
+                self.entries.push((name, tag));
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Tag<'a>> {
+        let index = self
+            .entries
+            .iter()
+            .position(|(entry_name, _)| entry_name == name)?;
+
+        Some(self.entries.remove(index).1)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Tag<'a>)> {
+        self.entries.iter().map(|(name, tag)| (name.as_ref(), tag))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct List<'a> {
+    element_type: u8,
+    elements: Vec<Tag<'a>>,
+}
+
+impl<'a> Default for List<'a> {
+    fn default() -> Self {
+        List::new(TAG_END)
+    }
+}
+
+impl<'a> List<'a> {
+    pub fn new(element_type: u8) -> Self {
+        List {
+            element_type,
+            elements: Vec::new(),
+        }
+    }
+
+    pub fn element_type(&self) -> u8 {
+        self.element_type
+    }
+
+    /// Empty lists of type TAG_END take the type of the first element pushed
+    pub fn push(&mut self, tag: Tag<'a>) -> Result<(), Error> {
+        if self.element_type == TAG_END && self.elements.is_empty() {
+            self.element_type = tag.id();
+        }
+
+        if tag.id() != self.element_type {
+            return Err(Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "List elements must all have the same type",
+            ));
+        }
+
+        self.elements.push(tag);
+
+        Ok(())
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Tag<'a>> {
+        self.elements.get(index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Tag<'a>> {
+        self.elements.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+}
+
+pub trait PackedElement: Copy {
+    const SIZE: usize;
+
+    fn from_be_slice(bytes: &[u8]) -> Self;
+
+    fn extend_be(self, data: &mut Vec<u8>);
+}
+
+impl PackedElement for i32 {
+    const SIZE: usize = 4;
+
+    fn from_be_slice(bytes: &[u8]) -> Self {
+        i32::from_be_bytes(bytes.try_into().unwrap())
+    }
+
+    fn extend_be(self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+impl PackedElement for i64 {
+    const SIZE: usize = 8;
+
+    fn from_be_slice(bytes: &[u8]) -> Self {
+        i64::from_be_bytes(bytes.try_into().unwrap())
+    }
+
+    fn extend_be(self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+/// An int or long array. Arrays that were read stay as the big endian bytes of the buffer they were read from and are only converted when accessed
+#[derive(Clone, Debug)]
+pub struct PackedArray<'a, T: PackedElement> {
+    data: PackedData<'a, T>,
+}
+
+#[derive(Clone, Debug)]
+enum PackedData<'a, T> {
+    Borrowed(&'a [u8], PhantomData<T>),
+    Owned(Vec<T>),
+}
+
+impl<'a, T: PackedElement> PackedArray<'a, T> {
+    fn borrowed(data: &'a [u8]) -> Self {
+        PackedArray {
+            data: PackedData::Borrowed(data, PhantomData),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.data {
+            PackedData::Borrowed(data, _) => data.len() / T::SIZE,
+            PackedData::Owned(data) => data.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<T> {
+        match &self.data {
+            PackedData::Borrowed(data, _) => data
+                .get(index * T::SIZE..(index + 1) * T::SIZE)
+                .map(T::from_be_slice),
+            PackedData::Owned(data) => data.get(index).copied(),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        (0..self.len()).map(|index| self.get(index).unwrap())
+    }
+
+    pub fn to_vec(&self) -> Vec<T> {
+        self.iter().collect()
+    }
+}
+
+impl<'a, T: PackedElement> From<Vec<T>> for PackedArray<'a, T> {
+    fn from(data: Vec<T>) -> Self {
+        PackedArray {
+            data: PackedData::Owned(data),
+        }
+    }
+}
+
+impl<'a, T: PackedElement + PartialEq> PartialEq for PackedArray<'a, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+/// Owns the decompressed data of a chunk so the tags parsed from it can borrow from it
+pub struct ChunkNbt {
+    data: Vec<u8>,
+}
+
+impl ChunkNbt {
+    pub fn new(data: Vec<u8>) -> Self {
+        ChunkNbt { data }
+    }
+
+    pub fn root(&self) -> Result<Compound<'_>, Error> {
+        Ok(read_nbt(&self.data)?.1)
+    }
+
+    pub fn data_version(&self) -> Result<Option<i32>, Error> {
+        Ok(get_data_version(&self.root()?))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Chunks from before 1.9 don't have a DataVersion
+pub fn get_data_version(root: &Compound) -> Option<i32> {
+    root.get("DataVersion").and_then(Tag::as_int)
+}
+
+/// Returns the compound that holds the chunk data, which is the Level compound for chunks from before 1.18
+pub fn get_chunk_data<'b, 'a>(root: &'b Compound<'a>) -> &'b Compound<'a> {
+    if get_data_version(root).map_or(true, |version| version < FLAT_CHUNK_DATA_VERSION) {
+        if let Some(level) = root.get("Level").and_then(Tag::as_compound) {
+            return level;
+        }
+    }
+
+    root
+}
+
+/// Reads a root compound and its name, borrowing as much as possible from data
+pub fn read_nbt(data: &[u8]) -> Result<(Cow<'_, str>, Compound<'_>), Error> {
+    let mut reader = NbtReader { data, position: 0 };
+
+    if reader.read_u8()? != TAG_COMPOUND {
+        return Err(invalid_nbt("Root tag is not a compound"));
+    }
+
+    let name = reader.read_string()?;
+
+    let root = reader.read_compound(0)?;
+
+    Ok((name, root))
+}
+
+pub fn write_nbt(name: &str, root: &Compound) -> Result<Vec<u8>, Error> {
+    let mut data = Vec::new();
+
+    data.push(TAG_COMPOUND);
+
+    write_string(&mut data, name)?;
+
+    write_compound(&mut data, root)?;
+
+    Ok(data)
+}
+
+pub(crate) fn invalid_nbt(message: &str) -> Error {
+    Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+struct NbtReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> NbtReader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], Error> {
+        let end = match self.position.checked_add(length) {
+            Some(end) if end <= self.data.len() => end,
+            _ => return Err(invalid_nbt("Unexpected end of NBT data")),
+        };
+
+        let data = &self.data[self.position..end];
+
+        self.position = end;
+
+        Ok(data)
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len() - self.position
+    }
+
+    fn read_u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn read_i16(&mut self) -> Result<i16, Error> {
+        Ok(i16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn read_i32(&mut self) -> Result<i32, Error> {
+        Ok(i32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn read_i64(&mut self) -> Result<i64, Error> {
+        Ok(i64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn read_length(&mut self) -> Result<usize, Error> {
+        match self.read_i32()? {
+            length if length < 0 => Err(invalid_nbt("Negative NBT length")),
+            length => Ok(length as usize),
+        }
+    }
+
+    fn read_string(&mut self) -> Result<Cow<'a, str>, Error> {
+        let length = u16::from_be_bytes(self.take(2)?.try_into().unwrap()) as usize;
+
+        decode_modified_utf8(self.take(length)?)
+    }
+
+    fn read_array(&mut self, element_size: usize) -> Result<&'a [u8], Error> {
+        let length = self.read_length()?;
+
+        match length.checked_mul(element_size) {
+            Some(size) => self.take(size),
+            None => Err(invalid_nbt("NBT array is too long")),
+        }
+    }
+
+    fn read_compound(&mut self, depth: usize) -> Result<Compound<'a>, Error> {
+        if depth >= MAX_DEPTH {
+            return Err(invalid_nbt("NBT is nested too deeply"));
+        }
+
+        let mut compound = Compound::new();
+
+        loop {
+            let tag_id = self.read_u8()?;
+
+            if tag_id == TAG_END {
+                return Ok(compound);
+            }
+
+            let name = self.read_string()?;
+
+            let tag = self.read_payload(tag_id, depth + 1)?;
+
+            compound.entries.push((name, tag));
+        }
+    }
+
+    fn read_payload(&mut self, tag_id: u8, depth: usize) -> Result<Tag<'a>, Error> {
+        Ok(match tag_id {
+            TAG_BYTE => Tag::Byte(self.read_u8()? as i8),
+            TAG_SHORT => Tag::Short(self.read_i16()?),
+            TAG_INT => Tag::Int(self.read_i32()?),
+            TAG_LONG => Tag::Long(self.read_i64()?),
+            TAG_FLOAT => Tag::Float(f32::from_bits(self.read_i32()? as u32)),
+            TAG_DOUBLE => Tag::Double(f64::from_bits(self.read_i64()? as u64)),
+            TAG_BYTE_ARRAY => Tag::ByteArray(Cow::Borrowed(self.read_array(1)?)),
+            TAG_STRING => Tag::String(self.read_string()?),
+            TAG_LIST => {
+                if depth >= MAX_DEPTH {
+                    return Err(invalid_nbt("NBT is nested too deeply"));
+                }
+
+                let element_type = self.read_u8()?;
+
+                let length = self.read_length()?;
+
+                if element_type == TAG_END && length != 0 {
+                    return Err(invalid_nbt("NBT list of end tags"));
+                }
+
+                // Every element takes at least a byte, so don't trust lengths longer than what is left
+                let mut elements = Vec::with_capacity(length.min(self.remaining()));
+
+                for _ in 0..length {
+                    elements.push(self.read_payload(element_type, depth + 1)?);
+                }
+
+                Tag::List(List {
+                    element_type,
+                    elements,
+                })
+            }
+            TAG_COMPOUND => Tag::Compound(self.read_compound(depth)?),
+            TAG_INT_ARRAY => Tag::IntArray(PackedArray::borrowed(self.read_array(4)?)),
+            TAG_LONG_ARRAY => Tag::LongArray(PackedArray::borrowed(self.read_array(8)?)),
+            _ => return Err(invalid_nbt("Invalid NBT tag type")),
+        })
+    }
+}
+
+fn write_compound(data: &mut Vec<u8>, compound: &Compound) -> Result<(), Error> {
+    for (name, tag) in compound.iter() {
+        data.push(tag.id());
+
+        write_string(data, name)?;
+
+        write_payload(data, tag)?;
+    }
+
+    data.push(TAG_END);
+
+    Ok(())
+}
+
+fn write_length(data: &mut Vec<u8>, length: usize) -> Result<(), Error> {
+    match i32::try_from(length) {
+        Ok(length) => {
+            data.extend_from_slice(&length.to_be_bytes());
+            Ok(())
+        }
+        Err(_) => Err(Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "NBT array is too long",
+        )),
+    }
+}
+
+fn write_payload(data: &mut Vec<u8>, tag: &Tag) -> Result<(), Error> {
+    match tag {
+        Tag::Byte(value) => data.push(*value as u8),
+        Tag::Short(value) => data.extend_from_slice(&value.to_be_bytes()),
+        Tag::Int(value) => data.extend_from_slice(&value.to_be_bytes()),
+        Tag::Long(value) => data.extend_from_slice(&value.to_be_bytes()),
+        Tag::Float(value) => data.extend_from_slice(&value.to_be_bytes()),
+        Tag::Double(value) => data.extend_from_slice(&value.to_be_bytes()),
+        Tag::ByteArray(value) => {
+            write_length(data, value.len())?;
+            data.extend_from_slice(value);
+        }
+        Tag::String(value) => write_string(data, value)?,
+        Tag::List(list) => {
+            data.push(list.element_type);
+            write_length(data, list.len())?;
+
+            for element in list.iter() {
+                write_payload(data, element)?;
+            }
+        }
+        Tag::Compound(compound) => write_compound(data, compound)?,
+        Tag::IntArray(array) => write_packed_array(data, array)?,
+        Tag::LongArray(array) => write_packed_array(data, array)?,
+    }
+
+    Ok(())
+}
+
+fn write_packed_array<T: PackedElement>(
+    data: &mut Vec<u8>,
+    array: &PackedArray<T>,
+) -> Result<(), Error> {
+    write_length(data, array.len())?;
+
+    match &array.data {
+        PackedData::Borrowed(bytes, _) => data.extend_from_slice(bytes),
+        PackedData::Owned(elements) => {
+            for element in elements.iter() {
+                element.extend_be(data);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// NBT strings are Java's modified UTF-8, which only differs from UTF-8 for NUL and characters outside the BMP
+pub(crate) fn decode_modified_utf8(data: &[u8]) -> Result<Cow<'_, str>, Error> {
+    if let Ok(string) = std::str::from_utf8(data) {
+        return Ok(Cow::Borrowed(string));
+    }
+
+    let mut units = Vec::with_capacity(data.len());
+
+    let mut index = 0;
+
+    while index < data.len() {
+        let continuation = |offset: usize| match data.get(index + offset) {
+            Some(byte) if byte & 0xC0 == 0x80 => Ok((byte & 0x3F) as u16),
+            _ => Err(invalid_nbt("Invalid modified UTF-8 string")),
+        };
+
+        let byte = data[index];
+
+        if byte & 0x80 == 0 {
+            units.push(byte as u16);
+            index += 1;
+        } else if byte & 0xE0 == 0xC0 {
+            units.push(((byte & 0x1F) as u16) << 6 | continuation(1)?);
+            index += 2;
+        } else if byte & 0xF0 == 0xE0 {
+            units.push(((byte & 0x0F) as u16) << 12 | continuation(1)? << 6 | continuation(2)?);
+            index += 3;
+        } else {
+            return Err(invalid_nbt("Invalid modified UTF-8 string"));
+        }
+    }
+
+    match String::from_utf16(&units) {
+        Ok(string) => Ok(Cow::Owned(string)),
+        Err(_) => Err(invalid_nbt("Invalid modified UTF-8 string")),
+    }
+}
+
+fn write_string(data: &mut Vec<u8>, string: &str) -> Result<(), Error> {
+    let length_position = data.len();
+
+    data.extend_from_slice(&[0, 0]);
+
+    if string.bytes().all(|byte| byte != 0) && string.chars().all(|char| (char as u32) < 0x10000) {
+        data.extend_from_slice(string.as_bytes());
+    } else {
+        for unit in string.encode_utf16() {
+            match unit {
+                0x01..=0x7F => data.push(unit as u8),
+                0x00 | 0x80..=0x7FF => {
+                    data.push(0xC0 | (unit >> 6) as u8);
+                    data.push(0x80 | (unit & 0x3F) as u8);
+                }
+                _ => {
+                    data.push(0xE0 | (unit >> 12) as u8);
+                    data.push(0x80 | ((unit >> 6) & 0x3F) as u8);
+                    data.push(0x80 | (unit & 0x3F) as u8);
+                }
+            }
+        }
+    }
+
+    let length = match u16::try_from(data.len() - length_position - 2) {
+        Ok(length) => length,
+        Err(_) => {
+            return Err(Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "NBT string is too long",
+            ));
+        }
+    };
+
+    data[length_position..length_position + 2].copy_from_slice(&length.to_be_bytes());
+
+    Ok(())
+}
@@ -0,0 +1,348 @@
+use std::borrow::Cow;
+use std::io::{BufReader, Error, Read};
+
+use crate::nbt::{
+    decode_modified_utf8, invalid_nbt, Compound, List, Tag, MAX_DEPTH, TAG_BYTE, TAG_BYTE_ARRAY,
+    TAG_COMPOUND, TAG_DOUBLE, TAG_END, TAG_FLOAT, TAG_INT, TAG_INT_ARRAY, TAG_LIST, TAG_LONG,
+    TAG_LONG_ARRAY, TAG_SHORT, TAG_STRING,
+};
+
+/// Finds the tags at `paths` without parsing the whole tree. Reading stops as soon as every path has been found or can't exist anymore, so anything after the last wanted tag is never read or decompressed.
+pub fn query_nbt<R: Read>(
+    reader: R,
+    paths: &[&[&str]],
+) -> Result<Vec<Option<Tag<'static>>>, Error> {
+    let mut query = NbtQuery {
+        reader: NbtStreamReader {
+            reader: BufReader::new(reader),
+        },
+        results: vec![None; paths.len()],
+        resolved: vec![false; paths.len()],
+        unresolved: paths.len(),
+    };
+
+    // An empty path asks for the root itself
+    if paths.iter().any(|path| path.is_empty()) {
+        let root = query.reader.read_root()?;
+
+        for (index, path) in paths.iter().enumerate() {
+            query.results[index] = find_path(&root, path);
+        }
+
+        return Ok(query.results);
+    }
+
+    if query.unresolved == 0 {
+        return Ok(query.results);
+    }
+
+    if query.reader.read_u8()? != TAG_COMPOUND {
+        return Err(invalid_nbt("Root tag is not a compound"));
+    }
+
+    query.reader.skip_string()?;
+
+    let wanted: Vec<(usize, &[&str])> = paths.iter().copied().enumerate().collect();
+
+    query.query_compound(&wanted, 0)?;
+
+    Ok(query.results)
+}
+
+fn find_path(root: &Tag<'static>, path: &[&str]) -> Option<Tag<'static>> {
+    let mut tag = root;
+
+    for name in path {
+        tag = tag.as_compound()?.get(name)?;
+    }
+
+    Some(tag.clone())
+}
+
+struct NbtQuery<R: Read> {
+    reader: NbtStreamReader<R>,
+    results: Vec<Option<Tag<'static>>>,
+    resolved: Vec<bool>,
+    unresolved: usize,
+}
+
+impl<R: Read> NbtQuery<R> {
+    fn resolve(&mut self, index: usize, tag: Option<Tag<'static>>) {
+        if !self.resolved[index] {
+            self.resolved[index] = true;
+            self.results[index] = tag;
+            self.unresolved -= 1;
+        }
+    }
+
+    // A wanted name that doesn't show up before the end of the compound isn't anywhere, and if a broken writer repeated a name only the first one counts
+    fn query_compound(&mut self, wanted: &[(usize, &[&str])], depth: usize) -> Result<(), Error> {
+        if depth >= MAX_DEPTH {
+            return Err(invalid_nbt("NBT is nested too deeply"));
+        }
+
+        loop {
+            let tag_id = self.reader.read_u8()?;
+
+            if tag_id == TAG_END {
+                for (index, _) in wanted.iter() {
+                    self.resolve(*index, None);
+                }
+
+                return Ok(());
+            }
+
+            let name = self.reader.read_string()?;
+
+            let matching: Vec<(usize, &[&str])> = wanted
+                .iter()
+                .filter(|(index, path)| !self.resolved[*index] && path[0] == name)
+                .map(|(index, path)| (*index, &path[1..]))
+                .collect();
+
+            if matching.is_empty() {
+                self.reader.skip_payload(tag_id, depth + 1)?;
+                continue;
+            }
+
+            if matching.iter().any(|(_, path)| path.is_empty()) {
+                let tag = self.reader.read_payload(tag_id, depth + 1)?;
+
+                for (index, path) in matching.iter() {
+                    self.resolve(*index, find_path(&tag, path));
+                }
+            } else if tag_id == TAG_COMPOUND {
+                self.query_compound(&matching, depth + 1)?;
+            } else {
+                self.reader.skip_payload(tag_id, depth + 1)?;
+
+                for (index, _) in matching.iter() {
+                    self.resolve(*index, None);
+                }
+            }
+
+            // Nothing else is wanted, the rest of the data is never read
+            if self.unresolved == 0 {
+                return Ok(());
+            }
+        }
+    }
+}
+
+struct NbtStreamReader<R: Read> {
+    reader: BufReader<R>,
+}
+
+impl<R: Read> NbtStreamReader<R> {
+    fn read_bytes<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut data = [0; N];
+
+        self.reader.read_exact(&mut data).map_err(map_eof)?;
+
+        Ok(data)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, Error> {
+        Ok(self.read_bytes::<1>()?[0])
+    }
+
+    fn read_i16(&mut self) -> Result<i16, Error> {
+        Ok(i16::from_be_bytes(self.read_bytes()?))
+    }
+
+    fn read_i32(&mut self) -> Result<i32, Error> {
+        Ok(i32::from_be_bytes(self.read_bytes()?))
+    }
+
+    fn read_i64(&mut self) -> Result<i64, Error> {
+        Ok(i64::from_be_bytes(self.read_bytes()?))
+    }
+
+    fn read_length(&mut self) -> Result<usize, Error> {
+        match self.read_i32()? {
+            length if length < 0 => Err(invalid_nbt("Negative NBT length")),
+            length => Ok(length as usize),
+        }
+    }
+
+    // Lengths come from the data, so the buffer only grows as far as the stream actually goes
+    fn read_vec(&mut self, length: usize) -> Result<Vec<u8>, Error> {
+        let mut data = Vec::new();
+
+        (&mut self.reader)
+            .take(length as u64)
+            .read_to_end(&mut data)?;
+
+        if data.len() != length {
+            return Err(invalid_nbt("Unexpected end of NBT data"));
+        }
+
+        Ok(data)
+    }
+
+    fn skip(&mut self, length: u64) -> Result<(), Error> {
+        let skipped = std::io::copy(&mut (&mut self.reader).take(length), &mut std::io::sink())?;
+
+        if skipped != length {
+            return Err(invalid_nbt("Unexpected end of NBT data"));
+        }
+
+        Ok(())
+    }
+
+    fn read_string(&mut self) -> Result<String, Error> {
+        let length = u16::from_be_bytes(self.read_bytes()?) as usize;
+
+        let data = self.read_vec(length)?;
+
+        Ok(decode_modified_utf8(&data)?.into_owned())
+    }
+
+    fn skip_string(&mut self) -> Result<(), Error> {
+        let length = u16::from_be_bytes(self.read_bytes()?) as u64;
+
+        self.skip(length)
+    }
+
+    fn read_array(&mut self, element_size: usize) -> Result<Vec<u8>, Error> {
+        let length = self.read_length()?;
+
+        match length.checked_mul(element_size) {
+            Some(size) => self.read_vec(size),
+            None => Err(invalid_nbt("NBT array is too long")),
+        }
+    }
+
+    fn read_root(&mut self) -> Result<Tag<'static>, Error> {
+        if self.read_u8()? != TAG_COMPOUND {
+            return Err(invalid_nbt("Root tag is not a compound"));
+        }
+
+        self.skip_string()?;
+
+        self.read_payload(TAG_COMPOUND, 0)
+    }
+
+    fn read_payload(&mut self, tag_id: u8, depth: usize) -> Result<Tag<'static>, Error> {
+        if depth >= MAX_DEPTH {
+            return Err(invalid_nbt("NBT is nested too deeply"));
+        }
+
+        Ok(match tag_id {
+            TAG_BYTE => Tag::Byte(self.read_u8()? as i8),
+            TAG_SHORT => Tag::Short(self.read_i16()?),
+            TAG_INT => Tag::Int(self.read_i32()?),
+            TAG_LONG => Tag::Long(self.read_i64()?),
+            TAG_FLOAT => Tag::Float(f32::from_bits(self.read_i32()? as u32)),
+            TAG_DOUBLE => Tag::Double(f64::from_bits(self.read_i64()? as u64)),
+            TAG_BYTE_ARRAY => Tag::ByteArray(Cow::Owned(self.read_array(1)?)),
+            TAG_STRING => Tag::String(Cow::Owned(self.read_string()?)),
+            TAG_LIST => {
+                let element_type = self.read_u8()?;
+
+                let length = self.read_length()?;
+
+                if element_type == TAG_END && length != 0 {
+                    return Err(invalid_nbt("NBT list of end tags"));
+                }
+
+                let mut list = List::new(element_type);
+
+                for _ in 0..length {
+                    list.push(self.read_payload(element_type, depth + 1)?)?;
+                }
+
+                Tag::List(list)
+            }
+            TAG_COMPOUND => {
+                let mut compound = Compound::new();
+
+                loop {
+                    let tag_id = self.read_u8()?;
+
+                    if tag_id == TAG_END {
+                        break;
+                    }
+
+                    let name = self.read_string()?;
+
+                    compound.insert(name, self.read_payload(tag_id, depth + 1)?);
+                }
+
+                Tag::Compound(compound)
+            }
+            TAG_INT_ARRAY => Tag::IntArray(
+                self.read_array(4)?
+                    .chunks_exact(4)
+                    .map(|bytes| i32::from_be_bytes(bytes.try_into().unwrap()))
+                    .collect::<Vec<i32>>()
+                    .into(),
+            ),
+            TAG_LONG_ARRAY => Tag::LongArray(
+                self.read_array(8)?
+                    .chunks_exact(8)
+                    .map(|bytes| i64::from_be_bytes(bytes.try_into().unwrap()))
+                    .collect::<Vec<i64>>()
+                    .into(),
+            ),
+            _ => return Err(invalid_nbt("Invalid NBT tag type")),
+        })
+    }
+
+    fn skip_array(&mut self, element_size: u64) -> Result<(), Error> {
+        let length = self.read_length()? as u64;
+
+        self.skip(length * element_size)
+    }
+
+    fn skip_payload(&mut self, tag_id: u8, depth: usize) -> Result<(), Error> {
+        if depth >= MAX_DEPTH {
+            return Err(invalid_nbt("NBT is nested too deeply"));
+        }
+
+        match tag_id {
+            TAG_BYTE => self.skip(1),
+            TAG_SHORT => self.skip(2),
+            TAG_INT | TAG_FLOAT => self.skip(4),
+            TAG_LONG | TAG_DOUBLE => self.skip(8),
+            TAG_BYTE_ARRAY => self.skip_array(1),
+            TAG_STRING => self.skip_string(),
+            TAG_LIST => {
+                let element_type = self.read_u8()?;
+
+                let length = self.read_length()?;
+
+                if element_type == TAG_END && length != 0 {
+                    return Err(invalid_nbt("NBT list of end tags"));
+                }
+
+                for _ in 0..length {
+                    self.skip_payload(element_type, depth + 1)?;
+                }
+
+                Ok(())
+            }
+            TAG_COMPOUND => loop {
+                let tag_id = self.read_u8()?;
+
+                if tag_id == TAG_END {
+                    return Ok(());
+                }
+
+                self.skip_string()?;
+
+                self.skip_payload(tag_id, depth + 1)?;
+            },
+            TAG_INT_ARRAY => self.skip_array(4),
+            TAG_LONG_ARRAY => self.skip_array(8),
+            _ => Err(invalid_nbt("Invalid NBT tag type")),
+        }
+    }
+}
+
+fn map_eof(error: Error) -> Error {
+    match error.kind() {
+        std::io::ErrorKind::UnexpectedEof => invalid_nbt("Unexpected end of NBT data"),
+        _ => error,
+    }
+}
@@ -0,0 +1,93 @@
+/// The position of a chunk in chunk coordinates, a chunk is 16 by 16 blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ChunkPos {
+    pub x: i32,
+    pub z: i32,
+}
+
+impl ChunkPos {
+    pub const fn new(x: i32, z: i32) -> ChunkPos {
+        ChunkPos { x, z }
+    }
+
+    /// The region the chunk is stored in. This rounds towards negative infinity, so chunk -1 is in region -1 and not region 0.
+    pub const fn region(self) -> RegionPos {
+        RegionPos::new(self.x >> 5, self.z >> 5)
+    }
+
+    /// Where the chunk is within its region. Chunk -1 is the last chunk of region -1, so this is 31 and not -1.
+    pub const fn local(self) -> LocalPos {
+        LocalPos {
+            x: (self.x & 31) as u8,
+            z: (self.z & 31) as u8,
+        }
+    }
+}
+
+/// The position of a region file, a region is 32 by 32 chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct RegionPos {
+    pub x: i32,
+    pub z: i32,
+}
+
+impl RegionPos {
+    pub const fn new(x: i32, z: i32) -> RegionPos {
+        RegionPos { x, z }
+    }
+
+    /// The chunk at `local` within this region.
+    pub const fn chunk(self, local: LocalPos) -> ChunkPos {
+        ChunkPos::new(self.x << 5 | local.x as i32, self.z << 5 | local.z as i32)
+    }
+
+    /// Every chunk of the region, in the same order as the location table.
+    pub fn chunks(self) -> impl Iterator<Item = ChunkPos> {
+        LocalPos::all().map(move |local| self.chunk(local))
+    }
+}
+
+/// The position of a chunk within its region, both coordinates are between 0 and 31.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, PartialOrd, Ord)]
+pub struct LocalPos {
+    // Ordered by z first, so sorting follows the location table
+    z: u8,
+    x: u8,
+}
+
+impl LocalPos {
+    /// Panics if `x` or `z` is 32 or more.
+    pub fn new(x: u8, z: u8) -> LocalPos {
+        assert!(x < 32 && z < 32, "Local position out of range");
+
+        LocalPos { x, z }
+    }
+
+    /// Panics if `index` is 1024 or more.
+    pub fn from_index(index: usize) -> LocalPos {
+        assert!(index < 1024, "Local index out of range");
+
+        LocalPos {
+            x: (index % 32) as u8,
+            z: (index / 32) as u8,
+        }
+    }
+
+    /// Every position of a region, in the same order as the location table.
+    pub fn all() -> impl Iterator<Item = LocalPos> {
+        (0..1024).map(LocalPos::from_index)
+    }
+
+    pub const fn x(self) -> u8 {
+        self.x
+    }
+
+    pub const fn z(self) -> u8 {
+        self.z
+    }
+
+    /// The index of the chunk in the location and timestamp tables.
+    pub const fn index(self) -> usize {
+        self.x as usize + self.z as usize * 32
+    }
+}
@@ -1,6 +1,6 @@
 use std::ops::Range;
 
-fn consolidate<Idx>(a: &Range<Idx>, b: &Range<Idx>) -> Option<Range<Idx>>
+pub(crate) fn consolidate<Idx>(a: &Range<Idx>, b: &Range<Idx>) -> Option<Range<Idx>>
 where
     Idx: PartialOrd + Clone,
 {
@@ -20,13 +20,13 @@ where
     }
 }
 
-fn consolidate_all<Idx>(mut ranges: Vec<Range<Idx>>) -> Vec<Range<Idx>>
+pub(crate) fn consolidate_all<Idx>(mut ranges: Vec<Range<Idx>>) -> Vec<Range<Idx>>
 where
     Idx: PartialOrd + Clone,
 {
     // Panics for incomparable elements! So no NaN for floats, for instance.
     //ranges.sort_by(|a, b| a.p);
-    glidesort::sort_by(&mut ranges, |a, b| a.start.partial_cmp(&b.start));
+    glidesort::sort_by(&mut ranges, |a, b| a.start.partial_cmp(&b.start).unwrap());
 
     let mut ranges = ranges.into_iter();
     let mut result = Vec::new();
@@ -1,17 +1,52 @@
-use concurrent_queue::{ConcurrentQueue, PushError};
+use concurrent_queue::ConcurrentQueue;
+use once_cell::sync::OnceCell;
+use std::borrow::Cow;
 use std::io::Error;
 use std::mem::{transmute, MaybeUninit};
 use std::ops::Range;
 use std::path::Path;
-use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
 
-use glam::IVec2;
 use parking_lot::RwLock;
 
+use crate::access_mode::AccessMode;
 use crate::chunk::{Chunk, ChunkGuard};
-use crate::memory_mapped_file::MemoryMappedFile;
-use crate::region_file_util::get_chunk_region_coords;
+use crate::compression::{copy_error, CompressedChunk, CompressionType};
+use crate::durability::Durability;
+use crate::encryption::{get_encryption_status, get_key_id, EncryptionKeys, ENCRYPTED_FLAG};
+use crate::memory_util::get_alignment_vector;
+#[cfg(feature = "nbt")]
+use crate::nbt::{query_nbt, Tag};
+use crate::position::{ChunkPos, LocalPos, RegionPos};
+use crate::range_util::consolidate_all;
+use crate::region_file_util::{
+    get_checksum_location, get_oversized_status, CHECKSUMS_SIZE, CHECKSUM_ENTRY_SIZE,
+};
+use crate::region_header::{RegionHeader, SectorRange, HEADER_SIZE};
+use crate::region_info::{ChunkInfo, RegionInfo};
 use crate::region_key::RegionKey;
+use crate::storage::{Storage, StorageFile};
+
+pub(crate) enum WriteCondition {
+    Newer,
+    Unchanged(u32),
+    // Only if there is no chunk at all, whatever its timestamp would be
+    Missing,
+}
+
+impl WriteCondition {
+    // current_timestamp is None if the chunk doesn't exist, a missing chunk counts as timestamp 0 otherwise
+    pub(crate) fn allows(&self, current_timestamp: Option<u32>, timestamp: u32) -> bool {
+        match self {
+            WriteCondition::Newer => timestamp > current_timestamp.unwrap_or(0),
+            WriteCondition::Unchanged(expected_timestamp) => {
+                current_timestamp.unwrap_or(0) == *expected_timestamp
+            }
+            WriteCondition::Missing => current_timestamp.is_none(),
+        }
+    }
+}
 
 pub(crate) struct MutableRegionMetadata {
     pub(crate) free_ranges: Box<[ConcurrentQueue<Range<u32>>; 256]>,
@@ -21,7 +56,42 @@ pub(crate) struct MutableRegionMetadata {
 
 pub(crate) struct StaticRegionMetadata {
     pub(crate) directory: &'static str,
-    pub(crate) file: Option<MemoryMappedFile>,
+    pub(crate) path: String,
+    pub(crate) storage: &'static dyn Storage,
+    pub(crate) access_mode: AccessMode,
+    pub(crate) encryption_keys: Option<Arc<EncryptionKeys>>,
+    pub(crate) file: Option<Box<dyn StorageFile>>,
+    pub(crate) checksums_enabled: bool,
+    // Sidecar file with a checksum for every chunk, only opened once it exists
+    pub(crate) checksums: OnceCell<Box<dyn StorageFile>>,
+}
+
+impl StaticRegionMetadata {
+    // Creates the sidecar file on the first checksum written to it, so only reading a region never leaves an empty one behind
+    pub(crate) fn get_checksums_for_write(&self) -> Result<Option<&dyn StorageFile>, Error> {
+        if !self.checksums_enabled {
+            return Ok(None);
+        }
+
+        self.checksums
+            .get_or_try_init(|| {
+                self.storage
+                    .open_file(
+                        CHECKSUMS_SIZE,
+                        Path::new(&format!("{}.crc", self.path)),
+                        true,
+                        self.access_mode,
+                        true,
+                    )?
+                    .ok_or_else(|| {
+                        Error::new(
+                            std::io::ErrorKind::NotFound,
+                            "Checksum file couldn't be created",
+                        )
+                    })
+            })
+            .map(|checksums| Some(&**checksums))
+    }
 }
 
 pub(crate) struct Region {
@@ -31,20 +101,54 @@ pub(crate) struct Region {
 }
 
 impl Region {
-    pub(crate) fn new(key: &RegionKey) -> Result<Region, Error> {
-        let file = MemoryMappedFile::open_file(
-            8192,
-            Path::new(&format!(
-                "{0}/r.{1}.{2}.mca",
-                key.directory, key.coords.x, key.coords.y
-            )),
-            true,
-        )?;
-        let end = ((file.get_file_size()? as u32 / 4096) - 2).max(1);
+    pub(crate) fn new(
+        key: &RegionKey,
+        storage: &'static dyn Storage,
+        access_mode: AccessMode,
+        encryption_keys: Option<Arc<EncryptionKeys>>,
+        checksums: bool,
+        create: bool,
+    ) -> Result<Option<Region>, Error> {
+        let path = format!(
+            "{0}/r.{1}.{2}.{3}",
+            key.directory,
+            key.coords.x,
+            key.coords.z,
+            key.format.extension()
+        );
+
+        let file =
+            match storage.open_file(HEADER_SIZE, Path::new(&path), true, access_mode, create)? {
+                None => return Ok(None),
+                Some(file) => file,
+            };
+
+        // Regions written before checksums were turned on have no sidecar file until a chunk is written, their chunks are checked once they are rewritten
+        let checksums_file = OnceCell::new();
+
+        if checksums {
+            if let Some(checksums) = storage.open_file(
+                CHECKSUMS_SIZE,
+                Path::new(&format!("{}.crc", path)),
+                true,
+                access_mode,
+                false,
+            )? {
+                let _ = checksums_file.set(checksums);
+            }
+        }
+
+        let header = RegionHeader::read(&*file)?;
 
         let static_region_metadata = StaticRegionMetadata {
             directory: key.directory,
+            path,
+            storage,
+            access_mode,
+            encryption_keys,
             file: Some(file),
+            checksums_enabled: checksums,
+            checksums: checksums_file,
         };
 
         let mut taken_ranges: [MaybeUninit<Range<usize>>; 1024] =
@@ -61,9 +165,12 @@ impl Region {
                     unsafe { MaybeUninit::uninit().assume_init() };
 
                 for y in y_array.iter_mut().enumerate() {
+                    let chunk_region_coords = LocalPos::new(x.0 as u8, y.0 as u8);
+
                     let (chunk, chunk_range) = Chunk::new(
-                        IVec2::new(x.0 as i32, y.0 as i32),
+                        chunk_region_coords,
                         key.coords,
+                        header.location(chunk_region_coords),
                         &static_region_metadata,
                     )?;
 
@@ -71,7 +178,7 @@ impl Region {
 
                     *y.1 = MaybeUninit::new(ChunkGuard {
                         chunk: RwLock::new(chunk),
-                        timestamp: AtomicU32::new(0),
+                        timestamp: AtomicU32::new(header.timestamp(chunk_region_coords)),
                     });
                 }
 
@@ -85,19 +192,43 @@ impl Region {
 
         let mut free_ranges = Vec::with_capacity(256);
 
-        free_ranges.fill_with(ConcurrentQueue::<Range<u32>>::unbounded);
+        free_ranges.resize_with(256, ConcurrentQueue::<Range<u32>>::unbounded);
 
         glidesort::sort_by(&mut taken_ranges, |a, b| a.start.cmp(&b.start));
 
-        Ok(Region {
+        let mutable_region_metadata = MutableRegionMetadata {
+            free_ranges: Box::try_from(free_ranges.into_boxed_slice()).unwrap(),
+            wanted_space: AtomicU32::new(2),
+            modify_lock: RwLock::new(()),
+        };
+
+        // Everything between the header and the last chunk that isn't owned by a chunk is free
+        let mut end = 2;
+
+        for taken_range in taken_ranges.iter().filter(|range| !range.is_empty()) {
+            if taken_range.start > end {
+                Chunk::free_space(
+                    end as u64..taken_range.start as u64,
+                    &mutable_region_metadata,
+                );
+            }
+
+            end = end.max(taken_range.end);
+        }
+
+        mutable_region_metadata
+            .wanted_space
+            .store(end as u32, Ordering::Relaxed);
+
+        Ok(Some(Region {
             static_metadata: static_region_metadata,
-            mutable_metadata: MutableRegionMetadata {
-                free_ranges: Box::try_from(free_ranges.into_boxed_slice()).unwrap(),
-                wanted_space: AtomicU32::new(end),
-                modify_lock: RwLock::new(()),
-            },
+            mutable_metadata: mutable_region_metadata,
             chunks,
-        })
+        }))
+    }
+
+    fn get_chunk_guard(&self, chunk_region_coords: LocalPos) -> &ChunkGuard {
+        &self.chunks[chunk_region_coords.x() as usize][chunk_region_coords.z() as usize]
     }
 
     pub(crate) fn close(&mut self) -> Result<(), Error> {
@@ -112,52 +243,589 @@ impl Region {
         }
         .close_file()?;
 
+        if let Some(checksums) = self.static_metadata.checksums.take() {
+            checksums.close_file()?;
+        }
+
         Ok(())
     }
 
-    pub(crate) fn read_chunk(&self, chunk_coords: IVec2) -> Result<Option<Vec<u8>>, Error> {
-        let chunk_region_coords = get_chunk_region_coords(chunk_coords);
+    // Rewrites the region file with its chunks packed right after the header, in the order they were already in, and closes the region. Returns how many sectors the file shrank by
+    pub(crate) fn compact(&mut self) -> Result<u64, Error> {
+        let storage = self.static_metadata.storage;
+
+        let compacted_path = format!("{}.tmp", self.static_metadata.path);
+
+        let result = self.write_compacted_file(Path::new(&compacted_path));
 
-        let chunk = &self.chunks[chunk_region_coords.x as usize][chunk_region_coords.y as usize]
-            .chunk
-            .read();
+        self.close()?;
 
-        let data =
-            chunk.read_chunk_data(chunk_coords, chunk_region_coords, &self.static_metadata)?;
+        match result {
+            Err(error) => {
+                let _ = storage.remove_file(Path::new(&compacted_path));
+
+                Err(error)
+            }
+            Ok(freed_sectors) => {
+                storage.rename_file(
+                    Path::new(&compacted_path),
+                    Path::new(&self.static_metadata.path),
+                )?;
 
-        Ok(data)
+                storage.sync_directory(Path::new(self.static_metadata.directory))?;
+
+                Ok(freed_sectors)
+            }
+        }
     }
 
+    fn write_compacted_file(&self, path: &Path) -> Result<u64, Error> {
+        if self.static_metadata.access_mode.is_read_only() {
+            return Err(Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "Region is read only",
+            ));
+        }
+
+        let file = match &self.static_metadata.file {
+            None => {
+                return Err(Error::new(
+                    std::io::ErrorKind::Other,
+                    "Region file is not open",
+                ));
+            }
+            Some(file) => file,
+        };
+
+        let mut header = RegionHeader::read(&**file)?;
+
+        let mut chunk_ranges: Vec<(LocalPos, Range<usize>)> = LocalPos::all()
+            .map(|local| (local, header.location(local).sectors()))
+            .filter(|(_, range)| !range.is_empty())
+            .collect();
+
+        glidesort::sort_by(&mut chunk_ranges, |a, b| a.1.start.cmp(&b.1.start));
+
+        // Timestamps stay valid since every chunk is copied as it is stored, checksums are moved along below
+        let mut end = 2;
+
+        for (local, range) in chunk_ranges.iter() {
+            header.set_location(*local, SectorRange::new(end as u32, range.len() as u8));
+
+            end += range.len();
+        }
+
+        let storage = self.static_metadata.storage;
+
+        // Left behind by a compaction that was interrupted
+        storage.remove_file(path)?;
+
+        let compacted_file =
+            match storage.open_file(HEADER_SIZE, path, false, AccessMode::ReadWrite, true)? {
+                None => {
+                    return Err(Error::new(
+                        std::io::ErrorKind::Other,
+                        "Compacted region file can't be created",
+                    ));
+                }
+                Some(file) => file,
+            };
+
+        compacted_file.ensure_file_size(end as u64 * 4096)?;
+
+        compacted_file.write_file(0, header.as_bytes())?;
+
+        let mut compacted_end = HEADER_SIZE;
+
+        for (_, range) in chunk_ranges.iter() {
+            let sector_data = file.read_file(range.start * 4096..range.end * 4096)?;
+
+            compacted_file.write_file(compacted_end, &sector_data)?;
+
+            compacted_end += sector_data.len();
+        }
+
+        compacted_file.flush(Durability::Sync)?;
+        compacted_file.close_file()?;
+
+        // Checksums only count for the location they were taken at. If this is interrupted before the rename they just count as unchecked
+        if let Some(checksums) = self.static_metadata.checksums.get() {
+            for (local, range) in chunk_ranges.iter() {
+                let checksum_location = get_checksum_location(*local);
+
+                if checksums.get_file_size()? < (checksum_location + CHECKSUM_ENTRY_SIZE) as u64 {
+                    continue;
+                }
+
+                let old_location = SectorRange::new(range.start as u32, range.len() as u8);
+
+                if SectorRange::from_entry(
+                    &checksums.read_file(checksum_location + 4..checksum_location + 8)?,
+                ) == old_location
+                {
+                    checksums
+                        .write_file(checksum_location + 4, &header.location(*local).to_entry())?;
+                }
+            }
+
+            checksums.flush(Durability::Sync)?;
+        }
+
+        Ok(((file.get_file_size()? + 4095) / 4096).saturating_sub(end as u64))
+    }
+
+    pub(crate) fn flush(&self, durability: Durability) -> Result<(), Error> {
+        match &self.static_metadata.file {
+            None => {
+                return Err(Error::new(
+                    std::io::ErrorKind::Other,
+                    "Region file is not open",
+                ));
+            }
+            Some(file) => file,
+        }
+        .flush(durability)?;
+
+        if let Some(checksums) = self.static_metadata.checksums.get() {
+            checksums.flush(durability)?;
+        }
+
+        if durability == Durability::SyncDirectory
+            && !self.static_metadata.access_mode.is_read_only()
+        {
+            self.static_metadata
+                .storage
+                .sync_directory(Path::new(self.static_metadata.directory))?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn read_chunk(&self, chunk_coords: ChunkPos) -> Result<Option<Vec<u8>>, Error> {
+        let chunk_region_coords = chunk_coords.local();
+
+        let chunk = &self.get_chunk_guard(chunk_region_coords).chunk.read();
+
+        chunk.read_chunk_data(
+            chunk_coords,
+            chunk_region_coords,
+            &self.static_metadata,
+            |compression_type, compressed_data| compression_type.decompress(compressed_data),
+        )
+    }
+
+    // Reads and decompresses the chunk so any corruption shows up as an error. Returns whether a checksum backed the read, or None if the chunk doesn't exist
+    pub(crate) fn verify_chunk(&self, chunk_coords: ChunkPos) -> Result<Option<bool>, Error> {
+        let chunk_region_coords = chunk_coords.local();
+
+        let chunk = &self.get_chunk_guard(chunk_region_coords).chunk.read();
+
+        let stored_length = match chunk.read_stored_data(
+            chunk_coords,
+            chunk_region_coords,
+            &self.static_metadata,
+            |compression_byte, stored_data| {
+                let stored_length = stored_data.len();
+
+                let (compression_type, compressed_data) = Chunk::decode_stored_data(
+                    chunk_coords,
+                    compression_byte,
+                    stored_data,
+                    &self.static_metadata,
+                )?;
+
+                compression_type.decompress(compressed_data)?;
+
+                Ok(stored_length)
+            },
+        )? {
+            None => return Ok(None),
+            Some(stored_length) => stored_length,
+        };
+
+        let file = match &self.static_metadata.file {
+            None => {
+                return Err(Error::new(
+                    std::io::ErrorKind::Other,
+                    "Region file is not open",
+                ));
+            }
+            Some(file) => file,
+        };
+
+        // The chunk lock is still held, so this is the location the data was just read from
+        let location = RegionHeader::read_location(&**file, chunk_region_coords)?;
+
+        Ok(Some(
+            Chunk::get_checksum(
+                chunk_region_coords,
+                location,
+                stored_length,
+                &self.static_metadata,
+            )?
+            .is_some(),
+        ))
+    }
+
+    #[cfg(feature = "nbt")]
+    pub(crate) fn query_chunk(
+        &self,
+        chunk_coords: ChunkPos,
+        paths: &[&[&str]],
+    ) -> Result<Option<Vec<Option<Tag<'static>>>>, Error> {
+        let chunk_region_coords = chunk_coords.local();
+
+        let chunk = &self.get_chunk_guard(chunk_region_coords).chunk.read();
+
+        chunk.read_chunk_data(
+            chunk_coords,
+            chunk_region_coords,
+            &self.static_metadata,
+            |compression_type, compressed_data| {
+                query_nbt(compression_type.decompress_stream(&compressed_data), paths)
+            },
+        )
+    }
+
+    // Copies the stored data of every chunk out of the file, decompressing is left to the caller so the chunks aren't locked while it runs. The coordinates have to be unique and sorted by their local position, which is also the order the chunks are locked in
+    pub(crate) fn read_compressed_chunks(&self, chunk_coords: &[ChunkPos]) -> Vec<CompressedChunk> {
+        let file = match &self.static_metadata.file {
+            Some(file) => file,
+            None => {
+                return chunk_coords
+                    .iter()
+                    .map(|_| {
+                        Err(Error::new(
+                            std::io::ErrorKind::Other,
+                            "Region file is not open",
+                        ))
+                    })
+                    .collect();
+            }
+        };
+
+        let chunks: Vec<_> = chunk_coords
+            .iter()
+            .map(|chunk_coords| self.get_chunk_guard(chunk_coords.local()).chunk.read())
+            .collect();
+
+        let locations: Vec<Result<SectorRange, Error>> = chunk_coords
+            .iter()
+            .map(|chunk_coords| RegionHeader::read_location(&**file, chunk_coords.local()))
+            .collect();
+
+        let sector_ranges: Vec<Range<usize>> = locations
+            .iter()
+            .map(|location| match location {
+                Ok(location) => location.sectors(),
+                Err(_) => 0..0,
+            })
+            .collect();
+
+        // Chunks that sit next to each other on disk are read together
+        let read_ranges = consolidate_all(
+            sector_ranges
+                .iter()
+                .filter(|range| !range.is_empty())
+                .cloned()
+                .collect(),
+        );
+
+        let read_data: Vec<Result<Cow<[u8]>, Error>> = read_ranges
+            .iter()
+            .map(|range| file.read_file(range.start * 4096..range.end * 4096))
+            .collect();
+
+        chunks
+            .iter()
+            .zip(chunk_coords.iter())
+            .zip(sector_ranges.iter())
+            .zip(locations.iter())
+            .map(|(((chunk, chunk_coords), sector_range), location)| {
+                let location = location.as_ref().map_err(copy_error)?;
+
+                if sector_range.is_empty() {
+                    return Ok(None);
+                }
+
+                let read_index =
+                    read_ranges.partition_point(|range| range.start <= sector_range.start) - 1;
+
+                let read_start = read_ranges[read_index].start;
+
+                let read_data = read_data[read_index].as_ref().map_err(copy_error)?;
+
+                let sector_data = &read_data[(sector_range.start - read_start) * 4096
+                    ..(sector_range.end - read_start) * 4096];
+
+                let (compression_type, compressed_data) = chunk.get_compressed_data(
+                    *chunk_coords,
+                    *location,
+                    sector_data,
+                    &self.static_metadata,
+                )?;
+
+                Ok(Some((compression_type, compressed_data.into_owned())))
+            })
+            .collect()
+    }
+
+    pub(crate) fn get_chunk_timestamp(&self, chunk_coords: ChunkPos) -> u32 {
+        let chunk_region_coords = chunk_coords.local();
+
+        self.get_chunk_guard(chunk_region_coords)
+            .timestamp
+            .load(Ordering::Acquire)
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn write_chunk(
         &self,
-        chunk_coords: IVec2,
+        chunk_coords: ChunkPos,
         timestamp: u32,
+        condition: &WriteCondition,
+        compression_byte: u8,
         data: &[u8],
         alignment_data: &[u8],
-    ) -> Result<(), Error> {
-        let chunk_region_coords = get_chunk_region_coords(chunk_coords);
+    ) -> Result<bool, Error> {
+        let chunk_region_coords = chunk_coords.local();
+
+        if self.static_metadata.access_mode.is_read_only() {
+            return Err(Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "Region is read only",
+            ));
+        }
 
-        let chunk_guard =
-            &self.chunks[chunk_region_coords.x as usize][chunk_region_coords.y as usize];
+        let chunk_guard = self.get_chunk_guard(chunk_region_coords);
 
-        if chunk_guard
-            .timestamp
-            .fetch_max(timestamp, Ordering::Relaxed)
-            >= timestamp
-        {
-            return Ok(());
+        // The timestamp can only change while the chunk is write locked, so checking it under the lock makes the write a compare and swap
+        let chunk = chunk_guard.chunk.write();
+
+        if !condition.allows(self.get_current_timestamp(chunk_coords)?, timestamp) {
+            return Ok(false);
         }
 
-        chunk_guard.chunk.write().write_chunk_data(
+        chunk.write_chunk_data(
             chunk_coords,
             chunk_region_coords,
             &self.static_metadata,
             &self.mutable_metadata,
             timestamp,
+            compression_byte,
             data,
             alignment_data,
         )?;
 
-        Ok(())
+        chunk_guard.timestamp.store(timestamp, Ordering::Release);
+
+        Ok(true)
+    }
+
+    pub(crate) fn delete_chunk(&self, chunk_coords: ChunkPos) -> Result<bool, Error> {
+        let chunk_region_coords = chunk_coords.local();
+
+        if self.static_metadata.access_mode.is_read_only() {
+            return Err(Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "Region is read only",
+            ));
+        }
+
+        let chunk_guard = self.get_chunk_guard(chunk_region_coords);
+
+        let chunk = chunk_guard.chunk.write();
+
+        let deleted = chunk.delete_chunk_data(
+            chunk_coords,
+            chunk_region_coords,
+            &self.static_metadata,
+            &self.mutable_metadata,
+        )?;
+
+        chunk_guard.timestamp.store(0, Ordering::Release);
+
+        Ok(deleted)
+    }
+
+    pub(crate) fn get_header(&self) -> Result<RegionHeader, Error> {
+        let file = match &self.static_metadata.file {
+            None => {
+                return Err(Error::new(
+                    std::io::ErrorKind::Other,
+                    "Region file is not open",
+                ));
+            }
+            Some(file) => file,
+        };
+
+        // Keeps writers from moving chunks around while the header is read
+        let _modify_guard = self.mutable_metadata.modify_lock.write();
+
+        RegionHeader::read(&**file)
+    }
+
+    pub(crate) fn get_info(&self, region_coords: RegionPos) -> Result<RegionInfo, Error> {
+        let file = match &self.static_metadata.file {
+            None => {
+                return Err(Error::new(
+                    std::io::ErrorKind::Other,
+                    "Region file is not open",
+                ));
+            }
+            Some(file) => file,
+        };
+
+        // Keeps writers from moving chunks around while the header is read
+        let _modify_guard = self.mutable_metadata.modify_lock.write();
+
+        let header = RegionHeader::read(&**file)?;
+
+        let mut chunks = Vec::new();
+
+        for chunk_region_coords in LocalPos::all() {
+            let location = header.location(chunk_region_coords);
+
+            if location.is_empty() {
+                continue;
+            }
+
+            let file_offset = location.bytes().start;
+
+            let compression_byte = file.read_file(file_offset + 4..file_offset + 5)?[0];
+
+            chunks.push(ChunkInfo {
+                coords: region_coords.chunk(chunk_region_coords),
+                timestamp: header.timestamp(chunk_region_coords),
+                sector_offset: location.offset,
+                sector_count: location.count,
+                compression_type: compression_byte & 63,
+                oversized: get_oversized_status(compression_byte),
+                encrypted: get_encryption_status(compression_byte),
+            });
+        }
+
+        Ok(RegionInfo {
+            file_size: file.get_file_size()?,
+            chunks,
+        })
+    }
+
+    // Returns the compression byte and payload to store for already compressed data, encrypting it if the region has a current key
+    pub(crate) fn encrypt_chunk_data(
+        &self,
+        chunk_coords: ChunkPos,
+        compression_type: &CompressionType,
+        data: Vec<u8>,
+    ) -> Result<(u8, Vec<u8>), Error> {
+        match &self.static_metadata.encryption_keys {
+            Some(encryption_keys) if encryption_keys.get_current_key_id().is_some() => {
+                let compression_byte = compression_type.to_u8() | ENCRYPTED_FLAG;
+
+                Ok((
+                    compression_byte,
+                    encryption_keys.encrypt(chunk_coords, compression_byte, &data)?,
+                ))
+            }
+            _ => Ok((compression_type.to_u8(), data)),
+        }
+    }
+
+    // Rewrites the chunk under the current key, or unencrypted if there is none, keeping its compressed data and timestamp. Returns whether it had to be rewritten
+    pub(crate) fn reencrypt_chunk(&self, chunk_coords: ChunkPos) -> Result<bool, Error> {
+        let chunk_region_coords = chunk_coords.local();
+
+        if self.static_metadata.access_mode.is_read_only() {
+            return Err(Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "Region is read only",
+            ));
+        }
+
+        let current_key_id = self
+            .static_metadata
+            .encryption_keys
+            .as_ref()
+            .and_then(|encryption_keys| encryption_keys.get_current_key_id());
+
+        let chunk_guard = self.get_chunk_guard(chunk_region_coords);
+
+        // Only this chunk is locked, the rest of the region stays readable and writable
+        let chunk = chunk_guard.chunk.write();
+
+        let stored_data = chunk.read_stored_data(
+            chunk_coords,
+            chunk_region_coords,
+            &self.static_metadata,
+            |compression_byte, stored_data| {
+                let key_id = match get_encryption_status(compression_byte) {
+                    true => get_key_id(&stored_data),
+                    false => None,
+                };
+
+                if key_id == current_key_id {
+                    return Ok(None);
+                }
+
+                let (compression_type, compressed_data) = Chunk::decode_stored_data(
+                    chunk_coords,
+                    compression_byte,
+                    stored_data,
+                    &self.static_metadata,
+                )?;
+
+                Ok(Some(self.encrypt_chunk_data(
+                    chunk_coords,
+                    &compression_type,
+                    compressed_data.into_owned(),
+                )?))
+            },
+        )?;
+
+        let (compression_byte, payload) = match stored_data.flatten() {
+            None => return Ok(false),
+            Some(stored_data) => stored_data,
+        };
+
+        chunk.write_chunk_data(
+            chunk_coords,
+            chunk_region_coords,
+            &self.static_metadata,
+            &self.mutable_metadata,
+            chunk_guard.timestamp.load(Ordering::Acquire),
+            compression_byte,
+            &payload,
+            &get_alignment_vector(payload.len() + 5, 4096),
+        )?;
+
+        Ok(true)
+    }
+
+    pub(crate) fn can_write_chunk(
+        &self,
+        chunk_coords: ChunkPos,
+        timestamp: u32,
+        condition: &WriteCondition,
+    ) -> Result<bool, Error> {
+        Ok(condition.allows(self.get_current_timestamp(chunk_coords)?, timestamp))
+    }
+
+    // None if the chunk doesn't exist. Goes by the location entry, a chunk can be stored with timestamp 0
+    fn get_current_timestamp(&self, chunk_coords: ChunkPos) -> Result<Option<u32>, Error> {
+        let file = match &self.static_metadata.file {
+            None => {
+                return Err(Error::new(
+                    std::io::ErrorKind::Other,
+                    "Region file is not open",
+                ));
+            }
+            Some(file) => file,
+        };
+
+        if RegionHeader::read_location(&**file, chunk_coords.local())?.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(self.get_chunk_timestamp(chunk_coords)))
     }
 }
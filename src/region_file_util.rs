@@ -1,26 +1,18 @@
-use glam::IVec2;
+use crc32c::{crc32c, crc32c_append};
 
 use crate::compression::CompressionType;
-use crate::memory_util::{u8x3_to_u32, u8x4_to_u32};
+use crate::memory_util::{u32_to_u8x4, u8x4_to_u32};
+use crate::position::LocalPos;
+use crate::region_header::SectorRange;
 
-#[inline]
-pub(crate) fn get_region_coords(chunk_coords: IVec2) -> IVec2 {
-    chunk_coords >> 5
-}
-
-#[inline]
-pub(crate) fn get_chunk_region_coords(chunk_coords: IVec2) -> IVec2 {
-    chunk_coords & 31
-}
+// Checksum entries are the timestamp, location table entry and stored length the checksum was taken for, followed by the checksum. They are indexed like the location table
+pub(crate) const CHECKSUM_ENTRY_SIZE: usize = 16;
 
-#[inline]
-pub(crate) fn get_chunk_location(chunk_region_coords: IVec2) -> i32 {
-    4 * ((chunk_region_coords.x) + (chunk_region_coords.y) * 32)
-}
+pub(crate) const CHECKSUMS_SIZE: usize = 1024 * CHECKSUM_ENTRY_SIZE;
 
 #[inline]
-pub(crate) fn get_chunk_offset(offset_data: &[u8]) -> u32 {
-    u8x3_to_u32(offset_data)
+pub(crate) fn get_checksum_location(local: LocalPos) -> usize {
+    CHECKSUM_ENTRY_SIZE * local.index()
 }
 
 #[inline]
@@ -35,10 +27,46 @@ pub(crate) fn get_chunk_timestamp(timestamp_data: &[u8]) -> u32 {
 
 #[inline]
 pub(crate) fn get_chunk_compression_type(compression_byte: u8) -> Option<CompressionType> {
-    CompressionType::from_u8(compression_byte ^ 128)
+    CompressionType::from_u8(compression_byte & 63)
 }
 
 #[inline]
 pub(crate) fn get_oversized_status(compression_byte: u8) -> bool {
     compression_byte & 128 != 0
 }
+
+#[inline]
+pub(crate) fn get_chunk_header_data(length: u32, compression_byte: u8) -> [u8; 5] {
+    let length_data = u32_to_u8x4(length);
+
+    [
+        length_data[0],
+        length_data[1],
+        length_data[2],
+        length_data[3],
+        compression_byte,
+    ]
+}
+
+// Covers the compression byte without the oversized flag and the payload exactly as it is stored
+#[inline]
+pub(crate) fn get_chunk_checksum(compression_byte: u8, stored_data: &[u8]) -> u32 {
+    crc32c_append(crc32c(&[compression_byte & 127]), stored_data)
+}
+
+#[inline]
+pub(crate) fn get_checksum_data(
+    timestamp: u32,
+    location: SectorRange,
+    stored_length: u32,
+    checksum: u32,
+) -> [u8; CHECKSUM_ENTRY_SIZE] {
+    let mut checksum_data = [0; CHECKSUM_ENTRY_SIZE];
+
+    checksum_data[0..4].copy_from_slice(&u32_to_u8x4(timestamp));
+    checksum_data[4..8].copy_from_slice(&location.to_entry());
+    checksum_data[8..12].copy_from_slice(&u32_to_u8x4(stored_length));
+    checksum_data[12..16].copy_from_slice(&u32_to_u8x4(checksum));
+
+    checksum_data
+}
@@ -0,0 +1,30 @@
+use crate::access_mode::AccessMode;
+
+#[derive(Hash, Eq, PartialEq, Copy, Clone, Debug)]
+pub(crate) enum RegionFormat {
+    Anvil,
+    // Beta 1.3 to 1.1, same header layout as Anvil
+    McRegion,
+    // Zstd compressed single blob used by some server forks, only read through LinearRegion
+    #[cfg(feature = "linear")]
+    Linear,
+}
+
+impl RegionFormat {
+    pub(crate) fn extension(&self) -> &'static str {
+        match self {
+            RegionFormat::Anvil => "mca",
+            RegionFormat::McRegion => "mcr",
+            #[cfg(feature = "linear")]
+            RegionFormat::Linear => "linear",
+        }
+    }
+
+    // McRegion files are only ever read, anything new gets written as Anvil
+    pub(crate) fn get_access_mode(&self, access_mode: AccessMode) -> AccessMode {
+        match (self, access_mode) {
+            (RegionFormat::McRegion, AccessMode::ReadWrite) => AccessMode::ReadOnly,
+            _ => access_mode,
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
+use std::sync::atomic::{fence, AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use parking_lot::{Condvar, Mutex};
+
+use crate::region::Region;
+
+// Wakes a close that is waiting for the other handles to its region to be dropped
+#[derive(Default)]
+struct Release {
+    waiting: AtomicBool,
+    lock: Mutex<()>,
+    condvar: Condvar,
+}
+
+// A shared handle to an open region, the region is only closed once every other handle is dropped
+pub(crate) struct RegionHandle {
+    region: ManuallyDrop<Arc<Region>>,
+    release: Arc<Release>,
+}
+
+impl RegionHandle {
+    pub(crate) fn new(region: Region) -> RegionHandle {
+        RegionHandle {
+            region: ManuallyDrop::new(Arc::new(region)),
+            release: Arc::new(Release::default()),
+        }
+    }
+
+    // Waits until this is the last handle and returns the region, or None if other handles are still around at deadline
+    pub(crate) fn get_unique(&mut self, deadline: Instant) -> Option<&mut Region> {
+        self.release.waiting.store(true, Ordering::Relaxed);
+
+        // Pairs with the fence in drop, so either this sees the handle is gone or the handle sees this waiting
+        fence(Ordering::SeqCst);
+
+        let mut release_guard = self.release.lock.lock();
+
+        let unique = loop {
+            if Arc::strong_count(&self.region) == 1 {
+                break true;
+            }
+
+            if self
+                .release
+                .condvar
+                .wait_until(&mut release_guard, deadline)
+                .timed_out()
+            {
+                break Arc::strong_count(&self.region) == 1;
+            }
+        };
+
+        drop(release_guard);
+
+        self.release.waiting.store(false, Ordering::Relaxed);
+
+        match unique {
+            true => Arc::get_mut(&mut self.region),
+            false => None,
+        }
+    }
+}
+
+impl Deref for RegionHandle {
+    type Target = Region;
+
+    fn deref(&self) -> &Region {
+        &self.region
+    }
+}
+
+impl Clone for RegionHandle {
+    fn clone(&self) -> RegionHandle {
+        RegionHandle {
+            region: self.region.clone(),
+            release: self.release.clone(),
+        }
+    }
+}
+
+impl Drop for RegionHandle {
+    fn drop(&mut self) {
+        // The region has to be let go of before waking the close, or it could still see this handle
+        unsafe { ManuallyDrop::drop(&mut self.region) };
+
+        fence(Ordering::SeqCst);
+
+        if self.release.waiting.load(Ordering::Relaxed) {
+            // Taken so the wake up can't land between the close checking the handles and going to sleep
+            let _release_guard = self.release.lock.lock();
+
+            self.release.condvar.notify_all();
+        }
+    }
+}
+
+// Marks a region that is being closed, it can't be opened again until its file is closed and unlocked
+#[derive(Default)]
+pub(crate) struct ClosingRegion {
+    done: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl ClosingRegion {
+    pub(crate) fn wait(&self) {
+        let mut done = self.done.lock();
+
+        while !*done {
+            self.condvar.wait(&mut done);
+        }
+    }
+
+    pub(crate) fn finish(&self) {
+        *self.done.lock() = true;
+
+        self.condvar.notify_all();
+    }
+}
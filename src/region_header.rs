@@ -0,0 +1,147 @@
+use std::io::Error;
+use std::ops::Range;
+
+use crate::memory_util::{u32_to_u8x3, u32_to_u8x4, u8x3_to_u32, u8x4_to_u32};
+use crate::position::LocalPos;
+use crate::storage::StorageFile;
+
+pub(crate) const SECTOR_SIZE: usize = 4096;
+
+// The location table takes the first sector and the timestamp table the second
+pub(crate) const HEADER_SIZE: usize = 2 * SECTOR_SIZE;
+
+/// Where a chunk is stored in its region file, counted in 4096 byte sectors. An offset of 0 means the chunk doesn't exist.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct SectorRange {
+    pub offset: u32,
+    pub count: u8,
+}
+
+impl SectorRange {
+    pub const EMPTY: SectorRange = SectorRange {
+        offset: 0,
+        count: 0,
+    };
+
+    pub const fn new(offset: u32, count: u8) -> SectorRange {
+        SectorRange { offset, count }
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.offset == 0
+    }
+
+    /// The sectors of the chunk, empty if the chunk doesn't exist.
+    pub fn sectors(self) -> Range<usize> {
+        match self.is_empty() {
+            true => 0..0,
+            false => self.offset as usize..self.offset as usize + self.count as usize,
+        }
+    }
+
+    /// The bytes of the chunk in the region file, empty if the chunk doesn't exist.
+    pub fn bytes(self) -> Range<usize> {
+        let sectors = self.sectors();
+
+        sectors.start * SECTOR_SIZE..sectors.end * SECTOR_SIZE
+    }
+
+    // A location table entry is the offset as 3 big endian bytes followed by the count
+    pub(crate) fn from_entry(entry: &[u8]) -> SectorRange {
+        SectorRange::new(u8x3_to_u32(&entry[0..3]), entry[3])
+    }
+
+    pub(crate) fn to_entry(self) -> [u8; 4] {
+        let offset_data = u32_to_u8x3(self.offset);
+
+        [offset_data[0], offset_data[1], offset_data[2], self.count]
+    }
+}
+
+/// The location and timestamp tables at the start of a region file. Both tables are indexed the same way, so every lookup goes through a `LocalPos`.
+#[derive(Clone)]
+pub struct RegionHeader {
+    data: Box<[u8]>,
+}
+
+impl RegionHeader {
+    pub(crate) fn read(file: &dyn StorageFile) -> Result<RegionHeader, Error> {
+        Ok(RegionHeader {
+            data: file.read_file(0..HEADER_SIZE)?.into(),
+        })
+    }
+
+    /// Where the chunk is stored.
+    pub fn location(&self, local: LocalPos) -> SectorRange {
+        let location = RegionHeader::location_offset(local);
+
+        SectorRange::from_entry(&self.data[location..location + 4])
+    }
+
+    /// When the chunk was last written, in seconds since the Unix epoch.
+    pub fn timestamp(&self, local: LocalPos) -> u32 {
+        let location = RegionHeader::timestamp_offset(local);
+
+        u8x4_to_u32(&self.data[location..location + 4])
+    }
+
+    pub(crate) fn set_location(&mut self, local: LocalPos, sector_range: SectorRange) {
+        let location = RegionHeader::location_offset(local);
+
+        self.data[location..location + 4].copy_from_slice(&sector_range.to_entry());
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    // Region files are memory mapped, so single entries are read and written in place instead of going through a copy of the header
+
+    pub(crate) fn read_location(
+        file: &dyn StorageFile,
+        local: LocalPos,
+    ) -> Result<SectorRange, Error> {
+        let location = RegionHeader::location_offset(local);
+
+        Ok(SectorRange::from_entry(
+            &file.read_file(location..location + 4)?,
+        ))
+    }
+
+    pub(crate) fn write_location(
+        file: &dyn StorageFile,
+        local: LocalPos,
+        sector_range: SectorRange,
+    ) -> Result<(), Error> {
+        file.write_file(
+            RegionHeader::location_offset(local),
+            &sector_range.to_entry(),
+        )
+    }
+
+    pub(crate) fn read_timestamp(file: &dyn StorageFile, local: LocalPos) -> Result<u32, Error> {
+        let location = RegionHeader::timestamp_offset(local);
+
+        Ok(u8x4_to_u32(&file.read_file(location..location + 4)?))
+    }
+
+    pub(crate) fn write_timestamp(
+        file: &dyn StorageFile,
+        local: LocalPos,
+        timestamp: u32,
+    ) -> Result<(), Error> {
+        file.write_file(
+            RegionHeader::timestamp_offset(local),
+            &u32_to_u8x4(timestamp),
+        )
+    }
+
+    fn location_offset(local: LocalPos) -> usize {
+        4 * local.index()
+    }
+
+    // The timestamp table is laid out exactly like the location table, just a sector later
+    fn timestamp_offset(local: LocalPos) -> usize {
+        SECTOR_SIZE + RegionHeader::location_offset(local)
+    }
+}
@@ -0,0 +1,45 @@
+use crate::position::ChunkPos;
+use crate::sector_map::SectorMap;
+
+/// What the header of a region file says about it and its chunks.
+#[derive(Debug, Clone)]
+pub struct RegionInfo {
+    pub file_size: u64,
+    /// Chunks in the order of the location table
+    pub chunks: Vec<ChunkInfo>,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ChunkInfo {
+    pub coords: ChunkPos,
+    pub timestamp: u32,
+    /// First sector of the chunk, sectors are 4096 bytes
+    pub sector_offset: u32,
+    pub sector_count: u8,
+    /// 1 for gzip, 2 for zlib, 3 for uncompressed and 4 for LZ4
+    pub compression_type: u8,
+    /// The data is stored in a separate `c.X.Z.mcc` file
+    pub oversized: bool,
+    pub encrypted: bool,
+}
+
+impl RegionInfo {
+    /// Sectors taken by the header and chunks.
+    pub fn get_used_sectors(&self) -> u64 {
+        2 + self
+            .chunks
+            .iter()
+            .map(|chunk| chunk.sector_count as u64)
+            .sum::<u64>()
+    }
+
+    /// Sectors in the file that no chunk uses, which `compact_region` gives back.
+    pub fn get_free_sectors(&self) -> u64 {
+        ((self.file_size + 4095) / 4096).saturating_sub(self.get_used_sectors())
+    }
+
+    /// Which chunk, if any, owns each sector of the file.
+    pub fn get_sector_map(&self) -> SectorMap {
+        SectorMap::new(self)
+    }
+}
@@ -1,7 +1,9 @@
-use glam::IVec2;
+use crate::position::RegionPos;
+use crate::region_format::RegionFormat;
 
 #[derive(Hash, Eq, PartialEq, Copy, Clone)]
 pub(crate) struct RegionKey {
-    pub(crate) coords: IVec2,
+    pub(crate) coords: RegionPos,
     pub(crate) directory: &'static str,
+    pub(crate) format: RegionFormat,
 }
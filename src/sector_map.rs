@@ -0,0 +1,113 @@
+use std::io::{Error, Write};
+use std::ops::Range;
+
+use crate::position::ChunkPos;
+use crate::region_info::RegionInfo;
+
+// Sectors per row in the exported images and text
+const ROW_SECTORS: usize = 32;
+
+/// What a 4096 byte sector of a region file is used for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SectorOwner {
+    /// The location and timestamp tables
+    Header,
+    Chunk(ChunkPos),
+    /// Claimed by more than one chunk, which only happens if the header is corrupt
+    Overlap,
+    Free,
+}
+
+/// Who owns each sector of a region file, in file order.
+#[derive(Debug, Clone)]
+pub struct SectorMap {
+    pub sectors: Vec<SectorOwner>,
+}
+
+impl SectorMap {
+    pub(crate) fn new(info: &RegionInfo) -> SectorMap {
+        // Chunks that point past the end of a truncated file still show up
+        let sector_count = info
+            .chunks
+            .iter()
+            .map(|chunk| chunk.sector_offset as usize + chunk.sector_count as usize)
+            .fold(((info.file_size + 4095) / 4096) as usize, usize::max)
+            .max(2);
+
+        let mut sectors = vec![SectorOwner::Free; sector_count];
+
+        sectors[0] = SectorOwner::Header;
+        sectors[1] = SectorOwner::Header;
+
+        for chunk in info.chunks.iter() {
+            let start = chunk.sector_offset as usize;
+
+            for sector in sectors[start..start + chunk.sector_count as usize].iter_mut() {
+                *sector = match sector {
+                    SectorOwner::Free => SectorOwner::Chunk(chunk.coords),
+                    _ => SectorOwner::Overlap,
+                };
+            }
+        }
+
+        SectorMap { sectors }
+    }
+
+    /// Free sector ranges, the same space writes can reuse.
+    pub fn get_free_ranges(&self) -> Vec<Range<usize>> {
+        let mut free_ranges: Vec<Range<usize>> = Vec::new();
+
+        for (index, sector) in self.sectors.iter().enumerate() {
+            if *sector != SectorOwner::Free {
+                continue;
+            }
+
+            match free_ranges.last_mut() {
+                Some(range) if range.end == index => range.end += 1,
+                _ => free_ranges.push(index..index + 1),
+            }
+        }
+
+        free_ranges
+    }
+
+    /// Writes the map as a binary PGM image with one pixel per sector and 32 sectors per row. Free sectors are black, the header is dark gray, chunks alternate between two lighter grays so neighbours can be told apart, and overlaps are white.
+    pub fn write_pgm(&self, writer: &mut impl Write) -> Result<(), Error> {
+        let rows = (self.sectors.len() + ROW_SECTORS - 1) / ROW_SECTORS;
+
+        write!(writer, "P5\n{} {}\n255\n", ROW_SECTORS, rows)?;
+
+        let mut pixels = vec![0u8; rows * ROW_SECTORS];
+
+        for (pixel, sector) in pixels.iter_mut().zip(self.sectors.iter()) {
+            *pixel = match sector {
+                SectorOwner::Free => 0,
+                SectorOwner::Header => 64,
+                SectorOwner::Chunk(coords) => match coords.local().index() % 2 {
+                    0 => 144,
+                    _ => 192,
+                },
+                SectorOwner::Overlap => 255,
+            };
+        }
+
+        writer.write_all(&pixels)
+    }
+
+    /// Renders the map as text with one character per sector and 32 sectors per line. `H` is the header, `.` is free, `#` is an overlap and chunks cycle through the letters so neighbours can be told apart.
+    pub fn to_text(&self) -> String {
+        let mut text = String::with_capacity(self.sectors.len() + self.sectors.len() / ROW_SECTORS);
+
+        for row in self.sectors.chunks(ROW_SECTORS) {
+            text.extend(row.iter().map(|sector| match sector {
+                SectorOwner::Free => '.',
+                SectorOwner::Header => 'H',
+                SectorOwner::Chunk(coords) => (b'a' + (coords.local().index() % 26) as u8) as char,
+                SectorOwner::Overlap => '#',
+            }));
+            text.push('\n');
+        }
+
+        text
+    }
+}
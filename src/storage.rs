@@ -0,0 +1,115 @@
+use std::borrow::Cow;
+use std::fs;
+use std::fs::File;
+use std::io::{Error, Write};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::access_mode::AccessMode;
+use crate::durability::Durability;
+use crate::file_util::sync_directory;
+use crate::memory_mapped_file::MemoryMappedFile;
+
+// An open region, checksum or oversized chunk file
+pub(crate) trait StorageFile: Send + Sync {
+    fn read_file(&self, range: Range<usize>) -> Result<Cow<[u8]>, Error>;
+
+    fn write_file(&self, offset: usize, data: &[u8]) -> Result<(), Error>;
+
+    fn get_file_size(&self) -> Result<u64, Error>;
+
+    fn ensure_file_size(&self, size: u64) -> Result<(), Error>;
+
+    fn flush(&self, durability: Durability) -> Result<(), Error>;
+
+    fn close_file(self: Box<Self>) -> Result<(), Error>;
+}
+
+// Where the files of a directory live. Regions only ever touch their files through this
+pub(crate) trait Storage: Send + Sync {
+    // Returns None if the file doesn't exist and create is false or the file can't be created
+    fn open_file(
+        &self,
+        initial_size: usize,
+        path: &Path,
+        is_random: bool,
+        access_mode: AccessMode,
+        create: bool,
+    ) -> Result<Option<Box<dyn StorageFile>>, Error>;
+
+    // Replaces the whole file with data and makes it durable, creating the directory if it isn't there yet
+    fn write_whole_file(&self, path: &Path, data: &[u8]) -> Result<(), Error>;
+
+    // Removing a file that doesn't exist is fine
+    fn remove_file(&self, path: &Path) -> Result<(), Error>;
+
+    fn rename_file(&self, from: &Path, to: &Path) -> Result<(), Error>;
+
+    fn sync_directory(&self, path: &Path) -> Result<(), Error>;
+
+    // Names of the files in the directory, a missing directory is empty
+    fn list_directory(&self, path: &Path) -> Result<Vec<String>, Error>;
+}
+
+pub(crate) struct DiskStorage;
+
+impl Storage for DiskStorage {
+    fn open_file(
+        &self,
+        initial_size: usize,
+        path: &Path,
+        is_random: bool,
+        access_mode: AccessMode,
+        create: bool,
+    ) -> Result<Option<Box<dyn StorageFile>>, Error> {
+        Ok(
+            MemoryMappedFile::open_file(initial_size, path, is_random, access_mode, create)?
+                .map(|file| Box::new(file) as Box<dyn StorageFile>),
+        )
+    }
+
+    fn write_whole_file(&self, path: &Path, data: &[u8]) -> Result<(), Error> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut file = File::create(path)?;
+
+        file.write_all(data)?;
+
+        file.sync_data()
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<(), Error> {
+        match fs::remove_file(path) {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        }
+    }
+
+    fn rename_file(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        fs::rename(from, to)
+    }
+
+    fn sync_directory(&self, path: &Path) -> Result<(), Error> {
+        sync_directory(path)
+    }
+
+    fn list_directory(&self, path: &Path) -> Result<Vec<String>, Error> {
+        let entries = match fs::read_dir(path) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error),
+        };
+
+        let mut file_names = Vec::new();
+
+        for entry in entries {
+            if let Some(file_name) = entry?.file_name().to_str() {
+                file_names.push(file_name.to_owned());
+            }
+        }
+
+        Ok(file_names)
+    }
+}
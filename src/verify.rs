@@ -0,0 +1,32 @@
+use std::io::Error;
+
+use crate::position::ChunkPos;
+
+/// What verifying a set of regions found.
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    /// Chunks that matched their checksum and decompressed fine
+    pub verified_chunks: usize,
+    /// Chunks without a checksum for their current data that still decompressed fine
+    pub unchecked_chunks: usize,
+    pub corrupt_chunks: Vec<CorruptChunk>,
+}
+
+#[derive(Debug)]
+pub struct CorruptChunk {
+    pub directory: &'static str,
+    pub coords: ChunkPos,
+    pub error: Error,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.corrupt_chunks.is_empty()
+    }
+
+    pub(crate) fn merge(&mut self, other: VerifyReport) {
+        self.verified_chunks += other.verified_chunks;
+        self.unchecked_chunks += other.unchecked_chunks;
+        self.corrupt_chunks.extend(other.corrupt_chunks);
+    }
+}
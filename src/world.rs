@@ -0,0 +1,327 @@
+use std::io::Error;
+
+use ahash::RandomState;
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+
+use crate::access_mode::AccessMode;
+use crate::durability::Durability;
+use crate::position::{ChunkPos, RegionPos};
+
+// Directories are interned so every World opened on the same folder shares the same open regions
+static DIRECTORIES: Lazy<DashMap<String, &'static str, RandomState>> =
+    Lazy::new(|| DashMap::with_capacity_and_hasher(1, RandomState::default()));
+
+#[derive(Hash, Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Dimension {
+    Overworld,
+    Nether,
+    End,
+}
+
+impl Dimension {
+    const ALL: [Dimension; 3] = [Dimension::Overworld, Dimension::Nether, Dimension::End];
+
+    // Ids used by the foreign bindings
+    #[cfg(any(feature = "ffi", feature = "java"))]
+    pub(crate) fn from_u32(id: u32) -> Option<Dimension> {
+        Dimension::ALL.get(id as usize).copied()
+    }
+
+    fn get_directory(&self) -> Option<&'static str> {
+        match self {
+            Dimension::Overworld => None,
+            Dimension::Nether => Some("DIM-1"),
+            Dimension::End => Some("DIM1"),
+        }
+    }
+}
+
+#[derive(Hash, Eq, PartialEq, Copy, Clone, Debug)]
+pub enum RegionKind {
+    /// Terrain and block entities
+    Region,
+    /// Entities, split out of the terrain since 1.17
+    Entities,
+    /// Points of interest like beds and workstations
+    Poi,
+}
+
+impl RegionKind {
+    const ALL: [RegionKind; 3] = [RegionKind::Region, RegionKind::Entities, RegionKind::Poi];
+
+    #[cfg(any(feature = "ffi", feature = "java"))]
+    pub(crate) fn from_u32(id: u32) -> Option<RegionKind> {
+        RegionKind::ALL.get(id as usize).copied()
+    }
+
+    fn get_directory(&self) -> &'static str {
+        match self {
+            RegionKind::Region => "region",
+            RegionKind::Entities => "entities",
+            RegionKind::Poi => "poi",
+        }
+    }
+}
+
+/// A world folder, addressing its region files by dimension and kind instead of by directory. Every kind of every dimension has its own regions, so they are cached and locked separately.
+pub struct World {
+    directories: [[&'static str; 3]; 3],
+}
+
+impl World {
+    pub fn new(directory: &str) -> World {
+        let mut directories = [[""; 3]; 3];
+
+        for dimension in Dimension::ALL {
+            for kind in RegionKind::ALL {
+                let path = match dimension.get_directory() {
+                    None => format!("{}/{}", directory, kind.get_directory()),
+                    Some(dimension_directory) => format!(
+                        "{}/{}/{}",
+                        directory,
+                        dimension_directory,
+                        kind.get_directory()
+                    ),
+                };
+
+                directories[dimension as usize][kind as usize] = intern_directory(path);
+            }
+        }
+
+        World { directories }
+    }
+
+    pub fn get_directory(&self, dimension: Dimension, kind: RegionKind) -> &'static str {
+        self.directories[dimension as usize][kind as usize]
+    }
+
+    pub fn read_chunk(
+        &self,
+        dimension: Dimension,
+        kind: RegionKind,
+        coords: ChunkPos,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        crate::read_chunk(self.get_directory(dimension, kind), coords)
+    }
+
+    pub fn read_chunks(
+        &self,
+        dimension: Dimension,
+        kind: RegionKind,
+        coords: &[ChunkPos],
+    ) -> Vec<Result<Option<Vec<u8>>, Error>> {
+        crate::read_chunks(self.get_directory(dimension, kind), coords)
+    }
+
+    pub async fn read_chunk_async(
+        &self,
+        dimension: Dimension,
+        kind: RegionKind,
+        coords: ChunkPos,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        crate::read_chunk_async(self.get_directory(dimension, kind), coords).await
+    }
+
+    pub fn read_chunk_timestamp(
+        &self,
+        dimension: Dimension,
+        kind: RegionKind,
+        coords: ChunkPos,
+    ) -> Result<u32, Error> {
+        crate::read_chunk_timestamp(self.get_directory(dimension, kind), coords)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn write_chunk(
+        &self,
+        dimension: Dimension,
+        kind: RegionKind,
+        coords: ChunkPos,
+        timestamp: u32,
+        data: &[u8],
+        compression_type: u8,
+        compression_level: i32,
+    ) -> Result<(), Error> {
+        crate::write_chunk(
+            self.get_directory(dimension, kind),
+            coords,
+            timestamp,
+            data,
+            compression_type,
+            compression_level,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn write_chunk_async(
+        &self,
+        dimension: Dimension,
+        kind: RegionKind,
+        coords: ChunkPos,
+        timestamp: u32,
+        data: Vec<u8>,
+        compression_type: u8,
+        compression_level: i32,
+    ) -> Result<(), Error> {
+        crate::write_chunk_async(
+            self.get_directory(dimension, kind),
+            coords,
+            timestamp,
+            data,
+            compression_type,
+            compression_level,
+        )
+        .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn write_chunk_if_newer(
+        &self,
+        dimension: Dimension,
+        kind: RegionKind,
+        coords: ChunkPos,
+        timestamp: u32,
+        data: &[u8],
+        compression_type: u8,
+        compression_level: i32,
+    ) -> Result<bool, Error> {
+        crate::write_chunk_if_newer(
+            self.get_directory(dimension, kind),
+            coords,
+            timestamp,
+            data,
+            compression_type,
+            compression_level,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn write_chunk_if_unchanged(
+        &self,
+        dimension: Dimension,
+        kind: RegionKind,
+        coords: ChunkPos,
+        expected_timestamp: u32,
+        timestamp: u32,
+        data: &[u8],
+        compression_type: u8,
+        compression_level: i32,
+    ) -> Result<bool, Error> {
+        crate::write_chunk_if_unchanged(
+            self.get_directory(dimension, kind),
+            coords,
+            expected_timestamp,
+            timestamp,
+            data,
+            compression_type,
+            compression_level,
+        )
+    }
+
+    pub fn close_region(
+        &self,
+        dimension: Dimension,
+        kind: RegionKind,
+        coords: RegionPos,
+    ) -> Result<(), Error> {
+        crate::close_region(self.get_directory(dimension, kind), coords)
+    }
+
+    pub fn flush_region(
+        &self,
+        dimension: Dimension,
+        kind: RegionKind,
+        coords: RegionPos,
+        durability: Durability,
+    ) -> Result<(), Error> {
+        crate::flush_region(self.get_directory(dimension, kind), coords, durability)
+    }
+
+    /// Flushes every open region of this world.
+    pub fn flush(&self, durability: Durability) -> Result<(), Error> {
+        for directory in self.directories.iter().flatten() {
+            crate::flush_regions(directory, durability)?;
+        }
+
+        Ok(())
+    }
+
+    /// Sets the access mode of every dimension and kind of this world.
+    pub fn set_access_mode(&self, access_mode: AccessMode) -> Result<(), Error> {
+        for directory in self.directories.iter().flatten() {
+            crate::set_access_mode(directory, access_mode)?;
+        }
+
+        Ok(())
+    }
+
+    /// Sets the encryption keys of every dimension and kind of this world, see `set_encryption_keys`.
+    pub fn set_encryption_keys(
+        &self,
+        keys: &[(u32, [u8; 32])],
+        current_key_id: Option<u32>,
+    ) -> Result<(), Error> {
+        for directory in self.directories.iter().flatten() {
+            crate::set_encryption_keys(directory, keys, current_key_id)?;
+        }
+
+        Ok(())
+    }
+
+    /// Turns checksums on or off for every dimension and kind of this world, see `set_checksums`.
+    pub fn set_checksums(&self, enabled: bool) -> Result<(), Error> {
+        for directory in self.directories.iter().flatten() {
+            crate::set_checksums(directory, enabled)?;
+        }
+
+        Ok(())
+    }
+
+    /// Keeps every dimension and kind of this world in memory, see `set_memory_backend`.
+    pub fn set_memory_backend(&self, enabled: bool) -> Result<(), Error> {
+        for directory in self.directories.iter().flatten() {
+            crate::set_memory_backend(directory, enabled)?;
+        }
+
+        Ok(())
+    }
+
+    /// Rewrites every chunk of this world under the current encryption key, see `reencrypt_regions`. Safe to run while the world is in use and to run again after an interruption.
+    pub fn reencrypt(&self) -> Result<usize, Error> {
+        let mut reencrypted_chunks = 0;
+
+        for directory in self.directories.iter().flatten() {
+            reencrypted_chunks += crate::reencrypt_regions(directory)?;
+        }
+
+        Ok(reencrypted_chunks)
+    }
+
+    /// Closes every open region of this world.
+    pub fn close(&self) -> Result<(), Error> {
+        for directory in self.directories.iter().flatten() {
+            crate::close_regions(directory)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn intern_directory(directory: String) -> &'static str {
+    if let Some(interned) = DIRECTORIES.get(&directory) {
+        return *interned;
+    }
+
+    match DIRECTORIES.entry(directory) {
+        Entry::Occupied(entry) => entry.get(),
+        Entry::Vacant(entry) => {
+            let interned: &'static str = Box::leak(entry.key().clone().into_boxed_str());
+
+            entry.insert(interned);
+
+            interned
+        }
+    }
+}
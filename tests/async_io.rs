@@ -0,0 +1,270 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::thread::Thread;
+use std::time::Duration;
+
+use p2vec::{ChunkPos, RegionPos};
+
+fn get_directory(name: &str) -> &'static str {
+    let directory =
+        std::env::temp_dir().join(format!("p2vec-async-{}-{}", name, std::process::id()));
+
+    let _ = std::fs::remove_dir_all(&directory);
+
+    Box::leak(directory.to_string_lossy().into_owned().into_boxed_str())
+}
+
+// Every payload can be checked on its own, so a torn write is noticed
+fn get_payload(seed: u32, length: usize) -> Vec<u8> {
+    let mut data = seed.to_le_bytes().to_vec();
+
+    data.extend((0..length).map(|index| (index as u32 ^ seed.wrapping_mul(2654435761)) as u8));
+
+    data
+}
+
+fn check_payload(data: &[u8]) {
+    let seed = u32::from_le_bytes(data[0..4].try_into().unwrap());
+
+    assert!(
+        data == get_payload(seed, data.len() - 4).as_slice(),
+        "chunk data is torn"
+    );
+}
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn get_waker() -> Waker {
+    Arc::new(ThreadWaker(thread::current())).into()
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let waker = get_waker();
+    let mut context = Context::from_waker(&waker);
+
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+
+        thread::park();
+    }
+}
+
+// Polls the future once, which is what hands its job to the I/O pool
+fn start<F: Future>(future: F) -> Pin<Box<F>> {
+    let mut future = Box::pin(future);
+
+    let _ = future.as_mut().poll(&mut Context::from_waker(&get_waker()));
+
+    future
+}
+
+#[test]
+fn async_results_match_sync_results() {
+    let sync_directory = get_directory("sync");
+    let async_directory = get_directory("async");
+
+    for (x, compression_type) in [(0, 1), (1, 2), (2, 3), (3, 4)] {
+        let coords = ChunkPos::new(x, -1);
+        let payload = get_payload(x as u32, 30000);
+
+        p2vec::write_chunk(sync_directory, coords, 5, &payload, compression_type, 6).unwrap();
+        block_on(p2vec::write_chunk_async(
+            async_directory,
+            coords,
+            5,
+            payload,
+            compression_type,
+            6,
+        ))
+        .unwrap();
+    }
+
+    // Both fail the same way
+    let sync_error = p2vec::write_chunk(sync_directory, ChunkPos::new(0, 0), 5, &[1], 9, 6);
+    let async_error = block_on(p2vec::write_chunk_async(
+        async_directory,
+        ChunkPos::new(0, 0),
+        5,
+        vec![1],
+        9,
+        6,
+    ));
+
+    assert_eq!(
+        sync_error.unwrap_err().kind(),
+        async_error.unwrap_err().kind()
+    );
+
+    p2vec::close_regions(sync_directory).unwrap();
+    p2vec::close_regions(async_directory).unwrap();
+
+    // Written the same way, down to the bytes in the file
+    assert!(
+        std::fs::read(format!("{}/r.0.-1.mca", sync_directory)).unwrap()
+            == std::fs::read(format!("{}/r.0.-1.mca", async_directory)).unwrap()
+    );
+
+    // Present, missing and in a region that doesn't exist
+    for x in [0, 1, 2, 3, 4, 100] {
+        let coords = ChunkPos::new(x, -1);
+
+        let sync_chunk = p2vec::read_chunk(sync_directory, coords).unwrap();
+
+        assert!(sync_chunk == block_on(p2vec::read_chunk_async(async_directory, coords)).unwrap());
+        assert!(sync_chunk == block_on(p2vec::read_chunk_async(sync_directory, coords)).unwrap());
+    }
+
+    // A chunk that can't be decompressed fails both ways
+    p2vec::close_regions(sync_directory).unwrap();
+
+    let path = format!("{}/r.0.-1.mca", sync_directory);
+
+    let mut region = std::fs::read(&path).unwrap();
+
+    let index = ChunkPos::new(1, -1).local().index();
+    let offset = u32::from_be_bytes([
+        0,
+        region[index * 4],
+        region[index * 4 + 1],
+        region[index * 4 + 2],
+    ]) as usize
+        * 4096;
+
+    region[offset + 5..offset + 100].fill(0xFF);
+
+    std::fs::write(&path, region).unwrap();
+
+    let coords = ChunkPos::new(1, -1);
+
+    assert_eq!(
+        p2vec::read_chunk(sync_directory, coords)
+            .unwrap_err()
+            .kind(),
+        block_on(p2vec::read_chunk_async(sync_directory, coords))
+            .unwrap_err()
+            .kind()
+    );
+
+    p2vec::close_regions(sync_directory).unwrap();
+    p2vec::close_regions(async_directory).unwrap();
+    std::fs::remove_dir_all(sync_directory).unwrap();
+    std::fs::remove_dir_all(async_directory).unwrap();
+}
+
+#[test]
+fn dropping_before_the_job_starts_cancels_it() {
+    let directory = get_directory("cancel");
+
+    // Slow enough to decompress that the pool is still busy with them long after the write is dropped
+    for x in 0..4 {
+        p2vec::write_chunk(
+            directory,
+            ChunkPos::new(x, 0),
+            1,
+            &get_payload(x as u32, 2 << 20),
+            1,
+            6,
+        )
+        .unwrap();
+    }
+
+    let threads = thread::available_parallelism().map_or(4, |threads| threads.get());
+
+    for _ in 0..3 {
+        let reads: Vec<_> = (0..threads * 8)
+            .map(|index| {
+                start(p2vec::read_chunk_async(
+                    directory,
+                    ChunkPos::new(index as i32 % 4, 0),
+                ))
+            })
+            .collect();
+
+        // Queued behind every read, and dropped right away
+        drop(start(p2vec::write_chunk_async(
+            directory,
+            ChunkPos::new(0, 1),
+            1,
+            get_payload(9, 100),
+            3,
+            0,
+        )));
+
+        for read in reads {
+            check_payload(&block_on(read).unwrap().unwrap());
+        }
+
+        // The pool takes jobs in order, so the write has been taken off the queue once this is done
+        block_on(p2vec::read_chunk_async(directory, ChunkPos::new(0, 0))).unwrap();
+
+        assert!(p2vec::read_chunk(directory, ChunkPos::new(0, 1))
+            .unwrap()
+            .is_none());
+    }
+
+    p2vec::close_regions(directory).unwrap();
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn dropping_during_a_write_leaves_a_whole_chunk() {
+    let directory = get_directory("drop-during-write");
+
+    p2vec::set_checksums(directory, true).unwrap();
+
+    let coords = ChunkPos::new(3, 3);
+
+    p2vec::write_chunk(directory, coords, 1, &get_payload(0, 1000), 3, 0).unwrap();
+
+    for generation in 1..100u32 {
+        // Some are big enough to go to their own file, which takes the longest to write
+        let length = match generation % 3 {
+            0 => 2 << 20,
+            _ => 1000 + generation as usize * 997,
+        };
+
+        let write = start(p2vec::write_chunk_async(
+            directory,
+            coords,
+            generation + 1,
+            get_payload(generation, length),
+            (generation % 4) as u8 + 1,
+            1,
+        ));
+
+        thread::sleep(Duration::from_micros(generation as u64 * 13 % 500));
+
+        drop(write);
+
+        // Whichever write last finished, never part of one
+        check_payload(&p2vec::read_chunk(directory, coords).unwrap().unwrap());
+    }
+
+    block_on(p2vec::read_chunk_async(directory, coords)).unwrap();
+
+    check_payload(&p2vec::read_chunk(directory, coords).unwrap().unwrap());
+
+    // Nothing torn made it to disk either
+    p2vec::close_regions(directory).unwrap();
+
+    assert!(p2vec::verify_region(directory, RegionPos::new(0, 0))
+        .unwrap()
+        .is_ok());
+    check_payload(&p2vec::read_chunk(directory, coords).unwrap().unwrap());
+
+    p2vec::close_regions(directory).unwrap();
+    p2vec::set_checksums(directory, false).unwrap();
+    std::fs::remove_dir_all(directory).unwrap();
+}
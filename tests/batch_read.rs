@@ -0,0 +1,170 @@
+use std::path::Path;
+
+use p2vec::ChunkPos;
+
+fn get_directory(name: &str) -> &'static str {
+    let directory =
+        std::env::temp_dir().join(format!("p2vec-batch-{}-{}", name, std::process::id()));
+
+    let _ = std::fs::remove_dir_all(&directory);
+
+    Box::leak(directory.to_string_lossy().into_owned().into_boxed_str())
+}
+
+fn get_payload(chunk_coords: ChunkPos) -> Vec<u8> {
+    let seed = (chunk_coords.x as u32).wrapping_mul(31) ^ chunk_coords.z as u32;
+
+    (0..1000 + (seed as usize % 7) * 1000)
+        .map(|index| (index as u32 ^ seed.wrapping_mul(2654435761)) as u8)
+        .collect()
+}
+
+// Chunks in four regions, on both sides of zero
+fn get_written_coords() -> Vec<ChunkPos> {
+    let mut coords = Vec::new();
+
+    for x in [-40, -33, -1, 0, 5, 31, 32, 63] {
+        for z in [-2, 0, 7] {
+            coords.push(ChunkPos::new(x, z));
+        }
+    }
+
+    coords
+}
+
+fn write_chunks(directory: &'static str) {
+    for (index, chunk_coords) in get_written_coords().into_iter().enumerate() {
+        p2vec::write_chunk(
+            directory,
+            chunk_coords,
+            1,
+            &get_payload(chunk_coords),
+            (index % 4) as u8 + 1,
+            6,
+        )
+        .unwrap();
+    }
+}
+
+#[test]
+fn results_follow_the_requested_order() {
+    let directory = get_directory("order");
+
+    write_chunks(directory);
+
+    // Interleaved across regions, with duplicates and chunks that were never written
+    let mut coords = get_written_coords();
+
+    coords.reverse();
+    coords.insert(3, ChunkPos::new(1, 1));
+    coords.push(ChunkPos::new(-1, -2));
+    coords.push(ChunkPos::new(31, 7));
+    coords.insert(0, ChunkPos::new(-1, -2));
+    coords.push(ChunkPos::new(100, 100));
+    coords.push(ChunkPos::new(1, 1));
+
+    for _ in 0..2 {
+        let chunks = p2vec::read_chunks(directory, &coords);
+
+        assert_eq!(chunks.len(), coords.len());
+
+        for (chunk_coords, chunk) in coords.iter().zip(chunks) {
+            let expected = match get_written_coords().contains(chunk_coords) {
+                true => Some(get_payload(*chunk_coords)),
+                false => None,
+            };
+
+            assert!(chunk.unwrap() == expected, "{:?}", chunk_coords);
+        }
+
+        // The second time around every region is opened by the batch itself
+        p2vec::close_regions(directory).unwrap();
+    }
+
+    assert!(p2vec::read_chunks(directory, &[]).is_empty());
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn missing_regions_read_as_missing_chunks() {
+    let directory = get_directory("missing");
+
+    write_chunks(directory);
+
+    let coords = [
+        ChunkPos::new(1000, 1000),
+        ChunkPos::new(0, 0),
+        ChunkPos::new(-1000, 5),
+        ChunkPos::new(-1000, 5),
+    ];
+
+    let chunks = p2vec::read_chunks(directory, &coords);
+
+    assert!(chunks[0].as_ref().unwrap().is_none());
+    assert!(chunks[1].as_ref().unwrap() == &Some(get_payload(ChunkPos::new(0, 0))));
+    assert!(chunks[2].as_ref().unwrap().is_none());
+    assert!(chunks[3].as_ref().unwrap().is_none());
+
+    // Nothing was created for them
+    assert!(!Path::new(directory).join("r.31.31.mca").exists());
+
+    p2vec::close_regions(directory).unwrap();
+
+    // A directory that doesn't exist at all
+    let chunks = p2vec::read_chunks(get_directory("nowhere"), &coords);
+
+    assert!(chunks.iter().all(|chunk| chunk.as_ref().unwrap().is_none()));
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn bad_chunks_only_fail_their_own_entries() {
+    let directory = get_directory("bad");
+
+    write_chunks(directory);
+
+    p2vec::close_regions(directory).unwrap();
+
+    // Garbles the compressed data of 5 0, which is stored with zlib
+    let path = Path::new(directory).join("r.0.0.mca");
+
+    let mut region = std::fs::read(&path).unwrap();
+
+    let offset = u32::from_be_bytes([0, region[20], region[21], region[22]]) as usize * 4096;
+
+    region[offset + 5..offset + 200].fill(0x55);
+
+    std::fs::write(&path, region).unwrap();
+
+    // A region file that can't be opened at all
+    std::fs::create_dir(Path::new(directory).join("r.2.0.mca")).unwrap();
+
+    let coords = [
+        ChunkPos::new(0, 0),
+        ChunkPos::new(5, 0),
+        ChunkPos::new(70, 7),
+        ChunkPos::new(-1, 0),
+        ChunkPos::new(5, 0),
+        ChunkPos::new(63, -2),
+    ];
+
+    let chunks = p2vec::read_chunks(directory, &coords);
+
+    assert!(chunks[0].as_ref().unwrap() == &Some(get_payload(coords[0])));
+    assert_eq!(
+        chunks[1].as_ref().unwrap_err().kind(),
+        std::io::ErrorKind::InvalidData
+    );
+    assert!(chunks[2].is_err());
+    assert!(chunks[3].as_ref().unwrap() == &Some(get_payload(coords[3])));
+    assert_eq!(
+        chunks[4].as_ref().unwrap_err().kind(),
+        std::io::ErrorKind::InvalidData
+    );
+    assert!(chunks[5].as_ref().unwrap() == &Some(get_payload(coords[5])));
+
+    p2vec::close_regions(directory).unwrap();
+    std::fs::remove_dir_all(directory).unwrap();
+}
@@ -0,0 +1,220 @@
+use std::path::Path;
+
+use p2vec::{ChunkPos, RegionPos};
+
+const TIMESTAMP: u32 = 1680000000;
+
+fn get_directory(name: &str) -> &'static str {
+    let directory =
+        std::env::temp_dir().join(format!("p2vec-checksums-{}-{}", name, std::process::id()));
+
+    let _ = std::fs::remove_dir_all(&directory);
+
+    Box::leak(directory.to_string_lossy().into_owned().into_boxed_str())
+}
+
+fn get_payload(seed: u32, length: usize) -> Vec<u8> {
+    (0..length)
+        .map(|index| (index as u32 ^ seed.wrapping_mul(2654435761)) as u8)
+        .collect()
+}
+
+fn get_region_path(directory: &str) -> std::path::PathBuf {
+    Path::new(directory).join("r.0.0.mca")
+}
+
+// Writes a chunk to the end of the region file the way something that doesn't know about the sidecar file would, keeping the timestamp
+fn rewrite_chunk_externally(directory: &str, index: usize, data: &[u8]) {
+    let path = get_region_path(directory);
+
+    let mut region = std::fs::read(&path).unwrap();
+
+    let offset = region.len() / 4096;
+    let sectors = (data.len() + 5).div_ceil(4096);
+
+    region.extend_from_slice(&(data.len() as u32 + 1).to_be_bytes());
+    region.push(3);
+    region.extend_from_slice(data);
+    region.resize((offset + sectors) * 4096, 0);
+
+    region[index * 4..index * 4 + 3].copy_from_slice(&(offset as u32).to_be_bytes()[1..4]);
+    region[index * 4 + 3] = sectors as u8;
+
+    std::fs::write(&path, region).unwrap();
+}
+
+#[test]
+fn rewrite_in_the_same_second_is_unchecked() {
+    let directory = get_directory("same-second");
+
+    p2vec::set_checksums(directory, true).unwrap();
+
+    p2vec::write_chunk(
+        directory,
+        ChunkPos::new(0, 0),
+        TIMESTAMP,
+        &get_payload(0, 1000),
+        3,
+        0,
+    )
+    .unwrap();
+    p2vec::close_region(directory, RegionPos::new(0, 0)).unwrap();
+
+    let payload = get_payload(1, 6000);
+
+    rewrite_chunk_externally(directory, 0, &payload);
+
+    assert_eq!(
+        p2vec::read_chunk(directory, ChunkPos::new(0, 0)).unwrap(),
+        Some(payload)
+    );
+
+    let report = p2vec::verify_region(directory, RegionPos::new(0, 0)).unwrap();
+
+    assert!(report.is_ok());
+    assert_eq!(report.verified_chunks, 0);
+    assert_eq!(report.unchecked_chunks, 1);
+
+    p2vec::close_region(directory, RegionPos::new(0, 0)).unwrap();
+    p2vec::set_checksums(directory, false).unwrap();
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn corruption_in_place_is_still_caught() {
+    let directory = get_directory("in-place");
+
+    p2vec::set_checksums(directory, true).unwrap();
+
+    p2vec::write_chunk(
+        directory,
+        ChunkPos::new(0, 0),
+        TIMESTAMP,
+        &get_payload(0, 1000),
+        3,
+        0,
+    )
+    .unwrap();
+    p2vec::close_region(directory, RegionPos::new(0, 0)).unwrap();
+
+    let path = get_region_path(directory);
+
+    let mut region = std::fs::read(&path).unwrap();
+
+    region[8192 + 5 + 500] ^= 1;
+
+    std::fs::write(&path, region).unwrap();
+
+    let report = p2vec::verify_region(directory, RegionPos::new(0, 0)).unwrap();
+
+    assert_eq!(report.corrupt_chunks.len(), 1);
+    assert_eq!(
+        report.corrupt_chunks[0].error.kind(),
+        std::io::ErrorKind::InvalidData
+    );
+
+    p2vec::close_region(directory, RegionPos::new(0, 0)).unwrap();
+    p2vec::set_checksums(directory, false).unwrap();
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn compaction_keeps_chunks_checked() {
+    let directory = get_directory("compaction");
+
+    p2vec::set_checksums(directory, true).unwrap();
+
+    for x in 0..4 {
+        p2vec::write_chunk(
+            directory,
+            ChunkPos::new(x, 0),
+            TIMESTAMP,
+            &get_payload(x as u32, 5000 * (x as usize + 1)),
+            3,
+            0,
+        )
+        .unwrap();
+    }
+
+    assert!(p2vec::delete_chunk(directory, ChunkPos::new(1, 0)).unwrap());
+    assert!(p2vec::compact_region(directory, RegionPos::new(0, 0)).unwrap() > 0);
+
+    let report = p2vec::verify_region(directory, RegionPos::new(0, 0)).unwrap();
+
+    assert!(report.is_ok());
+    assert_eq!(report.verified_chunks, 3);
+    assert_eq!(report.unchecked_chunks, 0);
+
+    for x in [0, 2, 3] {
+        assert_eq!(
+            p2vec::read_chunk(directory, ChunkPos::new(x, 0)).unwrap(),
+            Some(get_payload(x as u32, 5000 * (x as usize + 1)))
+        );
+    }
+
+    p2vec::close_region(directory, RegionPos::new(0, 0)).unwrap();
+    p2vec::set_checksums(directory, false).unwrap();
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn sidecar_file_is_only_created_by_writes() {
+    let directory = get_directory("lazy-sidecar");
+
+    let sidecar_path = Path::new(directory).join("r.0.0.mca.crc");
+
+    for x in 0..2 {
+        p2vec::write_chunk(
+            directory,
+            ChunkPos::new(x, 0),
+            TIMESTAMP,
+            &get_payload(x as u32, 1000),
+            3,
+            0,
+        )
+        .unwrap();
+    }
+
+    p2vec::set_checksums(directory, true).unwrap();
+
+    // Reading, verifying and deleting a chunk have no checksum to write
+    assert_eq!(
+        p2vec::read_chunk(directory, ChunkPos::new(0, 0)).unwrap(),
+        Some(get_payload(0, 1000))
+    );
+    assert_eq!(
+        p2vec::verify_region(directory, RegionPos::new(0, 0))
+            .unwrap()
+            .unchecked_chunks,
+        2
+    );
+    assert!(p2vec::delete_chunk(directory, ChunkPos::new(1, 0)).unwrap());
+
+    p2vec::flush_region(directory, RegionPos::new(0, 0), p2vec::Durability::Sync).unwrap();
+    p2vec::close_region(directory, RegionPos::new(0, 0)).unwrap();
+
+    assert!(!sidecar_path.exists());
+
+    p2vec::write_chunk(
+        directory,
+        ChunkPos::new(2, 0),
+        TIMESTAMP,
+        &get_payload(2, 1000),
+        3,
+        0,
+    )
+    .unwrap();
+
+    assert!(sidecar_path.exists());
+
+    p2vec::close_region(directory, RegionPos::new(0, 0)).unwrap();
+
+    let report = p2vec::verify_region(directory, RegionPos::new(0, 0)).unwrap();
+
+    assert_eq!(report.verified_chunks, 1);
+    assert_eq!(report.unchecked_chunks, 1);
+
+    p2vec::close_region(directory, RegionPos::new(0, 0)).unwrap();
+    p2vec::set_checksums(directory, false).unwrap();
+    std::fs::remove_dir_all(directory).unwrap();
+}
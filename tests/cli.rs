@@ -0,0 +1,248 @@
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+use p2vec::{ChunkPos, Durability, RegionPos};
+
+fn get_directory(name: &str) -> &'static str {
+    let directory = std::env::temp_dir().join(format!("p2vec-cli-{}-{}", name, std::process::id()));
+
+    let _ = std::fs::remove_dir_all(&directory);
+
+    Box::leak(directory.to_string_lossy().into_owned().into_boxed_str())
+}
+
+fn get_payload(seed: u32, length: usize) -> Vec<u8> {
+    (0..length)
+        .map(|index| (index as u32 ^ seed.wrapping_mul(2654435761)) as u8)
+        .collect()
+}
+
+fn run(args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_p2vec"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+
+    child.wait_with_output().unwrap()
+}
+
+// Every file in the directory with its contents, to see that a command left them alone
+fn read_files(directory: &str) -> Vec<(String, Vec<u8>)> {
+    let mut files: Vec<(String, Vec<u8>)> = std::fs::read_dir(directory)
+        .unwrap()
+        .map(|entry| {
+            let path = entry.unwrap().path();
+
+            (
+                path.file_name().unwrap().to_string_lossy().into_owned(),
+                std::fs::read(&path).unwrap(),
+            )
+        })
+        .collect();
+
+    files.sort();
+
+    files
+}
+
+#[test]
+fn exit_codes() {
+    let directory = get_directory("exit-codes");
+
+    assert_eq!(run(&["help"], b"").status.code(), Some(0));
+
+    // Usage errors
+    for args in [
+        &[][..],
+        &["unknown", directory],
+        &["cat"],
+        &["cat", directory, "0"],
+        &["cat", directory, "0", "zero"],
+        &["--unlocked"],
+        &["--unlocked", "put", directory, "0", "0"],
+        &["--unlocked", "compact", directory],
+    ] {
+        let output = run(args, b"");
+
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("Usage:"));
+    }
+
+    // Things that don't exist
+    for args in [
+        &["cat", directory, "0", "0"][..],
+        &["info", directory, "0", "0"],
+        &["ls", directory, "-1", "2"],
+    ] {
+        let output = run(args, b"");
+
+        assert_eq!(output.status.code(), Some(1), "{:?}", args);
+        assert!(output.stdout.is_empty());
+    }
+
+    // Reading never creates anything
+    assert!(!Path::new(directory).exists());
+}
+
+#[test]
+fn put_then_cat() {
+    let directory = get_directory("put-cat");
+
+    for (x, compression_type) in [("0", "1"), ("1", "2"), ("2", "3"), ("3", "4")] {
+        let payload = get_payload(x.parse().unwrap(), 20000);
+
+        assert!(
+            run(&["put", directory, x, "-5", compression_type], &payload)
+                .status
+                .success()
+        );
+
+        let output = run(&["cat", directory, x, "-5"], b"");
+
+        assert!(output.status.success());
+        assert!(output.stdout == payload);
+    }
+
+    // A big chunk ends up in its own file and still comes back whole
+    let payload = get_payload(9, 2 << 20);
+
+    assert!(run(&["put", directory, "4", "-5", "3"], &payload)
+        .status
+        .success());
+    assert!(run(&["cat", directory, "4", "-5"], b"").stdout == payload);
+
+    let output = run(&["ls", directory, "0", "-1"], b"");
+
+    assert!(output.status.success());
+
+    let listing = String::from_utf8(output.stdout).unwrap();
+
+    assert_eq!(listing.lines().count(), 5);
+    assert!(listing
+        .lines()
+        .any(|line| line.starts_with("4\t-5\t") && line.ends_with("\toversized")));
+
+    assert!(run(&["rm", directory, "4", "-5"], b"").status.success());
+    assert_eq!(
+        run(&["cat", directory, "4", "-5"], b"").status.code(),
+        Some(1)
+    );
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn check_finds_corrupt_chunks() {
+    let directory = get_directory("check");
+
+    for x in 0..3 {
+        assert!(run(
+            &["put", directory, &x.to_string(), "0", "2"],
+            &get_payload(x, 10000)
+        )
+        .status
+        .success());
+    }
+
+    assert!(run(&["check", directory], b"").status.success());
+
+    // Garbles the zlib stream of the chunk at 1 0
+    let path = Path::new(directory).join("r.0.0.mca");
+
+    let mut region = std::fs::read(&path).unwrap();
+
+    let offset = u32::from_be_bytes([0, region[4], region[5], region[6]]) as usize * 4096;
+
+    region[offset + 5..offset + 100].fill(0xFF);
+
+    std::fs::write(&path, region).unwrap();
+
+    for args in [&["check", directory][..], &["check", directory, "0", "0"]] {
+        let output = run(args, b"");
+
+        assert_eq!(output.status.code(), Some(1));
+
+        let stdout = String::from_utf8(output.stdout).unwrap();
+
+        assert_eq!(stdout.lines().count(), 1);
+        assert!(stdout.starts_with("1\t0\t"));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("1 corrupt"));
+    }
+
+    assert_eq!(
+        run(&["cat", directory, "1", "0"], b"").status.code(),
+        Some(1)
+    );
+    assert!(run(&["cat", directory, "2", "0"], b"").stdout == get_payload(2, 10000));
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn reading_leaves_files_alone() {
+    let directory = get_directory("read-only");
+
+    assert!(
+        run(&["put", directory, "0", "0", "2"], &get_payload(0, 10000))
+            .status
+            .success()
+    );
+
+    let files = read_files(directory);
+
+    for args in [
+        &["info", directory, "0", "0"][..],
+        &["ls", directory, "0", "0"],
+        &["map", directory, "0", "0"],
+        &["map", directory, "0", "0", "pgm"],
+        &["cat", directory, "0", "0"],
+        &["check", directory],
+        &["--unlocked", "cat", directory, "0", "0"],
+    ] {
+        assert!(run(args, b"").status.success(), "{:?}", args);
+    }
+
+    assert!(read_files(directory) == files);
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn unlocked_reads_regions_that_are_open_for_writing() {
+    let directory = get_directory("unlocked");
+
+    // Keeps the region open, and with it the exclusive lock, for as long as the test runs
+    p2vec::write_chunk(
+        directory,
+        ChunkPos::new(0, 0),
+        1,
+        &get_payload(0, 10000),
+        2,
+        6,
+    )
+    .unwrap();
+    p2vec::flush_region(directory, RegionPos::new(0, 0), Durability::Sync).unwrap();
+
+    let output = run(&["cat", directory, "0", "0"], b"");
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+
+    let output = run(&["--unlocked", "cat", directory, "0", "0"], b"");
+
+    assert!(output.status.success());
+    assert!(output.stdout == get_payload(0, 10000));
+
+    assert!(run(&["--unlocked", "info", directory, "0", "0"], b"")
+        .status
+        .success());
+
+    p2vec::close_regions(directory).unwrap();
+    std::fs::remove_dir_all(directory).unwrap();
+}
@@ -0,0 +1,336 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake};
+use std::thread;
+use std::thread::Thread;
+use std::time::Duration;
+
+use p2vec::{ChunkPos, RegionPos};
+
+const WRITERS: i32 = 4;
+const WRITES: u32 = 200;
+
+fn get_directory(name: &str) -> &'static str {
+    let directory = std::env::temp_dir().join(format!("p2vec-{}-{}", name, std::process::id()));
+
+    let _ = std::fs::remove_dir_all(&directory);
+
+    Box::leak(directory.to_string_lossy().into_owned().into_boxed_str())
+}
+
+// Every payload can be checked on its own, so a reader notices torn or unmapped data
+fn get_payload(seed: u32) -> Vec<u8> {
+    let length = 1000 + (seed as usize * 7919) % 20000;
+
+    let mut data = seed.to_le_bytes().to_vec();
+
+    data.extend((0..length).map(|index| (index as u32 ^ seed.wrapping_mul(2654435761)) as u8));
+
+    data
+}
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let waker = Arc::new(ThreadWaker(thread::current())).into();
+    let mut context = Context::from_waker(&waker);
+
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+
+        thread::park();
+    }
+}
+
+fn check_payload(data: &[u8]) {
+    let seed = u32::from_le_bytes(data[0..4].try_into().unwrap());
+
+    assert_eq!(data, get_payload(seed).as_slice(), "chunk data is corrupt");
+}
+
+#[test]
+fn read_write_close_interleavings() {
+    let directory = get_directory("close-stress");
+    let done = Arc::new(AtomicBool::new(false));
+
+    let writers: Vec<_> = (0..WRITERS)
+        .map(|writer| {
+            thread::spawn(move || {
+                for timestamp in 1..=WRITES {
+                    let seed = writer as u32 * WRITES + timestamp;
+
+                    p2vec::write_chunk(
+                        directory,
+                        ChunkPos::new(writer, 0),
+                        timestamp,
+                        &get_payload(seed),
+                        3,
+                        0,
+                    )
+                    .unwrap();
+                }
+            })
+        })
+        .collect();
+
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let done = done.clone();
+
+            thread::spawn(move || {
+                let coords: Vec<ChunkPos> = (0..WRITERS).map(|x| ChunkPos::new(x, 0)).collect();
+
+                while !done.load(Ordering::Acquire) {
+                    for chunk_coords in coords.iter() {
+                        if let Some(data) = p2vec::read_chunk(directory, *chunk_coords).unwrap() {
+                            check_payload(&data);
+                        }
+                    }
+
+                    for data in p2vec::read_chunks(directory, &coords) {
+                        if let Some(data) = data.unwrap() {
+                            check_payload(&data);
+                        }
+                    }
+                }
+            })
+        })
+        .collect();
+
+    let closer = {
+        let done = done.clone();
+
+        thread::spawn(move || {
+            while !done.load(Ordering::Acquire) {
+                p2vec::close_region(directory, RegionPos::new(0, 0)).unwrap();
+
+                thread::yield_now();
+            }
+        })
+    };
+
+    for writer in writers {
+        writer.join().unwrap();
+    }
+
+    done.store(true, Ordering::Release);
+
+    for reader in readers {
+        reader.join().unwrap();
+    }
+
+    closer.join().unwrap();
+
+    p2vec::close_region(directory, RegionPos::new(0, 0)).unwrap();
+
+    // Nothing was lost across all the reopening
+    for writer in 0..WRITERS {
+        let data = p2vec::read_chunk(directory, ChunkPos::new(writer, 0))
+            .unwrap()
+            .unwrap();
+
+        check_payload(&data);
+
+        assert_eq!(
+            u32::from_le_bytes(data[0..4].try_into().unwrap()),
+            writer as u32 * WRITES + WRITES
+        );
+        assert_eq!(
+            p2vec::read_chunk_timestamp(directory, ChunkPos::new(writer, 0)).unwrap(),
+            WRITES
+        );
+    }
+
+    p2vec::close_region(directory, RegionPos::new(0, 0)).unwrap();
+}
+
+#[test]
+fn close_while_async_reads_are_in_flight() {
+    let directory = get_directory("close-async");
+
+    for x in 0..8 {
+        p2vec::write_chunk(
+            directory,
+            ChunkPos::new(x, 1),
+            1,
+            &get_payload(x as u32),
+            2,
+            6,
+        )
+        .unwrap();
+    }
+
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            thread::spawn(move || {
+                for _ in 0..100 {
+                    let reads: Vec<_> = (0..8)
+                        .map(|x| p2vec::read_chunk_async(directory, ChunkPos::new(x, 1)))
+                        .collect();
+
+                    for read in reads {
+                        check_payload(&block_on(read).unwrap().unwrap());
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for _ in 0..100 {
+        p2vec::close_region(directory, RegionPos::new(0, 0)).unwrap();
+    }
+
+    for reader in readers {
+        reader.join().unwrap();
+    }
+
+    p2vec::close_region(directory, RegionPos::new(0, 0)).unwrap();
+}
+
+#[test]
+fn close_gives_up_on_regions_that_stay_in_use() {
+    let directory = get_directory("close-timeout");
+
+    for x in 0..WRITERS {
+        p2vec::write_chunk(
+            directory,
+            ChunkPos::new(x, 0),
+            1,
+            &get_payload(x as u32),
+            3,
+            0,
+        )
+        .unwrap();
+    }
+
+    p2vec::set_close_timeout(directory, Duration::ZERO);
+
+    let done = Arc::new(AtomicBool::new(false));
+
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let done = done.clone();
+
+            thread::spawn(move || {
+                let coords: Vec<ChunkPos> = (0..WRITERS).map(|x| ChunkPos::new(x, 0)).collect();
+
+                while !done.load(Ordering::Acquire) {
+                    for data in p2vec::read_chunks(directory, &coords) {
+                        if let Some(data) = data.unwrap() {
+                            check_payload(&data);
+                        }
+                    }
+                }
+            })
+        })
+        .collect();
+
+    // Closing either works or gives up right away, and a region it gave up on is still open and intact
+    for _ in 0..1000 {
+        if let Err(error) = p2vec::close_region(directory, RegionPos::new(0, 0)) {
+            assert_eq!(error.kind(), std::io::ErrorKind::WouldBlock);
+
+            check_payload(
+                &p2vec::read_chunk(directory, ChunkPos::new(0, 0))
+                    .unwrap()
+                    .unwrap(),
+            );
+        }
+    }
+
+    done.store(true, Ordering::Release);
+
+    for reader in readers {
+        reader.join().unwrap();
+    }
+
+    p2vec::set_close_timeout(directory, Duration::from_secs(10));
+
+    p2vec::close_region(directory, RegionPos::new(0, 0)).unwrap();
+}
+
+#[test]
+fn concurrent_closers_that_give_up() {
+    let directory = get_directory("close-concurrent");
+
+    for x in 0..WRITERS {
+        p2vec::write_chunk(
+            directory,
+            ChunkPos::new(x, 0),
+            1,
+            &get_payload(x as u32),
+            3,
+            0,
+        )
+        .unwrap();
+    }
+
+    p2vec::set_close_timeout(directory, Duration::ZERO);
+
+    let done = Arc::new(AtomicBool::new(false));
+
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let done = done.clone();
+
+            thread::spawn(move || {
+                let coords: Vec<ChunkPos> = (0..WRITERS).map(|x| ChunkPos::new(x, 0)).collect();
+
+                while !done.load(Ordering::Acquire) {
+                    for data in p2vec::read_chunks(directory, &coords) {
+                        if let Some(data) = data.unwrap() {
+                            check_payload(&data);
+                        }
+                    }
+                }
+            })
+        })
+        .collect();
+
+    // Closers wait on each other, and one that gives up must neither hang the others nor let a second copy of the region be opened
+    let closers: Vec<_> = (0..3)
+        .map(|_| {
+            thread::spawn(move || {
+                for _ in 0..20000 {
+                    if let Err(error) = p2vec::close_region(directory, RegionPos::new(0, 0)) {
+                        assert_eq!(error.kind(), std::io::ErrorKind::WouldBlock);
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for closer in closers {
+        closer.join().unwrap();
+    }
+
+    done.store(true, Ordering::Release);
+
+    for reader in readers {
+        reader.join().unwrap();
+    }
+
+    p2vec::set_close_timeout(directory, Duration::from_secs(10));
+
+    p2vec::close_region(directory, RegionPos::new(0, 0)).unwrap();
+
+    for x in 0..WRITERS {
+        check_payload(
+            &p2vec::read_chunk(directory, ChunkPos::new(x, 0))
+                .unwrap()
+                .unwrap(),
+        );
+    }
+
+    p2vec::close_region(directory, RegionPos::new(0, 0)).unwrap();
+}
@@ -0,0 +1,435 @@
+use p2vec::{AccessMode, ChunkPos, RegionPos};
+
+// Same as in tests/golden.rs, the McRegion fixture is written by tests/fixtures/generator
+const TIMESTAMP: u32 = 1680000000;
+
+macro_rules! fixture {
+    ($name:literal) => {
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/", $name)
+    };
+}
+
+// Has to match payload in GenerateFixtures.java
+fn get_payload(seed: u8, length: usize) -> Vec<u8> {
+    (0..length)
+        .map(|index| (index % 251) as u8 ^ seed)
+        .collect()
+}
+
+// Has to match noise in GenerateFixtures.java
+fn get_noise(seed: u64, length: usize) -> Vec<u8> {
+    let mut state = seed | 1;
+
+    (0..length)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+
+            state as u8
+        })
+        .collect()
+}
+
+fn get_directory(name: &str) -> &'static str {
+    let directory =
+        std::env::temp_dir().join(format!("p2vec-convert-{}-{}", name, std::process::id()));
+
+    let _ = std::fs::remove_dir_all(&directory);
+
+    Box::leak(directory.to_string_lossy().into_owned().into_boxed_str())
+}
+
+fn get_mcregion_chunks() -> [(ChunkPos, Vec<u8>); 2] {
+    [
+        (ChunkPos::new(-32, 0), get_payload(8, 12000)),
+        (ChunkPos::new(-1, 5), get_noise(9, 10000)),
+    ]
+}
+
+#[test]
+fn reads_mcregion_fixture() {
+    let directory = fixture!("mcregion");
+
+    p2vec::set_access_mode(directory, AccessMode::ReadOnly).unwrap();
+
+    for (coords, payload) in get_mcregion_chunks() {
+        assert!(p2vec::read_legacy_chunk(directory, coords).unwrap() == Some(payload));
+    }
+
+    assert_eq!(
+        p2vec::read_legacy_chunk(directory, ChunkPos::new(-2, 0)).unwrap(),
+        None
+    );
+
+    // There is no Anvil file next to it
+    assert_eq!(
+        p2vec::read_chunk(directory, ChunkPos::new(-32, 0)).unwrap(),
+        None
+    );
+
+    p2vec::close_regions(directory).unwrap();
+}
+
+#[test]
+fn upgrades_mcregion_to_anvil() {
+    let directory = get_directory("mcregion");
+
+    std::fs::create_dir_all(directory).unwrap();
+    std::fs::copy(
+        concat!(fixture!("mcregion"), "/r.-1.0.mcr"),
+        format!("{}/r.-1.0.mcr", directory),
+    )
+    .unwrap();
+
+    // Already in the Anvil file, so the upgrade has to leave it alone
+    let newer = ChunkPos::new(-1, 5);
+
+    p2vec::write_chunk(directory, newer, TIMESTAMP + 5000, b"newer", 3, 0).unwrap();
+
+    assert_eq!(
+        p2vec::upgrade_legacy_region(directory, RegionPos::new(-1, 0), 2, 6).unwrap(),
+        1
+    );
+
+    // Running it again after it finished, or after it was interrupted, doesn't copy anything twice
+    assert_eq!(
+        p2vec::upgrade_legacy_region(directory, RegionPos::new(-1, 0), 2, 6).unwrap(),
+        0
+    );
+
+    p2vec::close_regions(directory).unwrap();
+
+    let (coords, payload) = &get_mcregion_chunks()[0];
+
+    assert!(p2vec::read_chunk(directory, *coords).unwrap().as_ref() == Some(payload));
+    assert_eq!(
+        p2vec::read_chunk_timestamp(directory, *coords).unwrap(),
+        TIMESTAMP + coords.local().index() as u32
+    );
+
+    assert_eq!(
+        p2vec::read_chunk(directory, newer).unwrap(),
+        Some(b"newer".to_vec())
+    );
+    assert_eq!(
+        p2vec::read_chunk_timestamp(directory, newer).unwrap(),
+        TIMESTAMP + 5000
+    );
+
+    // The McRegion file is only ever read
+    assert!(
+        std::fs::read(format!("{}/r.-1.0.mcr", directory)).unwrap()
+            == std::fs::read(concat!(fixture!("mcregion"), "/r.-1.0.mcr")).unwrap()
+    );
+
+    p2vec::close_regions(directory).unwrap();
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn upgrade_keeps_anvil_chunks_with_timestamp_zero() {
+    let directory = get_directory("mcregion-zero");
+
+    std::fs::create_dir_all(directory).unwrap();
+    std::fs::copy(
+        concat!(fixture!("mcregion"), "/r.-1.0.mcr"),
+        format!("{}/r.-1.0.mcr", directory),
+    )
+    .unwrap();
+
+    // Tools that don't set timestamps leave them at 0, the chunk still exists
+    let coords = ChunkPos::new(-32, 0);
+
+    assert!(
+        p2vec::write_chunk_if_unchanged(directory, coords, 0, 0, b"timestamp zero", 3, 0).unwrap()
+    );
+
+    p2vec::close_regions(directory).unwrap();
+
+    assert_eq!(
+        p2vec::upgrade_legacy_region(directory, RegionPos::new(-1, 0), 2, 6).unwrap(),
+        1
+    );
+
+    p2vec::close_regions(directory).unwrap();
+
+    assert_eq!(
+        p2vec::read_chunk(directory, coords).unwrap(),
+        Some(b"timestamp zero".to_vec())
+    );
+    assert_eq!(p2vec::read_chunk_timestamp(directory, coords).unwrap(), 0);
+
+    let (coords, payload) = &get_mcregion_chunks()[1];
+
+    assert!(p2vec::read_chunk(directory, *coords).unwrap().as_ref() == Some(payload));
+
+    p2vec::close_regions(directory).unwrap();
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn upgrades_every_mcregion_chunk() {
+    let directory = get_directory("mcregion-all");
+
+    std::fs::create_dir_all(directory).unwrap();
+    std::fs::copy(
+        concat!(fixture!("mcregion"), "/r.-1.0.mcr"),
+        format!("{}/r.-1.0.mcr", directory),
+    )
+    .unwrap();
+
+    assert_eq!(
+        p2vec::upgrade_legacy_region(directory, RegionPos::new(-1, 0), 4, 0).unwrap(),
+        2
+    );
+
+    p2vec::close_regions(directory).unwrap();
+
+    let header = p2vec::read_region_header(directory, RegionPos::new(-1, 0))
+        .unwrap()
+        .unwrap();
+
+    for (coords, payload) in get_mcregion_chunks() {
+        assert!(p2vec::read_chunk(directory, coords).unwrap() == Some(payload));
+        assert_eq!(
+            header.timestamp(coords.local()),
+            TIMESTAMP + coords.local().index() as u32
+        );
+    }
+
+    // Only the two chunks that were in the McRegion file
+    assert_eq!(
+        p2vec::LocalPos::all()
+            .filter(|local| !header.location(*local).is_empty())
+            .count(),
+        2
+    );
+
+    p2vec::close_regions(directory).unwrap();
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+// Chunks of every compression type, including one big enough to be oversized in Anvil
+fn get_anvil_chunks() -> Vec<(ChunkPos, u32, u8, Vec<u8>)> {
+    vec![
+        (ChunkPos::new(0, 0), TIMESTAMP, 1, get_payload(10, 20000)),
+        (
+            ChunkPos::new(31, 0),
+            TIMESTAMP + 1,
+            2,
+            get_payload(11, 9000),
+        ),
+        (ChunkPos::new(0, 31), TIMESTAMP + 2, 3, get_noise(12, 7000)),
+        (
+            ChunkPos::new(17, 9),
+            TIMESTAMP + 3,
+            4,
+            get_payload(13, 70000),
+        ),
+        (
+            ChunkPos::new(31, 31),
+            TIMESTAMP + 4,
+            3,
+            get_noise(14, 256 * 4096),
+        ),
+    ]
+}
+
+fn write_anvil_chunks(directory: &'static str) {
+    for (coords, timestamp, compression_type, payload) in get_anvil_chunks() {
+        p2vec::write_chunk(directory, coords, timestamp, &payload, compression_type, 6).unwrap();
+    }
+}
+
+fn assert_anvil_chunks(directory: &'static str) {
+    for (coords, timestamp, _, payload) in get_anvil_chunks() {
+        assert!(p2vec::read_chunk(directory, coords).unwrap() == Some(payload));
+        assert_eq!(
+            p2vec::read_chunk_timestamp(directory, coords).unwrap(),
+            timestamp
+        );
+    }
+
+    assert_eq!(
+        p2vec::read_chunk(directory, ChunkPos::new(1, 0)).unwrap(),
+        None
+    );
+}
+
+#[cfg(feature = "linear")]
+#[test]
+fn anvil_linear_anvil_round_trip() {
+    let anvil_directory = get_directory("anvil");
+    let linear_directory = get_directory("linear");
+    let imported_directory = get_directory("imported");
+
+    write_anvil_chunks(anvil_directory);
+
+    assert_eq!(
+        p2vec::export_linear_region(anvil_directory, RegionPos::new(0, 0), linear_directory, 3)
+            .unwrap(),
+        5
+    );
+    assert!(std::path::Path::new(&format!("{}/r.0.0.linear", linear_directory)).is_file());
+
+    // Served straight from the Linear file
+    p2vec::set_linear_backend(linear_directory, true);
+
+    assert_anvil_chunks(linear_directory);
+
+    p2vec::set_linear_backend(linear_directory, false);
+
+    assert_eq!(
+        p2vec::import_linear_region(
+            imported_directory,
+            RegionPos::new(0, 0),
+            linear_directory,
+            2,
+            6
+        )
+        .unwrap(),
+        5
+    );
+
+    // Nothing in the Linear file is newer than what was imported
+    assert_eq!(
+        p2vec::import_linear_region(
+            imported_directory,
+            RegionPos::new(0, 0),
+            linear_directory,
+            2,
+            6
+        )
+        .unwrap(),
+        0
+    );
+
+    p2vec::close_regions(imported_directory).unwrap();
+
+    assert_anvil_chunks(imported_directory);
+
+    for directory in [anvil_directory, imported_directory] {
+        p2vec::close_regions(directory).unwrap();
+    }
+
+    for directory in [anvil_directory, linear_directory, imported_directory] {
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
+
+#[cfg(feature = "linear")]
+#[test]
+fn linear_conversion_uses_the_memory_backend() {
+    let anvil_directory = get_directory("memory-anvil");
+    let linear_directory = get_directory("memory-linear");
+    let imported_directory = get_directory("memory-imported");
+
+    for directory in [anvil_directory, linear_directory, imported_directory] {
+        p2vec::set_memory_backend(directory, true).unwrap();
+    }
+
+    write_anvil_chunks(anvil_directory);
+
+    assert_eq!(
+        p2vec::export_linear_region(anvil_directory, RegionPos::new(0, 0), linear_directory, 3)
+            .unwrap(),
+        5
+    );
+
+    p2vec::set_linear_backend(linear_directory, true);
+
+    assert_anvil_chunks(linear_directory);
+
+    p2vec::set_linear_backend(linear_directory, false);
+
+    assert_eq!(
+        p2vec::import_linear_region(
+            imported_directory,
+            RegionPos::new(0, 0),
+            linear_directory,
+            2,
+            6
+        )
+        .unwrap(),
+        5
+    );
+
+    assert_anvil_chunks(imported_directory);
+
+    for directory in [anvil_directory, linear_directory, imported_directory] {
+        assert!(!std::path::Path::new(directory).exists());
+
+        p2vec::set_memory_backend(directory, false).unwrap();
+    }
+}
+
+// Lays out a Linear region around data the way export_linear_region does, whatever the data says
+#[cfg(feature = "linear")]
+fn write_linear_file(directory: &str, data: &[u8]) {
+    let compressed_data = zstd::stream::encode_all(data, 1).unwrap();
+
+    let mut file_data = Vec::new();
+
+    file_data.extend_from_slice(&0xc3ff13183cca9d9au64.to_be_bytes());
+    file_data.push(1);
+    file_data.extend_from_slice(&[0; 11]);
+    file_data.extend_from_slice(&(compressed_data.len() as u32).to_be_bytes());
+    file_data.extend_from_slice(&[0; 8]);
+    file_data.extend_from_slice(&compressed_data);
+    file_data.extend_from_slice(&0xc3ff13183cca9d9au64.to_be_bytes());
+
+    std::fs::create_dir_all(directory).unwrap();
+    std::fs::write(format!("{}/r.0.0.linear", directory), file_data).unwrap();
+}
+
+#[cfg(feature = "linear")]
+#[test]
+fn linear_regions_only_decompress_what_their_header_lists() {
+    let directory = get_directory("linear-bomb");
+
+    p2vec::set_linear_backend(directory, true);
+
+    // A 100 byte chunk at 0 0
+    let mut header = vec![0; 8 * 1024];
+
+    header[0..4].copy_from_slice(&100u32.to_be_bytes());
+
+    let mut data = header.clone();
+
+    data.extend_from_slice(&[7; 100]);
+
+    write_linear_file(directory, &data);
+
+    assert_eq!(
+        p2vec::read_chunk(directory, ChunkPos::new(0, 0)).unwrap(),
+        Some(vec![7; 100])
+    );
+
+    p2vec::close_regions(directory).unwrap();
+
+    let mut too_big = header.clone();
+
+    too_big[0..4].copy_from_slice(&((16u32 << 20) + 1).to_be_bytes());
+
+    // Much more than the header lists, too short for it, and a chunk bigger than any export writes
+    for data in [
+        [data.as_slice(), &vec![0; 64 << 20]].concat(),
+        data[..8 * 1024 + 10].to_vec(),
+        too_big,
+    ] {
+        write_linear_file(directory, &data);
+
+        assert_eq!(
+            p2vec::read_chunk(directory, ChunkPos::new(0, 0))
+                .unwrap_err()
+                .kind(),
+            std::io::ErrorKind::InvalidData
+        );
+
+        p2vec::close_regions(directory).unwrap();
+    }
+
+    p2vec::set_linear_backend(directory, false);
+    std::fs::remove_dir_all(directory).unwrap();
+}
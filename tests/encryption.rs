@@ -0,0 +1,261 @@
+use std::io::ErrorKind;
+use std::path::Path;
+
+use p2vec::{ChunkPos, RegionPos};
+
+const KEY_1: (u32, [u8; 32]) = (1, [1; 32]);
+const KEY_2: (u32, [u8; 32]) = (2, [2; 32]);
+const KEY_3: (u32, [u8; 32]) = (3, [3; 32]);
+
+fn get_directory(name: &str) -> &'static str {
+    let directory =
+        std::env::temp_dir().join(format!("p2vec-encryption-{}-{}", name, std::process::id()));
+
+    let _ = std::fs::remove_dir_all(&directory);
+
+    Box::leak(directory.to_string_lossy().into_owned().into_boxed_str())
+}
+
+fn get_payload(seed: u32, length: usize) -> Vec<u8> {
+    (0..length)
+        .map(|index| (index as u32 ^ seed.wrapping_mul(2654435761)) as u8)
+        .collect()
+}
+
+fn get_region_path(directory: &str) -> std::path::PathBuf {
+    Path::new(directory).join("r.0.0.mca")
+}
+
+// Writes one uncompressed chunk at (0, 0) under KEY_1 and returns the offset of its stored data in the region file and its length
+fn write_encrypted_chunk(directory: &'static str) -> (usize, usize) {
+    p2vec::set_encryption_keys(directory, &[KEY_1], Some(KEY_1.0)).unwrap();
+    p2vec::write_chunk(
+        directory,
+        ChunkPos::new(0, 0),
+        1,
+        &get_payload(0, 3000),
+        3,
+        0,
+    )
+    .unwrap();
+    p2vec::close_region(directory, RegionPos::new(0, 0)).unwrap();
+
+    let region = std::fs::read(get_region_path(directory)).unwrap();
+
+    let offset = u32::from_be_bytes([0, region[0], region[1], region[2]]) as usize * 4096;
+    let length = u32::from_be_bytes(region[offset..offset + 4].try_into().unwrap()) as usize - 1;
+
+    // Encrypted and uncompressed
+    assert_eq!(region[offset + 4], 64 | 3);
+
+    (offset + 5, length)
+}
+
+fn flip_byte(directory: &str, position: usize) {
+    let path = get_region_path(directory);
+
+    let mut region = std::fs::read(&path).unwrap();
+
+    region[position] ^= 1;
+
+    std::fs::write(&path, region).unwrap();
+}
+
+fn clean_up(directory: &'static str) {
+    p2vec::close_regions(directory).unwrap();
+    p2vec::set_encryption_keys(directory, &[], None).unwrap();
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn encrypted_chunks_round_trip() {
+    let directory = get_directory("round-trip");
+
+    write_encrypted_chunk(directory);
+
+    // The payload is not stored in the clear
+    let region = std::fs::read(get_region_path(directory)).unwrap();
+
+    assert!(!region
+        .windows(64)
+        .any(|window| window == &get_payload(0, 3000)[1000..1064]));
+
+    assert_eq!(
+        p2vec::read_chunk(directory, ChunkPos::new(0, 0)).unwrap(),
+        Some(get_payload(0, 3000))
+    );
+
+    clean_up(directory);
+}
+
+#[test]
+fn tampered_ciphertext_is_rejected() {
+    let directory = get_directory("ciphertext");
+
+    let (data_offset, _) = write_encrypted_chunk(directory);
+
+    // Past the key id and nonce
+    flip_byte(directory, data_offset + 4 + 12 + 100);
+
+    assert_eq!(
+        p2vec::read_chunk(directory, ChunkPos::new(0, 0))
+            .unwrap_err()
+            .kind(),
+        ErrorKind::InvalidData
+    );
+
+    clean_up(directory);
+}
+
+#[test]
+fn tampered_tag_is_rejected() {
+    let directory = get_directory("tag");
+
+    let (data_offset, length) = write_encrypted_chunk(directory);
+
+    flip_byte(directory, data_offset + length - 1);
+
+    assert_eq!(
+        p2vec::read_chunk(directory, ChunkPos::new(0, 0))
+            .unwrap_err()
+            .kind(),
+        ErrorKind::InvalidData
+    );
+
+    clean_up(directory);
+}
+
+#[test]
+fn tampered_nonce_is_rejected() {
+    let directory = get_directory("nonce");
+
+    let (data_offset, _) = write_encrypted_chunk(directory);
+
+    flip_byte(directory, data_offset + 4);
+
+    assert_eq!(
+        p2vec::read_chunk(directory, ChunkPos::new(0, 0))
+            .unwrap_err()
+            .kind(),
+        ErrorKind::InvalidData
+    );
+
+    clean_up(directory);
+}
+
+#[test]
+fn moved_chunks_are_rejected() {
+    let directory = get_directory("moved");
+
+    write_encrypted_chunk(directory);
+
+    // Points (1, 0) at the data of (0, 0)
+    let path = get_region_path(directory);
+
+    let mut region = std::fs::read(&path).unwrap();
+
+    region.copy_within(0..4, 4);
+    region.copy_within(4096..4100, 4100);
+
+    std::fs::write(&path, region).unwrap();
+
+    assert_eq!(
+        p2vec::read_chunk(directory, ChunkPos::new(1, 0))
+            .unwrap_err()
+            .kind(),
+        ErrorKind::InvalidData
+    );
+    assert_eq!(
+        p2vec::read_chunk(directory, ChunkPos::new(0, 0)).unwrap(),
+        Some(get_payload(0, 3000))
+    );
+
+    clean_up(directory);
+}
+
+#[test]
+fn unknown_key_is_permission_denied() {
+    let directory = get_directory("unknown-key");
+
+    write_encrypted_chunk(directory);
+
+    p2vec::set_encryption_keys(directory, &[KEY_2], Some(KEY_2.0)).unwrap();
+
+    assert_eq!(
+        p2vec::read_chunk(directory, ChunkPos::new(0, 0))
+            .unwrap_err()
+            .kind(),
+        ErrorKind::PermissionDenied
+    );
+
+    p2vec::set_encryption_keys(directory, &[], None).unwrap();
+
+    assert_eq!(
+        p2vec::read_chunk(directory, ChunkPos::new(0, 0))
+            .unwrap_err()
+            .kind(),
+        ErrorKind::PermissionDenied
+    );
+
+    clean_up(directory);
+}
+
+#[test]
+fn interrupted_reencryption_resumes() {
+    let directory = get_directory("reencrypt");
+
+    // Chunks are reencrypted in location table order, so the one under KEY_2 comes after the others
+    p2vec::set_encryption_keys(directory, &[KEY_1, KEY_2], Some(KEY_1.0)).unwrap();
+
+    for x in 0..4 {
+        p2vec::write_chunk(
+            directory,
+            ChunkPos::new(x, 0),
+            100 + x as u32,
+            &get_payload(x as u32, 2000),
+            2,
+            6,
+        )
+        .unwrap();
+    }
+
+    p2vec::set_encryption_keys(directory, &[KEY_1, KEY_2], Some(KEY_2.0)).unwrap();
+    p2vec::write_chunk(
+        directory,
+        ChunkPos::new(4, 0),
+        104,
+        &get_payload(4, 2000),
+        2,
+        6,
+    )
+    .unwrap();
+
+    // KEY_2 is missing, so the pass stops at the last chunk after rewriting the others
+    p2vec::set_encryption_keys(directory, &[KEY_1, KEY_3], Some(KEY_3.0)).unwrap();
+
+    assert_eq!(
+        p2vec::reencrypt_regions(directory).unwrap_err().kind(),
+        ErrorKind::PermissionDenied
+    );
+
+    p2vec::set_encryption_keys(directory, &[KEY_1, KEY_2, KEY_3], Some(KEY_3.0)).unwrap();
+
+    assert_eq!(p2vec::reencrypt_regions(directory).unwrap(), 1);
+    assert_eq!(p2vec::reencrypt_regions(directory).unwrap(), 0);
+
+    // Everything only needs the new key now
+    p2vec::set_encryption_keys(directory, &[KEY_3], Some(KEY_3.0)).unwrap();
+
+    for x in 0..5 {
+        assert_eq!(
+            p2vec::read_chunk(directory, ChunkPos::new(x, 0)).unwrap(),
+            Some(get_payload(x as u32, 2000))
+        );
+        assert_eq!(
+            p2vec::read_chunk_timestamp(directory, ChunkPos::new(x, 0)).unwrap(),
+            100 + x as u32
+        );
+    }
+
+    clean_up(directory);
+}
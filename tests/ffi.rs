@@ -0,0 +1,343 @@
+#![cfg(feature = "ffi")]
+
+use std::ffi::{CStr, CString};
+use std::ptr;
+
+use p2vec::ffi::*;
+
+fn get_directory(name: &str) -> CString {
+    let directory = std::env::temp_dir().join(format!("p2vec-ffi-{}-{}", name, std::process::id()));
+
+    let _ = std::fs::remove_dir_all(&directory);
+
+    CString::new(directory.to_string_lossy().into_owned()).unwrap()
+}
+
+fn get_payload(seed: u32, length: usize) -> Vec<u8> {
+    (0..length)
+        .map(|index| (index as u32 ^ seed.wrapping_mul(2654435761)) as u8)
+        .collect()
+}
+
+fn get_last_error() -> String {
+    let message = p2vec_last_error_message();
+
+    assert!(!message.is_null());
+
+    unsafe { CStr::from_ptr(message) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn open_world(directory: &CString) -> *mut P2vecWorld {
+    let mut world = ptr::null_mut();
+
+    assert_eq!(
+        unsafe { p2vec_open_world(directory.as_ptr(), &mut world) },
+        P2VEC_OK
+    );
+    assert!(!world.is_null());
+
+    world
+}
+
+unsafe fn read_chunk(world: *const P2vecWorld, dimension: u32, x: i32, z: i32) -> Option<Vec<u8>> {
+    let mut data = ptr::null_mut();
+    let mut length = 0;
+
+    assert_eq!(
+        p2vec_read_chunk(
+            world,
+            dimension,
+            P2VEC_REGION_KIND_REGION,
+            x,
+            z,
+            &mut data,
+            &mut length
+        ),
+        P2VEC_OK
+    );
+
+    if data.is_null() {
+        assert_eq!(length, 0);
+
+        return None;
+    }
+
+    let chunk = std::slice::from_raw_parts(data, length).to_vec();
+
+    p2vec_free_buffer(data, length);
+
+    Some(chunk)
+}
+
+#[test]
+fn checked_in_header_is_up_to_date() {
+    assert!(
+        include_str!(concat!(env!("OUT_DIR"), "/p2vec.h"))
+            == include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/include/p2vec.h")),
+        "include/p2vec.h is out of date, run cbindgen --config cbindgen.toml --output include/p2vec.h"
+    );
+}
+
+#[test]
+fn chunks_round_trip() {
+    let directory = get_directory("round-trip");
+
+    let world = open_world(&directory);
+
+    unsafe {
+        for (dimension, compression_type) in [
+            (P2VEC_DIMENSION_OVERWORLD, 2),
+            (P2VEC_DIMENSION_NETHER, 4),
+            (P2VEC_DIMENSION_END, 3),
+        ] {
+            let payload = get_payload(dimension, 5000);
+
+            assert_eq!(
+                p2vec_write_chunk(
+                    world,
+                    dimension,
+                    P2VEC_REGION_KIND_REGION,
+                    -3,
+                    40,
+                    7,
+                    payload.as_ptr(),
+                    payload.len(),
+                    compression_type,
+                    6,
+                ),
+                P2VEC_OK
+            );
+
+            assert_eq!(read_chunk(world, dimension, -3, 40), Some(payload));
+            assert_eq!(read_chunk(world, dimension, -3, 41), None);
+        }
+
+        // An empty chunk doesn't need a data pointer
+        assert_eq!(
+            p2vec_write_chunk(
+                world,
+                P2VEC_DIMENSION_OVERWORLD,
+                P2VEC_REGION_KIND_REGION,
+                0,
+                0,
+                1,
+                ptr::null(),
+                0,
+                3,
+                0,
+            ),
+            P2VEC_OK
+        );
+        assert_eq!(
+            read_chunk(world, P2VEC_DIMENSION_OVERWORLD, 0, 0),
+            Some(Vec::new())
+        );
+
+        assert_eq!(p2vec_flush_world(world, P2VEC_DURABILITY_SYNC), P2VEC_OK);
+        assert_eq!(p2vec_close_world(world), P2VEC_OK);
+    }
+
+    // Everything made it to disk, not only into the handle
+    let world = open_world(&directory);
+
+    unsafe {
+        assert_eq!(
+            read_chunk(world, P2VEC_DIMENSION_NETHER, -3, 40),
+            Some(get_payload(P2VEC_DIMENSION_NETHER, 5000))
+        );
+        assert_eq!(p2vec_close_world(world), P2VEC_OK);
+    }
+
+    std::fs::remove_dir_all(directory.to_str().unwrap()).unwrap();
+}
+
+#[test]
+fn null_arguments_are_rejected() {
+    let directory = get_directory("null");
+
+    let mut world = ptr::null_mut();
+    let mut data = ptr::null_mut();
+    let mut length = 0;
+
+    unsafe {
+        assert_eq!(
+            p2vec_open_world(ptr::null(), &mut world),
+            P2VEC_ERROR_INVALID_ARGUMENT
+        );
+        assert!(world.is_null());
+        assert_eq!(
+            p2vec_open_world(directory.as_ptr(), ptr::null_mut()),
+            P2VEC_ERROR_INVALID_ARGUMENT
+        );
+
+        assert_eq!(
+            p2vec_read_chunk(
+                ptr::null(),
+                P2VEC_DIMENSION_OVERWORLD,
+                P2VEC_REGION_KIND_REGION,
+                0,
+                0,
+                &mut data,
+                &mut length,
+            ),
+            P2VEC_ERROR_INVALID_ARGUMENT
+        );
+        assert!(!get_last_error().is_empty());
+
+        assert_eq!(
+            p2vec_write_chunk(
+                ptr::null(),
+                P2VEC_DIMENSION_OVERWORLD,
+                P2VEC_REGION_KIND_REGION,
+                0,
+                0,
+                1,
+                [0u8].as_ptr(),
+                1,
+                3,
+                0,
+            ),
+            P2VEC_ERROR_INVALID_ARGUMENT
+        );
+        assert_eq!(
+            p2vec_flush_world(ptr::null(), P2VEC_DURABILITY_ASYNC),
+            P2VEC_ERROR_INVALID_ARGUMENT
+        );
+
+        let world = open_world(&directory);
+
+        assert_eq!(
+            p2vec_read_chunk(
+                world,
+                P2VEC_DIMENSION_OVERWORLD,
+                P2VEC_REGION_KIND_REGION,
+                0,
+                0,
+                ptr::null_mut(),
+                &mut length,
+            ),
+            P2VEC_ERROR_INVALID_ARGUMENT
+        );
+        assert_eq!(
+            p2vec_read_chunk(
+                world,
+                P2VEC_DIMENSION_OVERWORLD,
+                P2VEC_REGION_KIND_REGION,
+                0,
+                0,
+                &mut data,
+                ptr::null_mut(),
+            ),
+            P2VEC_ERROR_INVALID_ARGUMENT
+        );
+
+        // Data can only be null when there is nothing to write
+        assert_eq!(
+            p2vec_write_chunk(
+                world,
+                P2VEC_DIMENSION_OVERWORLD,
+                P2VEC_REGION_KIND_REGION,
+                0,
+                0,
+                1,
+                ptr::null(),
+                10,
+                3,
+                0,
+            ),
+            P2VEC_ERROR_INVALID_ARGUMENT
+        );
+
+        // Null is fine for the functions that free
+        p2vec_free_buffer(ptr::null_mut(), 0);
+
+        assert_eq!(p2vec_close_world(ptr::null_mut()), P2VEC_OK);
+        assert_eq!(p2vec_close_world(world), P2VEC_OK);
+    }
+
+    // None of the rejected calls wrote anything
+    assert!(!std::path::Path::new(directory.to_str().unwrap()).exists());
+}
+
+#[test]
+fn invalid_values_are_rejected() {
+    let directory = get_directory("invalid");
+
+    let world = open_world(&directory);
+
+    let payload = get_payload(0, 100);
+
+    let mut data = ptr::null_mut();
+    let mut length = 0;
+
+    unsafe {
+        for (dimension, kind) in [
+            (3, P2VEC_REGION_KIND_REGION),
+            (u32::MAX, P2VEC_REGION_KIND_REGION),
+            (P2VEC_DIMENSION_OVERWORLD, 3),
+        ] {
+            assert_eq!(
+                p2vec_read_chunk(world, dimension, kind, 0, 0, &mut data, &mut length),
+                P2VEC_ERROR_INVALID_ARGUMENT
+            );
+            assert_eq!(
+                p2vec_write_chunk(
+                    world,
+                    dimension,
+                    kind,
+                    0,
+                    0,
+                    1,
+                    payload.as_ptr(),
+                    payload.len(),
+                    3,
+                    0,
+                ),
+                P2VEC_ERROR_INVALID_ARGUMENT
+            );
+            assert_eq!(get_last_error(), "Invalid dimension or region kind");
+        }
+
+        for compression_type in [0, 5, 255] {
+            assert_eq!(
+                p2vec_write_chunk(
+                    world,
+                    P2VEC_DIMENSION_OVERWORLD,
+                    P2VEC_REGION_KIND_REGION,
+                    0,
+                    0,
+                    1,
+                    payload.as_ptr(),
+                    payload.len(),
+                    compression_type,
+                    0,
+                ),
+                P2VEC_ERROR_INVALID_ARGUMENT
+            );
+        }
+
+        assert_eq!(p2vec_flush_world(world, 3), P2VEC_ERROR_INVALID_ARGUMENT);
+
+        assert_eq!(p2vec_close_world(world), P2VEC_OK);
+    }
+
+    assert!(!std::path::Path::new(directory.to_str().unwrap()).exists());
+}
+
+#[test]
+fn last_error_is_per_thread() {
+    unsafe {
+        assert_eq!(
+            p2vec_open_world(ptr::null(), ptr::null_mut()),
+            P2VEC_ERROR_INVALID_ARGUMENT
+        );
+    }
+
+    assert_eq!(get_last_error(), "Path and world must not be null");
+
+    assert!(std::thread::spawn(|| p2vec_last_error_message().is_null())
+        .join()
+        .unwrap());
+}
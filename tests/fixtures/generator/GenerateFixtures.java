@@ -0,0 +1,201 @@
+import java.io.ByteArrayOutputStream;
+import java.io.IOException;
+import java.io.OutputStream;
+import java.io.RandomAccessFile;
+import java.nio.file.Files;
+import java.nio.file.Path;
+import java.util.zip.Checksum;
+import java.util.zip.DeflaterOutputStream;
+import java.util.zip.GZIPOutputStream;
+
+import io.netty.buffer.ByteBuf;
+import io.netty.buffer.Unpooled;
+import io.netty.channel.embedded.EmbeddedChannel;
+import io.netty.handler.codec.compression.Lz4FrameEncoder;
+import net.jpountz.lz4.LZ4Factory;
+import org.apache.commons.codec.digest.XXHash32;
+
+// Writes the golden region files, see tests/fixtures/README.md for where each part comes from and how to run this
+// The payloads have to match get_payload and get_noise in tests/golden.rs
+public class GenerateFixtures {
+    static final int SECTOR_BYTES = 4096;
+    static final int TIMESTAMP = 1680000000;
+
+    static byte[] payload(int seed, int length) {
+        byte[] data = new byte[length];
+
+        for (int i = 0; i < length; i++) {
+            data[i] = (byte) ((i % 251) ^ seed);
+        }
+
+        return data;
+    }
+
+    // A block that compresses followed by one that doesn't, so the LZ4 fixture has both block types
+    static byte[] lz4Payload() {
+        ByteArrayOutputStream output = new ByteArrayOutputStream();
+
+        output.writeBytes(payload(4, 1 << 16));
+        output.writeBytes(noise(4, 70000 - (1 << 16)));
+
+        return output.toByteArray();
+    }
+
+    // Doesn't compress, for the oversized chunk
+    static byte[] noise(long seed, int length) {
+        byte[] data = new byte[length];
+        long state = seed | 1;
+
+        for (int i = 0; i < length; i++) {
+            state ^= state << 13;
+            state ^= state >>> 7;
+            state ^= state << 17;
+            data[i] = (byte) state;
+        }
+
+        return data;
+    }
+
+    static byte[] gzip(byte[] data) throws IOException {
+        ByteArrayOutputStream output = new ByteArrayOutputStream();
+
+        try (OutputStream stream = new GZIPOutputStream(output)) {
+            stream.write(data);
+        }
+
+        return output.toByteArray();
+    }
+
+    static byte[] zlib(byte[] data) throws IOException {
+        ByteArrayOutputStream output = new ByteArrayOutputStream();
+
+        try (OutputStream stream = new DeflaterOutputStream(output)) {
+            stream.write(data);
+        }
+
+        return output.toByteArray();
+    }
+
+    // lz4-java's LZ4BlockOutputStream checksums every block with xxHash32 and this seed, keeping only the lower 28 bits
+    static Checksum lz4BlockChecksum() {
+        XXHash32 hash = new XXHash32(0x9747b28c);
+
+        return new Checksum() {
+            public void update(int value) {
+                hash.update(value);
+            }
+
+            public void update(byte[] data, int offset, int length) {
+                hash.update(data, offset, length);
+            }
+
+            public long getValue() {
+                return hash.getValue() & 0xFFFFFFFL;
+            }
+
+            public void reset() {
+                hash.reset();
+            }
+        };
+    }
+
+    // Netty's Lz4FrameEncoder writes the same LZ4Block framing as lz4-java, with vanilla's default 64KiB blocks
+    static byte[] lz4(byte[] data) {
+        EmbeddedChannel channel = new EmbeddedChannel(
+                new Lz4FrameEncoder(LZ4Factory.fastestInstance(), false, 1 << 16, lz4BlockChecksum()));
+
+        channel.writeOutbound(Unpooled.wrappedBuffer(data));
+        channel.finish();
+
+        ByteArrayOutputStream output = new ByteArrayOutputStream();
+
+        for (ByteBuf buffer = channel.readOutbound(); buffer != null; buffer = channel.readOutbound()) {
+            byte[] bytes = new byte[buffer.readableBytes()];
+            buffer.readBytes(bytes);
+            buffer.release();
+
+            output.writeBytes(bytes);
+        }
+
+        return output.toByteArray();
+    }
+
+    static class Chunk {
+        final int x;
+        final int z;
+        final int compressionType;
+        final byte[] data;
+
+        Chunk(int x, int z, int compressionType, byte[] data) {
+            this.x = x;
+            this.z = z;
+            this.compressionType = compressionType;
+            this.data = data;
+        }
+    }
+
+    static void writeRegion(String directory, int regionX, int regionZ, Chunk... chunks) throws IOException {
+        writeRegion(directory, "mca", regionX, regionZ, chunks);
+    }
+
+    // McRegion files have the same layout, only their chunks are always zlib and never oversized
+    static void writeRegion(String directory, String extension, int regionX, int regionZ, Chunk... chunks)
+            throws IOException {
+        Path directoryPath = Path.of(directory);
+        Files.createDirectories(directoryPath);
+
+        Path path = directoryPath.resolve("r." + regionX + "." + regionZ + "." + extension);
+        Files.deleteIfExists(path);
+
+        try (RandomAccessFile file = new RandomAccessFile(path.toFile(), "rw")) {
+            file.write(new byte[2 * SECTOR_BYTES]);
+
+            int nextSector = 2;
+
+            for (Chunk chunk : chunks) {
+                byte[] stored = chunk.data;
+                int compressionByte = chunk.compressionType;
+
+                int sectors = (stored.length + 5 + SECTOR_BYTES - 1) / SECTOR_BYTES;
+
+                // Same as vanilla, anything that needs 256 sectors or more goes to its own file
+                if (sectors >= 256) {
+                    Files.write(directoryPath.resolve("c." + chunk.x + "." + chunk.z + ".mcc"), stored);
+
+                    stored = new byte[0];
+                    compressionByte |= 128;
+                    sectors = 1;
+                }
+
+                file.seek((long) nextSector * SECTOR_BYTES);
+                file.writeInt(stored.length + 1);
+                file.writeByte(compressionByte);
+                file.write(stored);
+                file.write(new byte[sectors * SECTOR_BYTES - stored.length - 5]);
+
+                int index = (chunk.x & 31) + (chunk.z & 31) * 32;
+
+                file.seek(index * 4L);
+                file.writeInt(nextSector << 8 | sectors);
+                file.seek(SECTOR_BYTES + index * 4L);
+                file.writeInt(TIMESTAMP + index);
+
+                nextSector += sectors;
+            }
+        }
+    }
+
+    public static void main(String[] args) throws IOException {
+        writeRegion("gzip", 0, 0, new Chunk(0, 0, 1, gzip(payload(1, 20000))));
+        writeRegion("zlib", 0, 0, new Chunk(0, 0, 2, zlib(payload(2, 20000))));
+        writeRegion("uncompressed", 0, 0, new Chunk(0, 0, 3, payload(3, 6000)));
+        writeRegion("lz4", 0, 0, new Chunk(0, 0, 4, lz4(lz4Payload())));
+        writeRegion("oversized", 0, 0, new Chunk(0, 0, 3, noise(5, 255 * SECTOR_BYTES)));
+        writeRegion("negative", -1, -1,
+                new Chunk(-1, -1, 2, zlib(payload(6, 9000))),
+                new Chunk(-32, -20, 1, gzip(payload(7, 30000))));
+        writeRegion("mcregion", "mcr", -1, 0,
+                new Chunk(-32, 0, 2, zlib(payload(8, 12000))),
+                new Chunk(-1, 5, 2, zlib(noise(9, 10000))));
+    }
+}
@@ -0,0 +1,76 @@
+import java.io.ByteArrayInputStream;
+import java.io.IOException;
+import java.io.InputStream;
+import java.nio.ByteBuffer;
+import java.nio.file.Files;
+import java.nio.file.Path;
+import java.util.zip.CRC32;
+import java.util.zip.GZIPInputStream;
+import java.util.zip.InflaterInputStream;
+
+// Lists every chunk of a region file as "x z timestamp length crc32", with the length and CRC32 of the decompressed
+// data, for tests/fixtures/server/*/chunks.txt. Only the JDK reads the file, so tests/golden.rs checks p2vec against a
+// reader that shares none of its code. See tests/fixtures/README.md for how to run this
+public class ListRegion {
+    static final int SECTOR_BYTES = 4096;
+
+    static InputStream decompress(byte compressionType, byte[] data) throws IOException {
+        return switch (compressionType & 127) {
+            case 1 -> new GZIPInputStream(new ByteArrayInputStream(data));
+            case 2 -> new InflaterInputStream(new ByteArrayInputStream(data));
+            case 3 -> new ByteArrayInputStream(data);
+            default -> throw new IOException("Unsupported compression type " + compressionType);
+        };
+    }
+
+    public static void main(String[] args) throws IOException {
+        Path path = Path.of(args[0]);
+
+        String[] name = path.getFileName().toString().split("\\.");
+
+        int regionX = Integer.parseInt(name[1]);
+        int regionZ = Integer.parseInt(name[2]);
+
+        ByteBuffer region = ByteBuffer.wrap(Files.readAllBytes(path));
+
+        // In the order of the location table, which is x first
+        for (int index = 0; index < 1024; index++) {
+            int location = region.getInt(index * 4);
+
+            if (location == 0) {
+                continue;
+            }
+
+            int x = regionX * 32 + index % 32;
+            int z = regionZ * 32 + index / 32;
+
+            int offset = (location >>> 8) * SECTOR_BYTES;
+            int length = region.getInt(offset);
+            byte compressionType = region.get(offset + 4);
+
+            // Oversized chunks keep only the compression type in the region file, with the flag vanilla sets on it
+            byte[] data = new byte[length - 1];
+
+            if ((compressionType & 128) != 0) {
+                data = Files.readAllBytes(path.resolveSibling("c." + x + "." + z + ".mcc"));
+            } else {
+                region.get(offset + 5, data);
+            }
+
+            CRC32 crc = new CRC32();
+            long decompressedLength = 0;
+
+            try (InputStream input = decompress(compressionType, data)) {
+                byte[] buffer = new byte[SECTOR_BYTES];
+
+                for (int read; (read = input.read(buffer)) != -1; ) {
+                    crc.update(buffer, 0, read);
+                    decompressedLength += read;
+                }
+            }
+
+            System.out.printf("%d %d %d %d %08x%n", x, z, region.getInt(SECTOR_BYTES + index * 4) & 0xFFFFFFFFL,
+                    decompressedLength, crc.getValue());
+        }
+    }
+}
@@ -0,0 +1,35 @@
+package net.jpountz.lz4;
+
+import java.io.ByteArrayOutputStream;
+import java.io.IOException;
+import java.nio.ByteBuffer;
+
+import org.apache.commons.compress.compressors.lz4.BlockLZ4CompressorOutputStream;
+
+// Netty only hands the block compression to lz4-java, the framing around it is Netty's own. The blocks come from
+// Apache Commons Compress instead, so neither half is written by this repository
+public class LZ4Compressor {
+    // LZ4_COMPRESSBOUND from the LZ4 block format
+    public int maxCompressedLength(int length) {
+        return length + length / 255 + 16;
+    }
+
+    public void compress(ByteBuffer source, ByteBuffer destination) {
+        byte[] data = new byte[source.remaining()];
+        source.get(data);
+
+        ByteArrayOutputStream output = new ByteArrayOutputStream();
+
+        try (BlockLZ4CompressorOutputStream stream = new BlockLZ4CompressorOutputStream(output)) {
+            stream.write(data);
+        } catch (IOException exception) {
+            throw new LZ4Exception(exception.getMessage());
+        }
+
+        if (output.size() > destination.remaining()) {
+            throw new LZ4Exception("Compressed block doesn't fit");
+        }
+
+        destination.put(output.toByteArray());
+    }
+}
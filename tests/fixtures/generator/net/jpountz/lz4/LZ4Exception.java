@@ -0,0 +1,9 @@
+package net.jpountz.lz4;
+
+public class LZ4Exception extends RuntimeException {
+    private static final long serialVersionUID = 1L;
+
+    public LZ4Exception(String message) {
+        super(message);
+    }
+}
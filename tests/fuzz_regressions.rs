@@ -0,0 +1,86 @@
+use p2vec::{AccessMode, ChunkPos, RegionPos};
+
+// Inputs the fuzz targets found problems with, replayed the same way fuzz/fuzz_targets/read_region.rs runs them
+const READ_REGION_INPUTS: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/fixtures/fuzz/read_region"
+);
+
+fn read_region(directory: &'static str) {
+    if let Ok(Some(info)) = p2vec::read_region_info(directory, RegionPos::new(0, 0)) {
+        let sector_map = info.get_sector_map();
+
+        let _ = sector_map.get_free_ranges();
+        let _ = sector_map.to_text();
+    }
+
+    let _ = p2vec::verify_region(directory, RegionPos::new(0, 0));
+
+    let coords: Vec<ChunkPos> = RegionPos::new(0, 0).chunks().collect();
+
+    for chunk_coords in coords.iter() {
+        let _ = p2vec::read_chunk(directory, *chunk_coords);
+    }
+
+    let _ = p2vec::read_chunks(directory, &coords);
+
+    p2vec::close_regions(directory).unwrap();
+}
+
+#[test]
+fn read_region_inputs_dont_panic() {
+    for entry in std::fs::read_dir(READ_REGION_INPUTS).unwrap() {
+        let path = entry.unwrap().path();
+
+        let directory = std::env::temp_dir().join(format!(
+            "p2vec-fuzz-regression-{}-{}",
+            path.file_name().unwrap().to_string_lossy(),
+            std::process::id()
+        ));
+
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::copy(&path, directory.join("r.0.0.mca")).unwrap();
+
+        let directory: &'static str =
+            Box::leak(directory.to_string_lossy().into_owned().into_boxed_str());
+
+        p2vec::set_access_mode(directory, AccessMode::ReadOnlyUnlocked).unwrap();
+
+        read_region(directory);
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
+
+#[test]
+fn short_gzip_chunk_is_invalid_data() {
+    let directory = std::env::temp_dir().join(format!(
+        "p2vec-fuzz-regression-short-gzip-{}",
+        std::process::id()
+    ));
+
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    std::fs::copy(
+        format!("{}/gzip_shorter_than_isize", READ_REGION_INPUTS),
+        directory.join("r.0.0.mca"),
+    )
+    .unwrap();
+
+    let directory: &'static str =
+        Box::leak(directory.to_string_lossy().into_owned().into_boxed_str());
+
+    p2vec::set_access_mode(directory, AccessMode::ReadOnlyUnlocked).unwrap();
+
+    assert_eq!(
+        p2vec::read_chunk(directory, ChunkPos::new(0, 0))
+            .unwrap_err()
+            .kind(),
+        std::io::ErrorKind::InvalidData
+    );
+
+    p2vec::close_regions(directory).unwrap();
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
@@ -0,0 +1,449 @@
+use std::collections::HashMap;
+use std::io::Read;
+
+use p2vec::{AccessMode, ChunkPos, LocalPos, RegionPos, SectorRange};
+
+// The fixtures are written by tests/fixtures/generator, see tests/fixtures/README.md for where each one comes from
+const TIMESTAMP: u32 = 1680000000;
+
+const SECTOR_SIZE: usize = 4096;
+
+macro_rules! fixture {
+    ($name:literal) => {
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/", $name)
+    };
+}
+
+// Has to match payload in GenerateFixtures.java
+fn get_payload(seed: u8, length: usize) -> Vec<u8> {
+    (0..length)
+        .map(|index| (index % 251) as u8 ^ seed)
+        .collect()
+}
+
+// Has to match noise in GenerateFixtures.java
+fn get_noise(seed: u64, length: usize) -> Vec<u8> {
+    let mut state = seed | 1;
+
+    (0..length)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+
+            state as u8
+        })
+        .collect()
+}
+
+fn assert_fixture_chunk(directory: &'static str, coords: ChunkPos, expected: &[u8]) {
+    p2vec::set_access_mode(directory, AccessMode::ReadOnly).unwrap();
+
+    // Payloads are too big to print when they differ
+    assert!(
+        p2vec::read_chunk(directory, coords).unwrap().as_deref() == Some(expected),
+        "chunk {:?} in {} differs",
+        coords,
+        directory
+    );
+    assert_eq!(
+        p2vec::read_chunk_timestamp(directory, coords).unwrap(),
+        TIMESTAMP + coords.local().index() as u32
+    );
+
+    p2vec::close_regions(directory).unwrap();
+}
+
+#[test]
+fn reads_gzip_fixture() {
+    assert_fixture_chunk(
+        fixture!("gzip"),
+        ChunkPos::new(0, 0),
+        &get_payload(1, 20000),
+    );
+}
+
+#[test]
+fn reads_zlib_fixture() {
+    assert_fixture_chunk(
+        fixture!("zlib"),
+        ChunkPos::new(0, 0),
+        &get_payload(2, 20000),
+    );
+}
+
+#[test]
+fn reads_uncompressed_fixture() {
+    assert_fixture_chunk(
+        fixture!("uncompressed"),
+        ChunkPos::new(0, 0),
+        &get_payload(3, 6000),
+    );
+}
+
+// Has to match lz4Payload in GenerateFixtures.java
+fn get_lz4_payload() -> Vec<u8> {
+    let mut payload = get_payload(4, 1 << 16);
+
+    payload.extend(get_noise(4, 70000 - (1 << 16)));
+
+    payload
+}
+
+#[test]
+fn reads_lz4_fixture() {
+    assert_fixture_chunk(fixture!("lz4"), ChunkPos::new(0, 0), &get_lz4_payload());
+}
+
+#[test]
+fn reads_oversized_fixture() {
+    assert_fixture_chunk(
+        fixture!("oversized"),
+        ChunkPos::new(0, 0),
+        &get_noise(5, 255 * SECTOR_SIZE),
+    );
+}
+
+#[test]
+fn reads_negative_fixture() {
+    assert_fixture_chunk(
+        fixture!("negative"),
+        ChunkPos::new(-1, -1),
+        &get_payload(6, 9000),
+    );
+    assert_fixture_chunk(
+        fixture!("negative"),
+        ChunkPos::new(-32, -20),
+        &get_payload(7, 30000),
+    );
+
+    let directory = fixture!("negative");
+
+    p2vec::set_access_mode(directory, AccessMode::ReadOnly).unwrap();
+
+    let header = p2vec::read_region_header(directory, RegionPos::new(-1, -1))
+        .unwrap()
+        .unwrap();
+
+    // Both chunks are written right after the header in the order they are listed in GenerateFixtures.java
+    let last = ChunkPos::new(-1, -1).local();
+    let first_column = ChunkPos::new(-32, -20).local();
+
+    assert_eq!(header.location(last), SectorRange::new(2, 1));
+    assert_eq!(header.timestamp(last), TIMESTAMP + 1023);
+    assert_eq!(header.location(first_column).offset, 2 + 1);
+    assert_eq!(header.timestamp(first_column), TIMESTAMP + 12 * 32);
+
+    for local in LocalPos::all().filter(|local| *local != last && *local != first_column) {
+        assert!(header.location(local).is_empty());
+        assert_eq!(header.timestamp(local), 0);
+    }
+
+    assert!(p2vec::read_region_header(directory, RegionPos::new(0, 0))
+        .unwrap()
+        .is_none());
+
+    p2vec::close_regions(directory).unwrap();
+}
+
+// Every fixture in tests/fixtures/server is a directory of region files a server wrote, with the chunks.txt listing
+// generator/ListRegion.java made of them. None has been checked in yet, see tests/fixtures/README.md
+#[test]
+#[ignore = "no server-written region file is checked in yet"]
+fn reads_server_fixtures() {
+    let mut fixtures = 0;
+
+    for entry in std::fs::read_dir(fixture!("server")).expect("tests/fixtures/server is missing") {
+        let directory: &'static str = Box::leak(
+            entry
+                .unwrap()
+                .path()
+                .to_string_lossy()
+                .into_owned()
+                .into_boxed_str(),
+        );
+
+        p2vec::set_access_mode(directory, AccessMode::ReadOnly).unwrap();
+
+        let mut listed = HashMap::new();
+
+        for line in std::fs::read_to_string(format!("{}/chunks.txt", directory))
+            .unwrap()
+            .lines()
+        {
+            let fields: Vec<&str> = line.split(' ').collect();
+
+            listed.insert(
+                ChunkPos::new(fields[0].parse().unwrap(), fields[1].parse().unwrap()),
+                (
+                    fields[2].parse::<u32>().unwrap(),
+                    fields[3].parse::<usize>().unwrap(),
+                    u32::from_str_radix(fields[4], 16).unwrap(),
+                ),
+            );
+        }
+
+        let mut found = 0;
+
+        for file_name in std::fs::read_dir(directory).unwrap() {
+            let file_name = file_name.unwrap().file_name().into_string().unwrap();
+
+            let name: Vec<&str> = file_name.split('.').collect();
+
+            if name.len() != 4 || name[0] != "r" || name[3] != "mca" {
+                continue;
+            }
+
+            let region_coords = RegionPos::new(name[1].parse().unwrap(), name[2].parse().unwrap());
+
+            for coords in region_coords.chunks() {
+                let chunk = p2vec::read_chunk(directory, coords).unwrap();
+
+                let timestamp = match (chunk, listed.get(&coords)) {
+                    (None, None) => continue,
+                    (Some(chunk), Some((timestamp, length, checksum))) => {
+                        let mut crc = flate2::Crc::new();
+
+                        crc.update(&chunk);
+
+                        assert_eq!(
+                            (chunk.len(), crc.sum()),
+                            (*length, *checksum),
+                            "chunk {:?} in {} differs",
+                            coords,
+                            directory
+                        );
+
+                        *timestamp
+                    }
+                    (chunk, _) => panic!(
+                        "chunk {:?} in {} is {} but the listing says otherwise",
+                        coords,
+                        directory,
+                        match chunk {
+                            Some(_) => "there",
+                            None => "missing",
+                        }
+                    ),
+                };
+
+                assert_eq!(
+                    p2vec::read_chunk_timestamp(directory, coords).unwrap(),
+                    timestamp,
+                    "chunk {:?} in {}",
+                    coords,
+                    directory
+                );
+
+                found += 1;
+            }
+        }
+
+        assert_eq!(
+            found,
+            listed.len(),
+            "{} lists chunks of missing regions",
+            directory
+        );
+
+        p2vec::close_regions(directory).unwrap();
+
+        fixtures += 1;
+    }
+
+    assert!(fixtures > 0, "no server-written fixtures are checked in");
+}
+
+fn get_temp_directory(name: &str) -> &'static str {
+    let directory =
+        std::env::temp_dir().join(format!("p2vec-golden-{}-{}", name, std::process::id()));
+
+    let _ = std::fs::remove_dir_all(&directory);
+
+    Box::leak(directory.to_string_lossy().into_owned().into_boxed_str())
+}
+
+fn get_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+// Checks a chunk the way vanilla's RegionFile reads it and returns its compression byte and stored data
+fn read_vanilla_chunk(file: &[u8], coords: ChunkPos) -> (u8, &[u8]) {
+    let index = coords.local().index();
+
+    assert_eq!(file.len() % SECTOR_SIZE, 0, "file isn't padded to a sector");
+
+    let location = get_u32(file, index * 4);
+    let offset = (location >> 8) as usize;
+    let sectors = (location & 255) as usize;
+
+    assert!(offset >= 2, "chunk overlaps the header");
+    assert!(
+        (offset + sectors) * SECTOR_SIZE <= file.len(),
+        "chunk is past the end of the file"
+    );
+
+    assert_eq!(
+        get_u32(file, SECTOR_SIZE + index * 4),
+        TIMESTAMP,
+        "timestamp isn't in the timestamp table"
+    );
+
+    let chunk = &file[offset * SECTOR_SIZE..(offset + sectors) * SECTOR_SIZE];
+
+    let length = get_u32(chunk, 0) as usize;
+
+    assert!(length >= 1, "chunk has no compression byte");
+    assert_eq!(
+        sectors,
+        (length + 4 + SECTOR_SIZE - 1) / SECTOR_SIZE,
+        "chunk doesn't use the fewest sectors"
+    );
+    assert!(
+        chunk[4 + length..].iter().all(|byte| *byte == 0),
+        "padding after the chunk isn't zeroed"
+    );
+
+    (chunk[4], &chunk[5..4 + length])
+}
+
+fn write_and_check(
+    name: &str,
+    compression_type: u8,
+    expected_data: &[u8],
+) -> (&'static str, u8, Vec<u8>) {
+    let directory = get_temp_directory(name);
+    let coords = ChunkPos::new(5, 7);
+
+    p2vec::write_chunk(
+        directory,
+        coords,
+        TIMESTAMP,
+        expected_data,
+        compression_type,
+        6,
+    )
+    .unwrap();
+    p2vec::close_regions(directory).unwrap();
+
+    let file = std::fs::read(format!("{}/r.0.0.mca", directory)).unwrap();
+
+    let (compression_byte, stored_data) = read_vanilla_chunk(&file, coords);
+
+    // Every other entry in both tables is left empty
+    for index in (0..1024).filter(|index| *index != coords.local().index()) {
+        assert_eq!(get_u32(&file, index * 4), 0);
+        assert_eq!(get_u32(&file, SECTOR_SIZE + index * 4), 0);
+    }
+
+    (directory, compression_byte, stored_data.to_vec())
+}
+
+#[test]
+fn writes_gzip_like_vanilla() {
+    let payload = get_payload(1, 20000);
+
+    let (directory, compression_byte, stored_data) = write_and_check("gzip", 1, &payload);
+
+    let mut data = Vec::new();
+    flate2::read::GzDecoder::new(stored_data.as_slice())
+        .read_to_end(&mut data)
+        .unwrap();
+
+    assert_eq!(compression_byte, 1);
+    assert!(data == payload);
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn writes_zlib_like_vanilla() {
+    let payload = get_payload(2, 20000);
+
+    let (directory, compression_byte, stored_data) = write_and_check("zlib", 2, &payload);
+
+    let mut data = Vec::new();
+    flate2::read::ZlibDecoder::new(stored_data.as_slice())
+        .read_to_end(&mut data)
+        .unwrap();
+
+    assert_eq!(compression_byte, 2);
+    assert!(data == payload);
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn writes_uncompressed_like_vanilla() {
+    // Exactly fills its sectors, so there's no padding at all
+    let payload = get_payload(3, 3 * SECTOR_SIZE - 5);
+
+    let (directory, compression_byte, stored_data) = write_and_check("uncompressed", 3, &payload);
+
+    assert_eq!(compression_byte, 3);
+    assert!(stored_data == payload);
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn writes_lz4_like_vanilla() {
+    let payload = get_payload(4, 70000);
+
+    let (directory, compression_byte, stored_data) = write_and_check("lz4", 4, &payload);
+
+    assert_eq!(compression_byte, 4);
+    assert_eq!(&stored_data[..8], b"LZ4Block");
+
+    // Reading it back goes through the same decoder as the lz4 fixture
+    assert!(p2vec::read_chunk(directory, ChunkPos::new(5, 7)).unwrap() == Some(payload));
+    p2vec::close_regions(directory).unwrap();
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn writes_oversized_like_vanilla() {
+    let payload = get_noise(5, 255 * SECTOR_SIZE);
+
+    let (directory, compression_byte, stored_data) = write_and_check("oversized", 3, &payload);
+
+    // Vanilla leaves only the flagged compression byte in the region file
+    assert_eq!(compression_byte, 3 | 128);
+    assert!(stored_data.is_empty());
+    assert!(std::fs::read(format!("{}/c.5.7.mcc", directory)).unwrap() == payload);
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn writes_negative_region_like_vanilla() {
+    let directory = get_temp_directory("negative");
+
+    let chunks = [
+        (ChunkPos::new(-1, -1), get_payload(6, 9000)),
+        (ChunkPos::new(-32, -20), get_payload(7, 30000)),
+    ];
+
+    for (coords, payload) in chunks.iter() {
+        p2vec::write_chunk(directory, *coords, TIMESTAMP, payload, 3, 6).unwrap();
+    }
+
+    p2vec::close_regions(directory).unwrap();
+
+    let file = std::fs::read(format!("{}/r.-1.-1.mca", directory)).unwrap();
+
+    for (coords, payload) in chunks.iter() {
+        let (compression_byte, stored_data) = read_vanilla_chunk(&file, *coords);
+
+        assert_eq!(compression_byte, 3);
+        assert!(stored_data == payload.as_slice());
+    }
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
@@ -0,0 +1,70 @@
+#![cfg(feature = "java")]
+
+use std::path::Path;
+use std::process::Command;
+
+// Runs tests/java/P2vecSmokeTest.java in a JVM against the library cargo built next to this test
+#[test]
+fn java_smoke_test() {
+    if Command::new("javac").arg("-version").output().is_err() {
+        eprintln!("javac isn't installed, skipping the Java smoke test");
+
+        return;
+    }
+
+    let manifest_directory = Path::new(env!("CARGO_MANIFEST_DIR"));
+
+    let library_directory = std::env::current_exe()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .to_path_buf();
+
+    assert!(
+        library_directory
+            .join(format!(
+                "{}p2vec{}",
+                std::env::consts::DLL_PREFIX,
+                std::env::consts::DLL_SUFFIX
+            ))
+            .is_file(),
+        "the cdylib wasn't built next to the test"
+    );
+
+    let directory = std::env::temp_dir().join(format!("p2vec-java-{}", std::process::id()));
+
+    let _ = std::fs::remove_dir_all(&directory);
+
+    let classes = directory.join("classes");
+    let world = directory.join("world");
+
+    std::fs::create_dir_all(&world).unwrap();
+
+    let status = Command::new("javac")
+        .arg("-d")
+        .arg(&classes)
+        .arg(manifest_directory.join("java/com/duplexsystem/p2vec/P2vec.java"))
+        .arg(manifest_directory.join("tests/java/P2vecSmokeTest.java"))
+        .status()
+        .unwrap();
+
+    assert!(status.success(), "javac failed");
+
+    // Checked JNI aborts on misuse such as calling into the JVM with an exception pending
+    let status = Command::new("java")
+        .arg("-Xcheck:jni")
+        .arg(format!(
+            "-Djava.library.path={}",
+            library_directory.display()
+        ))
+        .arg("-cp")
+        .arg(&classes)
+        .arg("P2vecSmokeTest")
+        .arg(&world)
+        .status()
+        .unwrap();
+
+    assert!(status.success(), "the Java smoke test failed");
+
+    std::fs::remove_dir_all(&directory).unwrap();
+}
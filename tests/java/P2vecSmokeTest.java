@@ -0,0 +1,210 @@
+import com.duplexsystem.p2vec.P2vec;
+
+import java.io.IOException;
+import java.io.RandomAccessFile;
+import java.nio.ByteBuffer;
+import java.nio.file.Path;
+import java.util.ArrayList;
+import java.util.List;
+import java.util.concurrent.ExecutorService;
+import java.util.concurrent.Executors;
+import java.util.concurrent.Future;
+
+// Run by tests/java.rs against the library built with the java feature, takes an empty directory to use as the world
+public final class P2vecSmokeTest {
+    private static final int THREADS = 8;
+    private static final int CHUNKS = 64;
+
+    interface Call {
+        void run() throws Exception;
+    }
+
+    public static void main(String[] arguments) throws Exception {
+        Path directory = Path.of(arguments[0]);
+
+        concurrentReadsAndWrites(directory.resolve("concurrent").toString());
+        freeBufferOnlyTakesReadBuffers(directory.resolve("free").toString());
+        invalidArgumentsThrow(directory.resolve("invalid").toString());
+        corruptChunksThrowIOException(directory.resolve("corrupt").toString());
+    }
+
+    // Has to be the same on every thread, so a torn or mixed up chunk is noticed
+    private static ByteBuffer payload(int seed, int length) {
+        ByteBuffer buffer = ByteBuffer.allocateDirect(length);
+
+        for (int index = 0; index < length; index++) {
+            buffer.put((byte) (index * 31 ^ seed * 7));
+        }
+
+        return buffer.flip();
+    }
+
+    private static void check(boolean condition, String message) {
+        if (!condition) {
+            throw new AssertionError(message);
+        }
+    }
+
+    private static void expect(Class<? extends Throwable> expected, Call call) throws Exception {
+        try {
+            call.run();
+        } catch (Throwable throwable) {
+            if (throwable.getClass() != expected) {
+                throw new AssertionError("Expected " + expected.getName() + " but got " + throwable, throwable);
+            }
+
+            return;
+        }
+
+        throw new AssertionError("Expected " + expected.getName());
+    }
+
+    private static void concurrentReadsAndWrites(String path) throws Exception {
+        long world = P2vec.openWorld(path);
+
+        ExecutorService executor = Executors.newFixedThreadPool(THREADS);
+        List<Future<?>> futures = new ArrayList<>();
+
+        // Every thread writes its own chunks and reads everyone's, across two regions and with every compression type
+        for (int thread = 0; thread < THREADS; thread++) {
+            int owner = thread;
+
+            futures.add(executor.submit(() -> {
+                for (int chunk = owner; chunk < CHUNKS; chunk += THREADS) {
+                    int x = chunk * 2 - CHUNKS / 2;
+                    int compressionType = chunk % 4 + 1;
+
+                    ByteBuffer data = payload(chunk, 1000 + chunk * 97);
+
+                    check(P2vec.writeChunk(world, P2vec.DIMENSION_OVERWORLD, P2vec.REGION_KIND_REGION, x, 3, 10, data,
+                            compressionType, 6), "write");
+                    check(!P2vec.writeChunk(world, P2vec.DIMENSION_OVERWORLD, P2vec.REGION_KIND_REGION, x, 3, 10,
+                            payload(0, 10), compressionType, 6), "not newer");
+
+                    for (int other = 0; other < CHUNKS; other++) {
+                        ByteBuffer read = P2vec.readChunk(world, P2vec.DIMENSION_OVERWORLD, P2vec.REGION_KIND_REGION,
+                                other * 2 - CHUNKS / 2, 3);
+
+                        if (read != null) {
+                            check(read.equals(payload(other, 1000 + other * 97)), "chunk " + other);
+
+                            P2vec.freeBuffer(read);
+                        }
+                    }
+
+                    P2vec.flushRegion(world, P2vec.DIMENSION_OVERWORLD, P2vec.REGION_KIND_REGION, x >> 5, 0,
+                            P2vec.DURABILITY_ASYNC);
+                }
+
+                return null;
+            }));
+        }
+
+        for (Future<?> future : futures) {
+            future.get();
+        }
+
+        executor.shutdown();
+
+        P2vec.flushWorld(world, P2vec.DURABILITY_SYNC_DIRECTORY);
+        P2vec.closeWorld(world);
+
+        long reopened = P2vec.openWorld(path);
+
+        for (int chunk = 0; chunk < CHUNKS; chunk++) {
+            int x = chunk * 2 - CHUNKS / 2;
+
+            ByteBuffer read = P2vec.readChunk(reopened, P2vec.DIMENSION_OVERWORLD, P2vec.REGION_KIND_REGION, x, 3);
+
+            check(read.equals(payload(chunk, 1000 + chunk * 97)), "chunk " + chunk + " after reopening");
+            check(P2vec.readChunkTimestamp(reopened, P2vec.DIMENSION_OVERWORLD, P2vec.REGION_KIND_REGION, x, 3)
+                    == 10, "timestamp");
+
+            P2vec.freeBuffer(read);
+        }
+
+        check(P2vec.readChunk(reopened, P2vec.DIMENSION_NETHER, P2vec.REGION_KIND_REGION, 0, 0) == null, "missing");
+
+        P2vec.closeWorld(reopened);
+    }
+
+    private static void freeBufferOnlyTakesReadBuffers(String path) throws Exception {
+        long world = P2vec.openWorld(path);
+
+        P2vec.writeChunk(world, P2vec.DIMENSION_OVERWORLD, P2vec.REGION_KIND_REGION, 0, 0, 1, payload(1, 5000),
+                P2vec.COMPRESSION_LZ4, 0);
+
+        ByteBuffer read = P2vec.readChunk(world, P2vec.DIMENSION_OVERWORLD, P2vec.REGION_KIND_REGION, 0, 0);
+
+        expect(IllegalArgumentException.class, () -> P2vec.freeBuffer(ByteBuffer.allocateDirect(5000)));
+        expect(IllegalArgumentException.class, () -> P2vec.freeBuffer(ByteBuffer.allocate(5000)));
+        expect(IllegalArgumentException.class, () -> P2vec.freeBuffer(read.slice(1, 4999)));
+        expect(IllegalArgumentException.class, () -> P2vec.freeBuffer(read.slice(0, 4999)));
+
+        // Still intact after the rejected frees
+        check(read.equals(payload(1, 5000)), "buffer");
+
+        P2vec.freeBuffer(read);
+
+        expect(IllegalArgumentException.class, () -> P2vec.freeBuffer(read));
+
+        // Empty chunks come back as empty buffers that can be freed as well
+        P2vec.writeChunk(world, P2vec.DIMENSION_OVERWORLD, P2vec.REGION_KIND_REGION, 1, 0, 1, payload(1, 0),
+                P2vec.COMPRESSION_UNCOMPRESSED, 0);
+
+        ByteBuffer empty = P2vec.readChunk(world, P2vec.DIMENSION_OVERWORLD, P2vec.REGION_KIND_REGION, 1, 0);
+
+        check(empty.capacity() == 0, "empty");
+
+        P2vec.freeBuffer(empty);
+        P2vec.freeBuffer(null);
+
+        P2vec.closeWorld(world);
+    }
+
+    private static void invalidArgumentsThrow(String path) throws Exception {
+        long world = P2vec.openWorld(path);
+
+        for (int compressionType : new int[] {0, 5, 257, -1}) {
+            expect(IllegalArgumentException.class, () -> P2vec.writeChunk(world, P2vec.DIMENSION_OVERWORLD,
+                    P2vec.REGION_KIND_REGION, 0, 0, 1, payload(0, 100), compressionType, 0));
+        }
+
+        expect(IllegalArgumentException.class, () -> P2vec.writeChunk(world, 3, P2vec.REGION_KIND_REGION, 0, 0, 1,
+                payload(0, 100), P2vec.COMPRESSION_ZLIB, 0));
+        expect(IllegalArgumentException.class, () -> P2vec.readChunk(world, P2vec.DIMENSION_END, -1, 0, 0));
+        expect(IllegalArgumentException.class, () -> P2vec.writeChunk(world, P2vec.DIMENSION_OVERWORLD,
+                P2vec.REGION_KIND_REGION, 0, 0, 1, ByteBuffer.allocate(100), P2vec.COMPRESSION_ZLIB, 0));
+        expect(IllegalArgumentException.class, () -> P2vec.flushWorld(world, 3));
+        expect(IllegalArgumentException.class, () -> P2vec.readChunk(0, P2vec.DIMENSION_OVERWORLD,
+                P2vec.REGION_KIND_REGION, 0, 0));
+        expect(IllegalArgumentException.class, () -> P2vec.openWorld(null));
+
+        // None of them wrote anything
+        check(P2vec.readChunk(world, P2vec.DIMENSION_OVERWORLD, P2vec.REGION_KIND_REGION, 0, 0) == null, "written");
+
+        P2vec.closeWorld(world);
+        P2vec.closeWorld(0);
+    }
+
+    private static void corruptChunksThrowIOException(String path) throws Exception {
+        long world = P2vec.openWorld(path);
+
+        P2vec.writeChunk(world, P2vec.DIMENSION_OVERWORLD, P2vec.REGION_KIND_REGION, 0, 0, 1, payload(2, 5000),
+                P2vec.COMPRESSION_ZLIB, 6);
+        P2vec.closeWorld(world);
+
+        // Garbles the zlib stream right after the chunk header
+        try (RandomAccessFile file = new RandomAccessFile(Path.of(path, "region", "r.0.0.mca").toFile(), "rw")) {
+            file.seek(8192 + 5);
+            file.write(new byte[64]);
+        }
+
+        long reopened = P2vec.openWorld(path);
+
+        expect(IOException.class, () -> P2vec.readChunk(reopened, P2vec.DIMENSION_OVERWORLD,
+                P2vec.REGION_KIND_REGION, 0, 0));
+
+        P2vec.closeWorld(reopened);
+    }
+}
@@ -0,0 +1,79 @@
+use std::path::Path;
+
+use p2vec::ChunkPos;
+
+fn get_payload(seed: u32, length: usize) -> Vec<u8> {
+    (0..length)
+        .map(|index| (index as u32 ^ seed.wrapping_mul(2654435761)) as u8)
+        .collect()
+}
+
+#[test]
+fn memory_backend_never_touches_disk() {
+    let directory = "p2vec-memory-backend-test";
+
+    p2vec::set_memory_backend(directory, true).unwrap();
+
+    let chunks = [
+        (ChunkPos::new(0, 0), 20000),
+        (ChunkPos::new(1, 0), 3000),
+        (ChunkPos::new(2, 0), 9000),
+        // Big enough to go to an oversized file
+        (ChunkPos::new(3, 0), 1 << 21),
+    ];
+
+    for (seed, (coords, length)) in chunks.iter().enumerate() {
+        p2vec::write_chunk(
+            directory,
+            *coords,
+            1,
+            &get_payload(seed as u32, *length),
+            3,
+            0,
+        )
+        .unwrap();
+    }
+
+    // Shrinking a chunk and deleting others leaves free sectors behind
+    p2vec::write_chunk(directory, chunks[0].0, 2, &get_payload(10, 100), 3, 0).unwrap();
+
+    assert!(p2vec::delete_chunk(directory, chunks[2].0).unwrap());
+    assert!(p2vec::delete_chunk(directory, chunks[3].0).unwrap());
+    assert!(!p2vec::delete_chunk(directory, chunks[3].0).unwrap());
+
+    assert!(p2vec::compact_regions(directory).unwrap() > 0);
+
+    assert_eq!(
+        p2vec::read_chunk(directory, chunks[0].0).unwrap().unwrap(),
+        get_payload(10, 100)
+    );
+    assert_eq!(
+        p2vec::read_chunk_timestamp(directory, chunks[0].0).unwrap(),
+        2
+    );
+    assert_eq!(
+        p2vec::read_chunk(directory, chunks[1].0).unwrap().unwrap(),
+        get_payload(1, 3000)
+    );
+    assert_eq!(p2vec::read_chunk(directory, chunks[2].0).unwrap(), None);
+    assert_eq!(p2vec::read_chunk(directory, chunks[3].0).unwrap(), None);
+
+    // The data outlives closing the regions
+    p2vec::close_regions(directory).unwrap();
+
+    assert!(p2vec::verify_regions(directory).unwrap().is_ok());
+    assert_eq!(
+        p2vec::read_chunk(directory, chunks[1].0).unwrap().unwrap(),
+        get_payload(1, 3000)
+    );
+
+    assert!(!Path::new(directory).exists());
+
+    // Turning it off throws everything away
+    p2vec::set_memory_backend(directory, false).unwrap();
+    p2vec::set_memory_backend(directory, true).unwrap();
+
+    assert_eq!(p2vec::read_chunk(directory, chunks[1].0).unwrap(), None);
+
+    p2vec::set_memory_backend(directory, false).unwrap();
+}
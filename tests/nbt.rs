@@ -0,0 +1,242 @@
+#![cfg(feature = "nbt")]
+
+use std::borrow::Cow;
+use std::io::ErrorKind;
+
+use p2vec::nbt::{
+    read_nbt, write_nbt, Compound, List, Tag, TAG_COMPOUND, TAG_END, TAG_INT, TAG_LIST,
+};
+use p2vec::ChunkPos;
+
+// Deepest a tag can be nested below the root compound
+const MAX_DEPTH: usize = 511;
+
+fn get_directory(name: &str) -> &'static str {
+    let directory = std::env::temp_dir().join(format!("p2vec-nbt-{}-{}", name, std::process::id()));
+
+    let _ = std::fs::remove_dir_all(&directory);
+
+    Box::leak(directory.to_string_lossy().into_owned().into_boxed_str())
+}
+
+// One of every tag type, including empty and nested lists
+fn get_every_tag() -> Compound<'static> {
+    let mut nested = Compound::new();
+
+    nested.insert("Status", Tag::String(Cow::Borrowed("minecraft:full")));
+    nested.insert("InhabitedTime", Tag::Long(i64::MIN));
+
+    let mut ints = List::new(TAG_INT);
+
+    for value in [i32::MIN, -1, 0, 1, i32::MAX] {
+        ints.push(Tag::Int(value)).unwrap();
+    }
+
+    let mut compounds = List::new(TAG_COMPOUND);
+
+    compounds.push(Tag::Compound(nested.clone())).unwrap();
+    compounds.push(Tag::Compound(Compound::new())).unwrap();
+
+    let mut lists = List::new(TAG_LIST);
+
+    lists.push(Tag::List(ints.clone())).unwrap();
+    lists.push(Tag::List(List::new(TAG_END))).unwrap();
+
+    let mut root = Compound::new();
+
+    root.insert("Byte", Tag::Byte(-128));
+    root.insert("Short", Tag::Short(-12345));
+    root.insert("Int", Tag::Int(3700));
+    root.insert("Long", Tag::Long(0x0123_4567_89AB_CDEF));
+    root.insert("Float", Tag::Float(-1.5e-20));
+    root.insert("Double", Tag::Double(std::f64::consts::PI));
+    root.insert("ByteArray", Tag::ByteArray(Cow::Owned((0..=255).collect())));
+    root.insert("String", Tag::String(Cow::Borrowed("")));
+    root.insert("Ints", Tag::List(ints));
+    root.insert("Compounds", Tag::List(compounds));
+    root.insert("Lists", Tag::List(lists));
+    root.insert("Empty", Tag::List(List::new(TAG_END)));
+    root.insert("Level", Tag::Compound(nested));
+    root.insert(
+        "IntArray",
+        Tag::IntArray(vec![i32::MIN, 0, i32::MAX].into()),
+    );
+    root.insert(
+        "LongArray",
+        Tag::LongArray(vec![i64::MIN, 0, i64::MAX].into()),
+    );
+    root.insert("EmptyLongArray", Tag::LongArray(Vec::new().into()));
+
+    root
+}
+
+fn nest_compounds(depth: usize) -> Compound<'static> {
+    let mut compound = Compound::new();
+
+    for _ in 0..depth {
+        let mut parent = Compound::new();
+
+        parent.insert("", Tag::Compound(compound));
+
+        compound = parent;
+    }
+
+    compound
+}
+
+// Raw data so nesting deeper than the writer would ever be asked to go can be tested without building the tree
+fn nest_lists(depth: usize) -> Vec<u8> {
+    let mut data = vec![TAG_COMPOUND, 0, 0, TAG_LIST, 0, 0];
+
+    for _ in 1..depth {
+        data.push(TAG_LIST);
+        data.extend_from_slice(&1i32.to_be_bytes());
+    }
+
+    data.push(TAG_END);
+    data.extend_from_slice(&0i32.to_be_bytes());
+    data.push(TAG_END);
+
+    data
+}
+
+#[test]
+fn every_tag_type_round_trips() {
+    let root = get_every_tag();
+
+    let data = write_nbt("root", &root).unwrap();
+
+    let (name, read_root) = read_nbt(&data).unwrap();
+
+    assert_eq!(name, "root");
+    assert_eq!(read_root, root);
+
+    // Reading borrows the arrays, writing them again has to give the same bytes
+    assert_eq!(write_nbt("root", &read_root).unwrap(), data);
+}
+
+#[test]
+fn chunk_nbt_round_trips() {
+    let directory = get_directory("chunk");
+
+    let mut root = get_every_tag();
+
+    root.insert("DataVersion", Tag::Int(3700));
+
+    p2vec::write_chunk_nbt(directory, ChunkPos::new(-1, 40), 1, &root, 2, 6).unwrap();
+
+    let chunk = p2vec::read_chunk_nbt(directory, ChunkPos::new(-1, 40))
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(chunk.root().unwrap(), root);
+    assert_eq!(chunk.data_version().unwrap(), Some(3700));
+
+    p2vec::close_regions(directory).unwrap();
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn strings_are_modified_utf8() {
+    let mut root = Compound::new();
+
+    root.insert("", Tag::String(Cow::Borrowed("a\0b\u{1F600}é")));
+
+    let data = write_nbt("", &root).unwrap();
+
+    // NUL takes two bytes and characters outside the BMP are written as two surrogates of three bytes each
+    let string = [
+        b'a', 0xC0, 0x80, b'b', 0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80, 0xC3, 0xA9,
+    ];
+
+    let mut expected = vec![TAG_COMPOUND, 0, 0, 8, 0, 0, 0, string.len() as u8];
+
+    expected.extend_from_slice(&string);
+    expected.push(TAG_END);
+
+    assert_eq!(data, expected);
+    assert_eq!(read_nbt(&data).unwrap().1, root);
+
+    // Tools that write plain UTF-8 use four bytes outside the BMP instead, which is read as well
+    let data = [TAG_COMPOUND, 0, 4, 0xF0, 0x9F, 0x98, 0x80, TAG_END];
+
+    assert_eq!(read_nbt(&data).unwrap().0, "\u{1F600}");
+}
+
+#[test]
+fn invalid_modified_utf8_is_rejected() {
+    for string in [
+        // Cut off in the middle of a character
+        &[b'a', 0xC3][..],
+        // A surrogate without its other half
+        &[0xED, 0xA0, 0xBD],
+        // Both halves, but the wrong way around
+        &[0xED, 0xB8, 0x80, 0xED, 0xA0, 0xBD],
+    ] {
+        let mut data = vec![TAG_COMPOUND, 0, string.len() as u8];
+
+        data.extend_from_slice(string);
+        data.push(TAG_END);
+
+        assert_eq!(
+            read_nbt(&data).unwrap_err().kind(),
+            ErrorKind::InvalidData,
+            "{:?}",
+            string
+        );
+    }
+}
+
+#[test]
+fn nesting_is_limited() {
+    let data = write_nbt("", &nest_compounds(MAX_DEPTH)).unwrap();
+
+    assert!(read_nbt(&data).is_ok());
+
+    let data = write_nbt("", &nest_compounds(MAX_DEPTH + 1)).unwrap();
+
+    assert_eq!(read_nbt(&data).unwrap_err().kind(), ErrorKind::InvalidData);
+
+    assert!(read_nbt(&nest_lists(MAX_DEPTH)).is_ok());
+    assert_eq!(
+        read_nbt(&nest_lists(MAX_DEPTH + 1)).unwrap_err().kind(),
+        ErrorKind::InvalidData
+    );
+
+    // Far too deep to recurse into
+    assert_eq!(
+        read_nbt(&nest_lists(1_000_000)).unwrap_err().kind(),
+        ErrorKind::InvalidData
+    );
+}
+
+#[test]
+fn truncated_data_is_rejected() {
+    let data = write_nbt("root", &get_every_tag()).unwrap();
+
+    for length in 0..data.len() {
+        assert_eq!(
+            read_nbt(&data[..length]).unwrap_err().kind(),
+            ErrorKind::InvalidData,
+            "{} bytes",
+            length
+        );
+    }
+}
+
+#[test]
+fn lengths_past_the_end_are_rejected() {
+    let mut data = vec![TAG_COMPOUND, 0, 0, TAG_LIST, 0, 0, TAG_INT];
+
+    data.extend_from_slice(&i32::MAX.to_be_bytes());
+    data.push(TAG_END);
+
+    assert_eq!(read_nbt(&data).unwrap_err().kind(), ErrorKind::InvalidData);
+
+    let mut data = vec![TAG_COMPOUND, 0, 0, 12, 0, 0];
+
+    data.extend_from_slice(&(-1i32).to_be_bytes());
+    data.push(TAG_END);
+
+    assert_eq!(read_nbt(&data).unwrap_err().kind(), ErrorKind::InvalidData);
+}
@@ -0,0 +1,286 @@
+#![cfg(feature = "nbt")]
+
+use std::borrow::Cow;
+use std::io::{ErrorKind, Read};
+
+use p2vec::nbt::{
+    query_nbt, read_nbt, write_nbt, Compound, List, Tag, TAG_COMPOUND, TAG_END, TAG_LIST,
+};
+use p2vec::ChunkPos;
+
+fn get_directory(name: &str) -> &'static str {
+    let directory =
+        std::env::temp_dir().join(format!("p2vec-nbt-query-{}-{}", name, std::process::id()));
+
+    let _ = std::fs::remove_dir_all(&directory);
+
+    Box::leak(directory.to_string_lossy().into_owned().into_boxed_str())
+}
+
+// Counts how much of the data a query actually asked for
+struct CountingReader<'a> {
+    data: &'a [u8],
+    read: usize,
+}
+
+impl<'a> Read for CountingReader<'a> {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        let read = (&self.data[self.read..]).read(buffer)?;
+
+        self.read += read;
+
+        Ok(read)
+    }
+}
+
+// Laid out like a chunk, with the small tags tools ask for in front of the big ones
+fn get_chunk() -> Compound<'static> {
+    let mut level = Compound::new();
+
+    level.insert("Status", Tag::String(Cow::Borrowed("minecraft:full")));
+    level.insert("InhabitedTime", Tag::Long(72000));
+
+    let mut sections = List::new(TAG_COMPOUND);
+
+    for y in -4..20 {
+        let mut section = Compound::new();
+
+        section.insert("Y", Tag::Byte(y));
+        section.insert("BlockStates", Tag::LongArray(vec![y as i64; 4096].into()));
+
+        sections.push(Tag::Compound(section)).unwrap();
+    }
+
+    let mut root = Compound::new();
+
+    root.insert("DataVersion", Tag::Int(3700));
+    root.insert("Level", Tag::Compound(level));
+    root.insert("sections", Tag::List(sections));
+    root.insert("LastUpdate", Tag::Long(123456));
+
+    root
+}
+
+#[test]
+fn query_finds_every_path() {
+    let root = get_chunk();
+
+    let data = write_nbt("", &root).unwrap();
+
+    let results = query_nbt(
+        data.as_slice(),
+        &[
+            &["LastUpdate"],
+            &["Level", "Status"],
+            &["Level", "Missing"],
+            &["Missing"],
+            &["DataVersion", "NotACompound"],
+            &["DataVersion"],
+        ],
+    )
+    .unwrap();
+
+    assert_eq!(
+        results,
+        vec![
+            Some(Tag::Long(123456)),
+            Some(Tag::String(Cow::Borrowed("minecraft:full"))),
+            None,
+            None,
+            None,
+            Some(Tag::Int(3700)),
+        ]
+    );
+
+    // An empty path is the whole root, which has to match what the full reader makes of it
+    let results = query_nbt(data.as_slice(), &[&[], &["Level"]]).unwrap();
+
+    assert_eq!(results[0], Some(Tag::Compound(read_nbt(&data).unwrap().1)));
+    assert_eq!(results[1].as_ref(), root.get("Level"));
+}
+
+#[test]
+fn query_stops_after_the_last_wanted_path() {
+    let mut data = write_nbt("", &get_chunk()).unwrap();
+
+    // Nothing after the wanted tags is looked at, not even to see if it is valid
+    data.pop();
+    data.extend_from_slice(&[99; 1 << 20]);
+
+    for (paths, expected) in [
+        (
+            &[&["DataVersion"][..], &["Level", "Status"]][..],
+            vec![
+                Some(Tag::Int(3700)),
+                Some(Tag::String(Cow::Borrowed("minecraft:full"))),
+            ],
+        ),
+        (&[&["Level", "InhabitedTime"]], vec![Some(Tag::Long(72000))]),
+        // Level ends before the sections, so nothing past it can have this
+        (&[&["Level", "Missing"]], vec![None]),
+    ] {
+        let mut reader = CountingReader {
+            data: &data,
+            read: 0,
+        };
+
+        assert_eq!(query_nbt(&mut reader, paths).unwrap(), expected);
+
+        // Only as far as one fill of the read buffer past the tags
+        assert!(reader.read <= 8192, "{} bytes read", reader.read);
+    }
+
+    // Looking for something that isn't there does run into the rest
+    let mut reader = CountingReader {
+        data: &data,
+        read: 0,
+    };
+
+    assert_eq!(
+        query_nbt(&mut reader, &[&["Missing"]]).unwrap_err().kind(),
+        ErrorKind::InvalidData
+    );
+}
+
+// The tags of a compound without the root tag around them, so they can be put together in ways the writer wouldn't
+fn get_entries(compound: &Compound) -> Vec<u8> {
+    let data = write_nbt("", compound).unwrap();
+
+    data[3..data.len() - 1].to_vec()
+}
+
+#[test]
+fn query_handles_repeated_names() {
+    let data = write_nbt("", &get_chunk()).unwrap();
+
+    // The same path more than once, and paths that only differ in the last name
+    let results = query_nbt(
+        data.as_slice(),
+        &[
+            &["Level", "Status"],
+            &["Level", "Status"],
+            &["Level", "InhabitedTime"],
+            &["Level", "Missing"],
+            &["LastUpdate"],
+            &["LastUpdate"],
+        ],
+    )
+    .unwrap();
+
+    assert_eq!(
+        results,
+        vec![
+            Some(Tag::String(Cow::Borrowed("minecraft:full"))),
+            Some(Tag::String(Cow::Borrowed("minecraft:full"))),
+            Some(Tag::Long(72000)),
+            None,
+            Some(Tag::Long(123456)),
+            Some(Tag::Long(123456)),
+        ]
+    );
+
+    // A name that is repeated within a compound, with something still wanted after it
+    let mut data = vec![TAG_COMPOUND, 0, 0];
+
+    for status in ["first", "second"] {
+        let mut level = Compound::new();
+
+        level.insert("Status", Tag::String(Cow::Borrowed(status)));
+
+        let mut compound = Compound::new();
+
+        compound.insert("Level", Tag::Compound(level));
+
+        data.extend_from_slice(&get_entries(&compound));
+    }
+
+    let mut compound = Compound::new();
+
+    compound.insert("LastUpdate", Tag::Long(123456));
+
+    data.extend_from_slice(&get_entries(&compound));
+    data.push(TAG_END);
+
+    assert_eq!(
+        query_nbt(
+            data.as_slice(),
+            &[&["Level", "Status"], &["Level", "Missing"], &["LastUpdate"]],
+        )
+        .unwrap(),
+        vec![
+            Some(Tag::String(Cow::Borrowed("first"))),
+            None,
+            Some(Tag::Long(123456)),
+        ]
+    );
+}
+
+#[test]
+fn query_nesting_is_limited() {
+    // A list of lists nested far deeper than the limit, placed before the wanted tag so it has to be skipped
+    let mut data = vec![TAG_COMPOUND, 0, 0, TAG_LIST, 0, 0];
+
+    for _ in 0..1_000_000 {
+        data.push(TAG_LIST);
+        data.extend_from_slice(&1i32.to_be_bytes());
+    }
+
+    data.push(TAG_END);
+    data.extend_from_slice(&0i32.to_be_bytes());
+    data.push(TAG_END);
+
+    for paths in [&[&["Wanted"][..]][..], &[&[""]], &[&[]]] {
+        assert_eq!(
+            query_nbt(data.as_slice(), paths).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+    }
+}
+
+#[test]
+fn query_rejects_truncated_data() {
+    let data = write_nbt("", &get_chunk()).unwrap();
+
+    // Cuts are made everywhere in the small tags and then every so often in the sections
+    for length in (0..200).chain((200..data.len()).step_by(97)) {
+        assert_eq!(
+            query_nbt(&data[..length], &[&["LastUpdate"]])
+                .unwrap_err()
+                .kind(),
+            ErrorKind::InvalidData,
+            "{} bytes",
+            length
+        );
+    }
+}
+
+#[test]
+fn query_chunk_reads_every_compression_type() {
+    let directory = get_directory("chunk");
+
+    let root = get_chunk();
+
+    for compression_type in 1..=4 {
+        let coords = ChunkPos::new(compression_type as i32, -7);
+
+        p2vec::write_chunk_nbt(directory, coords, 1, &root, compression_type, 6).unwrap();
+
+        assert_eq!(
+            p2vec::query_chunk(directory, coords, &["Level", "Status"]).unwrap(),
+            Some(Tag::String(Cow::Borrowed("minecraft:full")))
+        );
+        assert_eq!(
+            p2vec::query_chunk_paths(directory, coords, &[&["DataVersion"], &["LastUpdate"]])
+                .unwrap(),
+            vec![Some(Tag::Int(3700)), Some(Tag::Long(123456))]
+        );
+    }
+
+    assert_eq!(
+        p2vec::query_chunk(directory, ChunkPos::new(0, -7), &["DataVersion"]).unwrap(),
+        None
+    );
+
+    p2vec::close_regions(directory).unwrap();
+    std::fs::remove_dir_all(directory).unwrap();
+}
@@ -0,0 +1,162 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use p2vec::{ChunkPos, LocalPos, RegionPos};
+use proptest::prelude::*;
+
+// Every write gets a newer timestamp so it always wins
+static TIMESTAMP: AtomicU32 = AtomicU32::new(1);
+
+// Anything over 255 sectors goes to an oversized file
+const OVERSIZED_LENGTH: usize = 256 * 4096;
+
+// Doesn't compress, so a payload stays about as big after compression
+fn get_payload(seed: u64, length: usize) -> Vec<u8> {
+    let mut state = seed | 1;
+
+    (0..length)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+
+            state as u8
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+struct ChunkWrite {
+    coords: ChunkPos,
+    seed: u64,
+    length: usize,
+    compression_type: u8,
+    compression_level: i32,
+}
+
+fn chunk_write() -> impl Strategy<Value = ChunkWrite> {
+    (
+        (-2048..2048i32, -2048..2048i32),
+        any::<u64>(),
+        prop_oneof![
+            8 => 0..20000usize,
+            1 => OVERSIZED_LENGTH..OVERSIZED_LENGTH + 100000,
+        ],
+        1..=4u8,
+        1..=9i32,
+    )
+        .prop_map(
+            |((x, z), seed, length, compression_type, compression_level)| ChunkWrite {
+                coords: ChunkPos::new(x, z),
+                seed,
+                length,
+                compression_type,
+                compression_level,
+            },
+        )
+}
+
+fn chunk_writes(max_writes: usize) -> impl Strategy<Value = Vec<ChunkWrite>> {
+    prop::collection::vec(chunk_write(), 1..max_writes)
+}
+
+fn write_and_read_back(directory: &'static str, writes: &[ChunkWrite]) {
+    for write in writes {
+        p2vec::write_chunk(
+            directory,
+            write.coords,
+            TIMESTAMP.fetch_add(1, Ordering::Relaxed),
+            &get_payload(write.seed, write.length),
+            write.compression_type,
+            write.compression_level,
+        )
+        .unwrap();
+    }
+
+    // Only the last write to each chunk is expected to survive
+    let expected: Vec<&ChunkWrite> = writes
+        .iter()
+        .enumerate()
+        .filter(|(index, write)| {
+            writes[index + 1..]
+                .iter()
+                .all(|later| later.coords != write.coords)
+        })
+        .map(|(_, write)| write)
+        .collect();
+
+    for round in 0..2 {
+        for write in expected.iter() {
+            // Payloads are too big to print when they differ
+            assert!(
+                p2vec::read_chunk(directory, write.coords).unwrap()
+                    == Some(get_payload(write.seed, write.length)),
+                "chunk {:?} differs after round {}",
+                write.coords,
+                round
+            );
+        }
+
+        let coords: Vec<ChunkPos> = writes.iter().map(|write| write.coords).collect();
+
+        for (coords, data) in coords.iter().zip(p2vec::read_chunks(directory, &coords)) {
+            let write = expected
+                .iter()
+                .find(|write| write.coords == *coords)
+                .unwrap();
+
+            assert!(
+                data.unwrap() == Some(get_payload(write.seed, write.length)),
+                "chunk {:?} differs in batched read after round {}",
+                coords,
+                round
+            );
+        }
+
+        // The second round reads everything back from a freshly opened region
+        p2vec::close_regions(directory).unwrap();
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(48))]
+
+    #[test]
+    fn positions_round_trip(x in any::<i32>(), z in any::<i32>()) {
+        let coords = ChunkPos::new(x, z);
+
+        let region_coords = coords.region();
+        let local = coords.local();
+
+        prop_assert_eq!(region_coords, RegionPos::new(x.div_euclid(32), z.div_euclid(32)));
+        prop_assert_eq!(local.x() as i32, x.rem_euclid(32));
+        prop_assert_eq!(local.z() as i32, z.rem_euclid(32));
+        prop_assert_eq!(region_coords.chunk(local), coords);
+        prop_assert_eq!(LocalPos::from_index(local.index()), local);
+    }
+
+    #[test]
+    fn chunks_round_trip_in_memory(writes in chunk_writes(8)) {
+        let directory = "p2vec-round-trip-memory";
+
+        p2vec::set_memory_backend(directory, true).unwrap();
+
+        write_and_read_back(directory, &writes);
+
+        p2vec::set_memory_backend(directory, false).unwrap();
+    }
+
+    #[test]
+    fn chunks_round_trip_on_disk(writes in chunk_writes(4)) {
+        let directory = std::env::temp_dir().join(format!("p2vec-round-trip-{}", std::process::id()));
+
+        let _ = std::fs::remove_dir_all(&directory);
+
+        let directory: &'static str = Box::leak(directory.to_string_lossy().into_owned().into_boxed_str());
+
+        write_and_read_back(directory, &writes);
+
+        p2vec::close_regions(directory).unwrap();
+
+        let _ = std::fs::remove_dir_all(directory);
+    }
+}